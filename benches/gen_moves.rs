@@ -56,6 +56,12 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("has_any_move", |b| {
+        b.iter(|| {
+            black_box(game.has_any_move());
+        })
+    });
+
     c.bench_function("find_card", |b| {
         b.iter(|| {
             black_box(sample_deck.find_card(card));
@@ -81,6 +87,18 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("encode", |b| {
+        b.iter(|| {
+            black_box(game.state().encode());
+        })
+    });
+
+    c.bench_function("encode_cached", |b| {
+        b.iter(|| {
+            black_box(game.state().encode_cached());
+        })
+    });
+
     const TOTAL_GAME: u64 = 1000;
 
     c.bench_function("random_playout", |b| {
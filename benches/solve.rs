@@ -0,0 +1,69 @@
+use std::{cell::Cell, num::NonZeroU8};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use lonelybot::{
+    shuffler::default_shuffle,
+    solver::{
+        solve_with_cache_size, solve_with_tracking, DefaultTerminateSignal, SearchStatistics,
+    },
+    state::Solitaire,
+};
+
+#[derive(Default)]
+struct NodeCount(Cell<u64>);
+
+impl SearchStatistics for NodeCount {
+    fn hit_a_state(&self, _: usize) {
+        self.0.set(self.0.get() + 1);
+    }
+    fn hit_unique_state(&self, _: usize, _: u32) {}
+    fn finish_move(&self, _: usize) {}
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let draw_step = NonZeroU8::new(3).unwrap();
+    let games: Vec<Solitaire> = (1..8)
+        .map(|seed| Solitaire::new(&default_shuffle(seed), draw_step))
+        .collect();
+
+    let nodes = NodeCount::default();
+    for game in &games {
+        solve_with_tracking(&mut game.clone(), &nodes, &DefaultTerminateSignal);
+    }
+
+    let mut group = c.benchmark_group("solve");
+    // the nodes themselves don't allocate (see `traverse`), only the
+    // transposition table does: compare an unbounded table to a bounded one
+    group.throughput(Throughput::Elements(nodes.0.get()));
+    group.sample_size(10);
+
+    group.bench_function("growing_table", |b| {
+        b.iter(|| {
+            for game in &games {
+                black_box(solve_with_tracking(
+                    &mut game.clone(),
+                    &NodeCount::default(),
+                    &DefaultTerminateSignal,
+                ));
+            }
+        })
+    });
+
+    group.bench_function("bounded_table", |b| {
+        b.iter(|| {
+            for game in &games {
+                black_box(solve_with_cache_size(
+                    &mut game.clone(),
+                    1 << 24,
+                    &NodeCount::default(),
+                    &DefaultTerminateSignal,
+                ));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+use std::{
+    num::NonZeroU8,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use lonelybot::{
+    shuffler::default_shuffle,
+    solver::{solve_with_tracking, SearchResult},
+    state::Solitaire,
+    tracking::NodeLimit,
+};
+
+// the search is recursive, same as the one of the solve command
+const STACK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Solve the deals of `seeds` (shuffled with `default_shuffle`) on
+/// `n_threads` threads, stopping each search after `node_limit` states
+///
+/// Return for each seed, in the order of `seeds`, the search result and the
+/// length of the solution when one is found. The results don't depend on
+/// `n_threads`.
+///
+/// # Panics
+///
+/// When a solver thread can't be spawned
+#[must_use]
+pub fn solve_seeds(
+    seeds: &[u64],
+    draw_step: NonZeroU8,
+    node_limit: Option<usize>,
+    n_threads: usize,
+) -> Vec<(u64, SearchResult, Option<usize>)> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(seeds.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..n_threads.clamp(1, seeds.len().max(1)) {
+            thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&seed) = seeds.get(idx) else {
+                        break;
+                    };
+                    let mut game = Solitaire::new(&default_shuffle(seed), draw_step);
+                    let limit = NodeLimit::new(node_limit.unwrap_or(usize::MAX));
+                    let (res, history) = solve_with_tracking(&mut game, &limit, &limit);
+                    let len = history.map(|h| h.len());
+                    results.lock().unwrap().push((idx, (seed, res, len)));
+                })
+                .unwrap();
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_unstable_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, res)| res).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_seeds() {
+        let seeds: Vec<u64> = (0..12).collect();
+        let draw_step = NonZeroU8::new(3).unwrap();
+        let single = solve_seeds(&seeds, draw_step, Some(100_000), 1);
+        assert_eq!(single.len(), seeds.len());
+        assert!(single.iter().zip(&seeds).all(|(r, &seed)| r.0 == seed));
+        assert!(single.iter().any(|r| r.1 == SearchResult::Solved && r.2.is_some()));
+        assert!(single.iter().all(|r| (r.1 == SearchResult::Solved) == r.2.is_some()));
+
+        for n_threads in [2, 5, 32] {
+            assert_eq!(solve_seeds(&seeds, draw_step, Some(100_000), n_threads), single);
+        }
+        assert!(solve_seeds(&[], draw_step, None, 4).is_empty());
+    }
+}
@@ -1 +1,2 @@
+pub mod bulk;
 pub mod training;
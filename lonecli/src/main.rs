@@ -2,13 +2,14 @@ mod solver;
 mod solvitaire;
 mod tracking;
 mod tui;
-mod training;
 
 use bpci::{Interval, NSuccessesSample, WilsonScore};
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use lonecli::training;
 use lonelybot::convert::convert_moves;
 // use lonelybot::dependencies::DependencyEngine;
 use lonelybot::engine::SolitaireEngine;
+use lonelybot::graph::shortest_win;
 use lonelybot::mcts_solver::pick_moves;
 use lonelybot::pruning::{CyclePruner, FullPruner, NoPruner};
 use lonelybot::shuffler::{self, CardDeck, U256};
@@ -322,6 +323,7 @@ fn test_graph(seed: &Seed, draw_step: NonZeroU8, path: &String, terminated: &Arc
     let shuffled_deck = shuffle(seed);
 
     let g: Solitaire = Solitaire::new(&shuffled_deck, draw_step);
+    let start = g.encode();
 
     let now = Instant::now();
     let res = solver::run_graph(g, true, terminated);
@@ -331,6 +333,10 @@ fn test_graph(seed: &Seed, draw_step: NonZeroU8, path: &String, terminated: &Arc
         Some((res, graph)) => {
             println!("Graphed in {} edges", graph.len());
             if res == Control::Ok {
+                if let Some(n_moves) = shortest_win(&graph, start, |_| 1) {
+                    let weighted = shortest_win(&graph, start, |e| e.weight()).unwrap_or(n_moves);
+                    println!("Shortest win in {n_moves} moves (weighted {weighted})");
+                }
                 let mut f = std::io::BufWriter::new(File::create(path).unwrap());
                 writeln!(f, "s,t,e,id").unwrap();
                 for (id, e) in graph.iter().skip(1).enumerate() {
@@ -537,6 +543,12 @@ enum Commands {
         /// Number of games to generate
         #[arg(default_value_t = 1000)]
         n_games: usize,
+        /// Output format, jsonl or csv
+        #[arg(long, default_value = "jsonl")]
+        format: String,
+        /// Output file, `training_data.<format>` by default
+        #[arg(long)]
+        out: Option<String>,
     },
 }
 
@@ -601,8 +613,28 @@ fn main() {
                 );
             }
         }
-        Commands::Collect { n_games } => {
-            if let Err(e) = training::collect_training_data(*n_games) {
+        Commands::Collect {
+            n_games,
+            format,
+            out,
+        } => {
+            let format: training::OutputFormat = match format.parse() {
+                Ok(format) => format,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return;
+                }
+            };
+            let out = out
+                .clone()
+                .unwrap_or_else(|| format!("training_data.{}", format.extension()));
+            let res = training::collect_training_data_to(
+                out.as_ref(),
+                *n_games,
+                format,
+                training::print_progress,
+            );
+            if let Err(e) = res {
                 eprintln!("{e}");
             }
         }
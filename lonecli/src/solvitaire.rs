@@ -1,97 +1,12 @@
 use core::fmt;
 
-use lonelybot::{
-    card::{Card, N_SUITS},
-    deck::N_PILES,
-    formatter::NUMBERS,
-    standard::StandardSolitaire,
-};
-
-pub(crate) struct SolvitaireCard<const LOWER: bool>(Card);
-
-impl<const LOWER: bool> fmt::Display for SolvitaireCard<LOWER> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let (rank, suit) = self.0.split();
-        let s = match suit {
-            0 => 'H',
-            1 => 'D',
-            2 => 'C',
-            3 => 'S',
-            _ => 'x',
-        };
-        write!(
-            f,
-            r#""{}{}""#,
-            NUMBERS[rank as usize],
-            if LOWER { s.to_ascii_lowercase() } else { s }
-        )
-    }
-}
+use lonelybot::{formatter::to_solvitaire_json, standard::StandardSolitaire};
 
 pub struct Solvitaire(pub StandardSolitaire);
 
 impl fmt::Display for Solvitaire {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, r#"{{"tableau piles": ["#)?;
-
-        for i in 0..N_PILES as usize {
-            write!(f, "[")?;
-            for c in &self.0.get_hidden()[i] {
-                // hidden cards
-                write!(f, "{},", SolvitaireCard::<true>(*c))?;
-            }
-            for (idx, c) in self.0.get_piles()[i].iter().enumerate() {
-                if idx != 0 {
-                    write!(f, ",")?;
-                }
-                write!(f, "{}", SolvitaireCard::<false>(*c))?;
-            }
-            if i + 1 < N_PILES as usize {
-                writeln!(f, "],")?;
-            } else {
-                writeln!(f, "]")?;
-            }
-        }
-
-        write!(f, "],\"stock\": [")?;
-
-        for c in self.0.get_deck().deck_iter().rev().enumerate() {
-            if c.0 > 0 {
-                write!(f, ",")?;
-            }
-            write!(f, "{}", SolvitaireCard::<false>(c.1))?;
-        }
-        write!(f, "],\"waste\": [")?;
-
-        for c in self.0.get_deck().waste_iter().enumerate() {
-            if c.0 > 0 {
-                write!(f, ",")?;
-            }
-            write!(f, "{}", SolvitaireCard::<false>(c.1))?;
-        }
-        write!(f, "]")?;
-
-        // foundation
-        write!(f, ",\n\"foundation\": [")?;
-
-        for suit in 0..N_SUITS {
-            if suit > 0 {
-                write!(f, ",")?;
-            }
-
-            write!(f, "[")?;
-            for rank in 0..self.0.get_stack().get(suit) {
-                if rank > 0 {
-                    write!(f, ",")?;
-                }
-                let c = Card::new(rank, suit);
-                write!(f, "{}", SolvitaireCard::<false>(c))?;
-            }
-            write!(f, "]")?;
-        }
-        write!(f, "]}}")?;
-
-        Ok(())
+        f.write_str(&to_solvitaire_json(&self.0))
     }
 }
 
@@ -1,17 +1,55 @@
 use lonelybot::analysis::{ranked_moves, HeuristicConfig, PlayStyle};
 use lonelybot::engine::SolitaireEngine;
+use lonelybot::moves::Move;
 use lonelybot::partial::PartialState;
 use lonelybot::pruning::FullPruner;
 use lonelybot::state::Solitaire;
 use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use serde_json::{json, to_string, Value};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::collections::HashSet;
-use std::env;
+use std::path::Path;
+use std::str::FromStr;
 
-fn state_to_json(state: &PartialState) -> Value {
+/// File layout of the generated records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One nested JSON object per line
+    #[default]
+    Jsonl,
+    /// Flat columns with the state serialized by [`compact_state`]
+    Csv,
+}
+
+impl OutputFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("unknown training data format `{s}`, expected jsonl or csv")),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "turn,selected_move,available_moves,win,style,state";
+
+/// JSON object of `state`, in the layout read back by the Python `GameState.from_json`
+#[must_use]
+pub fn state_to_json(state: &PartialState) -> Value {
     let columns: Vec<Value> = state
         .columns
         .iter()
@@ -35,25 +73,139 @@ fn state_to_json(state: &PartialState) -> Value {
         .iter()
         .map(|o| o.map(|x| x.to_string()).unwrap_or_else(|| "unknown".into()))
         .collect();
+    let foundations: serde_json::Map<String, Value> = ["H", "D", "C", "S"]
+        .iter()
+        .zip(0..)
+        .map(|(suit, i)| ((*suit).to_string(), json!(state.foundations.get(i))))
+        .collect();
     json!({
         "draw_step": state.draw_step,
+        "redeal_limit": state.redeal_limit,
         "columns": columns,
         "deck": deck,
+        "deck_offset": state.deck_offset,
+        "foundations": foundations,
     })
 }
 
+/// Serialize a state on a single line without commas: the draw step, the
+/// seven columns and the deck separated by `|`. A column lists its hidden
+/// cards, then `:` and its visible cards, unknown cards being written `??`.
+#[must_use]
+pub fn compact_state(state: &PartialState) -> String {
+    fn cards<'a>(it: impl Iterator<Item = &'a Option<lonelybot::card::Card>>) -> String {
+        it.map(|o| o.map_or_else(|| "??".into(), |c| c.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    let mut parts = vec![state.draw_step.to_string()];
+    for c in &state.columns {
+        let visible: Vec<_> = c.visible.iter().map(|&x| Some(x)).collect();
+        parts.push(format!("{}:{}", cards(c.hidden.iter()), cards(visible.iter())));
+    }
+    parts.push(cards(state.deck.iter()));
+    parts.join("|")
+}
+
+/// Default progress report: print on stderr every 1000 games.
+pub fn print_progress(done: usize, total: usize) -> bool {
+    if done.is_multiple_of(1000) && done < total {
+        eprintln!("generated {done}/{total} games");
+    }
+    true
+}
+
+/// Generate `n_games` self-play games into `training_data.jsonl`, reporting
+/// progress on stderr every 1000 games.
 pub fn collect_training_data(n_games: usize) -> std::io::Result<()> {
-    use std::env;
-    let current_dir = env::current_dir()?;
-    let file_path = current_dir.join("training_data.jsonl");
-    let file = File::create(file_path)?;
+    collect_training_data_with_progress(n_games, print_progress)
+}
+
+/// Same as [`collect_training_data`] but calls `progress(done, total)` after
+/// every finished game. Returning `false` stops the generation early, keeping
+/// the records of the games played so far.
+pub fn collect_training_data_with_progress<F: FnMut(usize, usize) -> bool>(
+    n_games: usize,
+    progress: F,
+) -> std::io::Result<()> {
+    let file_path = std::env::current_dir()?.join("training_data.jsonl");
+    collect_training_data_to(&file_path, n_games, OutputFormat::Jsonl, progress)
+}
+
+/// Same as [`collect_training_data_with_progress`] with a chosen output file
+/// and format.
+pub fn collect_training_data_to<F: FnMut(usize, usize) -> bool>(
+    path: &Path,
+    n_games: usize,
+    format: OutputFormat,
+    progress: F,
+) -> std::io::Result<()> {
+    let file = File::create(path)?;
     let mut writer = BufWriter::new(file);
+    write_training_data_as(&mut writer, n_games, format, progress)?;
+    writer.flush()
+}
+
+/// Write the self-play records of up to `n_games` games into `writer`, one
+/// JSON object per line.
+pub fn write_training_data<W: Write, F: FnMut(usize, usize) -> bool>(
+    writer: &mut W,
+    n_games: usize,
+    progress: F,
+) -> std::io::Result<()> {
+    write_training_data_as(writer, n_games, OutputFormat::Jsonl, progress)
+}
+
+fn write_record<W: Write>(
+    writer: &mut W,
+    format: OutputFormat,
+    turn: usize,
+    state: &PartialState,
+    moves: &[Move],
+    mv: Move,
+    win: bool,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Jsonl => {
+            let record = json!({
+                "turn": turn,
+                "partial_state": state_to_json(state),
+                "available_moves": moves.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+                "selected_move": mv.to_string(),
+                "win": win,
+                "style": PlayStyle::Neutral.to_string(),
+            });
+            writer.write_all(to_string(&record)?.as_bytes())?;
+        }
+        OutputFormat::Csv => {
+            let moves = moves.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+            write!(
+                writer,
+                "{turn},{mv},{},{win},{},{}",
+                moves.join(";"),
+                PlayStyle::Neutral,
+                compact_state(state)
+            )?;
+        }
+    }
+    writer.write_all(b"\n")
+}
+
+/// Write the self-play records of up to `n_games` games into `writer` in the
+/// given format.
+pub fn write_training_data_as<W: Write, F: FnMut(usize, usize) -> bool>(
+    writer: &mut W,
+    n_games: usize,
+    format: OutputFormat,
+    mut progress: F,
+) -> std::io::Result<()> {
+    if format == OutputFormat::Csv {
+        writeln!(writer, "{CSV_HEADER}")?;
+    }
     let mut rng = SmallRng::seed_from_u64(0);
 
     for i in 0..n_games {
-        if i % 1000 == 0 && i > 0 {
-            eprintln!("generated {}/{} games", i, n_games);
-        }
         let solitaire = Solitaire::deal_with_rng(&mut rng);
         let mut engine: SolitaireEngine<FullPruner> = solitaire.into();
         let mut seen = HashSet::new();
@@ -71,20 +223,157 @@ pub fn collect_training_data(n_games: usize) -> std::io::Result<()> {
             let ranked = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
             let mv = ranked.first().map(|m| m.mv).unwrap_or(moves[0]);
             engine.do_move(mv);
-            let record = json!({
-                "turn": turn,
-                "partial_state": state_to_json(&state),
-                "available_moves": moves.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
-                "selected_move": mv.to_string(),
-                "win": engine.state().is_win(),
-                "style": "neutral",
-            });
-            writer.write_all(to_string(&record)?.as_bytes())?;
-            writer.write_all(b"\n")?;
+            write_record(writer, format, turn, &state, &moves, mv, engine.state().is_win())?;
             turn += 1;
         }
+
+        if !progress(i + 1, n_games) {
+            break;
+        }
     }
 
-    writer.flush()
+    Ok(())
+}
+
+/// One self-play game played by [`self_play`]
+#[derive(Debug, Clone)]
+pub struct Episode {
+    /// The state seen by the player before every move
+    pub states: Vec<PartialState>,
+    pub moves: Vec<Move>,
+    /// `1` per move, `100` for the winning move and `-1` for a move leaving no moves
+    pub reward: i32,
+    pub win: bool,
+}
+
+/// Play the game dealt from `seed` for up to `max_steps` moves, `policy`
+/// picking every move from the player's view and the legal moves
+///
+/// The episode stops when `policy` returns `None` or a move that isn't legal.
+pub fn self_play<F: FnMut(&PartialState, &[Move]) -> Option<Move>>(
+    seed: u64,
+    max_steps: usize,
+    mut policy: F,
+) -> Episode {
+    let solitaire = Solitaire::deal_with_rng(&mut SmallRng::seed_from_u64(seed));
+    let mut engine: SolitaireEngine<FullPruner> = solitaire.into();
+    let mut episode = Episode {
+        states: Vec::new(),
+        moves: Vec::new(),
+        reward: 0,
+        win: false,
+    };
+
+    while episode.moves.len() < max_steps && !engine.state().is_win() {
+        let moves = engine.list_moves_dom();
+        if moves.is_empty() {
+            break;
+        }
+        let state = PartialState::from_blind(engine.state());
+        let Some(mv) = policy(&state, &moves) else {
+            break;
+        };
+        if !moves.contains(&mv) || !engine.do_move(mv) {
+            break;
+        }
+        episode.states.push(state);
+        episode.moves.push(mv);
+
+        episode.reward += if engine.state().is_win() {
+            100
+        } else if engine.list_moves_dom().is_empty() {
+            -1
+        } else {
+            1
+        };
+    }
+    episode.win = engine.state().is_win();
+    episode
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_early() {
+        let mut stopped = Vec::new();
+        let mut calls = 0;
+        write_training_data(&mut stopped, 10, |done, total| {
+            calls += 1;
+            assert_eq!(total, 10);
+            done < 2
+        })
+        .unwrap();
+        assert_eq!(calls, 2);
+
+        let mut full = Vec::new();
+        write_training_data(&mut full, 2, |_, _| true).unwrap();
+
+        assert!(!stopped.is_empty());
+        assert_eq!(stopped, full);
+
+        let n_games = String::from_utf8(stopped)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .filter(|r| r["turn"] == 0)
+            .count();
+        assert_eq!(n_games, 2);
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut jsonl = Vec::new();
+        write_training_data(&mut jsonl, 2, |_, _| true).unwrap();
+        let mut csv = Vec::new();
+        write_training_data_as(&mut csv, 2, OutputFormat::Csv, |_, _| true).unwrap();
+
+        let jsonl = String::from_utf8(jsonl).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+
+        let n_columns = CSV_HEADER.split(',').count();
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), jsonl.lines().count());
+        assert!(rows.iter().all(|r| r.split(',').count() == n_columns));
+        assert!(rows[0].starts_with("0,"));
+    }
+
+    #[test]
+    fn test_bytes_smaller_than_json() {
+        let solitaire = Solitaire::deal_with_rng(&mut SmallRng::seed_from_u64(0));
+        let state = PartialState::from_blind(&solitaire);
+        let bytes = state.to_bytes();
+        assert_eq!(PartialState::from_bytes(&bytes), Ok(state.clone()));
+        assert!(bytes.len() * 5 < to_string(&state_to_json(&state)).unwrap().len());
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!("CSV".parse(), Ok(OutputFormat::Csv));
+        assert_eq!("jsonl".parse(), Ok(OutputFormat::Jsonl));
+        assert!("parquet".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_self_play() {
+        let mut calls = 0;
+        let episode = self_play(3, 300, |_, moves| {
+            calls += 1;
+            moves.first().copied()
+        });
+        assert!(!episode.moves.is_empty() && episode.moves.len() <= 300);
+        assert_eq!(episode.states.len(), episode.moves.len());
+        // one policy call per move played
+        assert_eq!(calls, episode.moves.len());
+
+        // stopping right away
+        let empty = self_play(3, 300, |_, _| None);
+        assert!(empty.moves.is_empty() && empty.reward == 0 && !empty.win);
+
+        let short = self_play(3, 5, |_, moves| moves.first().copied());
+        assert_eq!(short.moves, episode.moves[..5]);
+    }
+}
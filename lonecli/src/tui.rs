@@ -38,13 +38,7 @@ pub(crate) fn print_foundation(stack: &Stack) {
     print!("\t\t");
     // print out the foundation stack
     for i in 0..N_SUITS {
-        let card = stack.get(i);
-        let card = if card == 0 {
-            None
-        } else {
-            Some(Card::new(card - 1, i))
-        };
-        print!("{}.{} ", i + 1, color(card));
+        print!("{}.{} ", i + 1, color(stack.top(i)));
     }
     println!();
 }
@@ -5,11 +5,17 @@ from lonelybot_py.lonelybot_py import (
     GameState,
     MovePy,
     HeuristicConfigPy,
+    OpeningBook,
     ranked_moves_py,
     best_move_py,
+    sample_move_py,
     best_move_mcts_py,
+    rank_moves_mcts_py,
     column_probabilities_py,
     analyze_state_py,
+    remaining_by_suit_py,
+    analysis_runs_py,
+    plan_to_draw_py,
     collect_training_data_py,
     generate_random_state_py,
     step_py,
@@ -23,6 +29,7 @@ from lonelybot_py.lonelybot_py import (
     get_board_size_py,
     get_action_size_py,
     get_canonical_board_py,
+    self_play_py,
 )
 
 def step(state: GameState, move: str):
@@ -43,9 +50,14 @@ def encode_observation(state: GameState) -> np.ndarray:
 # Expose the functions with their public names
 ranked_moves = ranked_moves_py
 best_move = best_move_py
+sample_move = sample_move_py
 best_move_mcts = best_move_mcts_py
+rank_moves_mcts = rank_moves_mcts_py
 column_probabilities = column_probabilities_py
 analyze_state = analyze_state_py
+remaining_by_suit = remaining_by_suit_py
+analysis_runs = analysis_runs_py
+plan_to_draw = plan_to_draw_py
 collect_training_data = collect_training_data_py
 generate_random_state = generate_random_state_py
 legal_actions = legal_actions_py
@@ -55,16 +67,23 @@ get_game_result = get_game_result_py
 get_board_size = get_board_size_py
 get_action_size = get_action_size_py
 get_canonical_board = get_canonical_board_py
+self_play = self_play_py
 
 __all__ = [
     "GameState",
     "MovePy",
     "HeuristicConfigPy",
+    "OpeningBook",
     "ranked_moves",
     "best_move",
+    "sample_move",
     "best_move_mcts",
+    "rank_moves_mcts",
     "column_probabilities",
     "analyze_state",
+    "remaining_by_suit",
+    "analysis_runs",
+    "plan_to_draw",
     "collect_training_data",
     "generate_random_state",
     "step",
@@ -78,4 +97,5 @@ __all__ = [
     "get_action_size",
     "get_canonical_board",
     "encode_observation",
+    "self_play",
 ]
@@ -4,17 +4,26 @@ use pyo3::wrap_pyfunction;
 use numpy::{PyReadonlyArray2, PyArray2, IntoPyArray};
 use ndarray::Array2;
 
-use lonelybot::analysis::{ranked_moves, ranked_moves_from_partial, analyze_state, HeuristicConfig, PlayStyle, StateAnalysis};
-use lonelybot::game_theory::best_move_mcts;
-use lonelybot::partial::{PartialState, PartialColumn};
-use lonelybot::engine::SolitaireEngine;
+use lonelybot::analysis::{ranked_moves, ranked_moves_from_partial, analyze_state, sample_move, score_move_from_partial, HeuristicConfig, PlayStyle, RankedMove, StateAnalysis};
+use lonelybot::cache::OpeningBook;
+use lonelybot::game_theory::{best_move_mcts, break_ties, rank_moves_mcts, MctsParams};
+use lonelybot::partial::{FillError, PartialState, PartialColumn, PartialStateBuilder};
+use lonelybot::engine::{GameStatus, SolitaireEngine};
 use lonelybot::pruning::FullPruner;
 use lonelybot::standard::StandardSolitaire;
+use lonelybot::stack::Foundation;
+use lonelybot::solver::{solve_with_tracking, SearchResult};
+use lonelybot::tracking::{CancelToken, EmptySearchStats};
 use lonelybot::card::{Card, N_SUITS, N_RANKS};
 use rand::SeedableRng;
 use rand::rngs::SmallRng;
 use pyo3::types::PyDict;
 use serde_json::Value;
+use lonecli::bulk::solve_seeds;
+use lonecli::training::{self_play, state_to_json};
+use std::cell::OnceCell;
+use std::num::NonZeroU8;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 const BOARD_SIZE: usize = 100;
 const ACTION_SIZE: usize = 215;
@@ -37,6 +46,49 @@ impl MovePy {
     }
 }
 
+/// Moves to play in given states, looked up by `best_move_mcts_py` and
+/// `rank_moves_mcts_py` before any playout.
+#[pyclass(name = "OpeningBook")]
+#[derive(Default)]
+pub struct PyOpeningBook {
+    book: OpeningBook,
+}
+
+#[pymethods]
+impl PyOpeningBook {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a book written by `save`
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        let bytes = std::fs::read(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let book = OpeningBook::from_bytes(&bytes)
+            .map_err(|e| PyValueError::new_err(format!("invalid opening book: {e:?}")))?;
+        Ok(Self { book })
+    }
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        std::fs::write(path, self.book.to_bytes()).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Book `mv` for `state`, returning the move it replaces if any
+    fn insert(&mut self, state: &GameState, mv: &str) -> PyResult<Option<String>> {
+        let mv = parse_move_str(mv)?;
+        Ok(self.book.insert(&state.state, mv).map(|m| m.to_string()))
+    }
+
+    fn lookup(&self, state: &GameState) -> Option<String> {
+        self.book.lookup(&state.state).map(|m| m.to_string())
+    }
+
+    fn __len__(&self) -> usize {
+        self.book.len()
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 pub struct HeuristicConfigPy {
@@ -51,15 +103,25 @@ pub struct HeuristicConfigPy {
     #[pyo3(get, set)]
     pub deadlock_penalty: i32,
     #[pyo3(get, set)]
+    pub deadlock_lookahead: u8,
+    #[pyo3(get, set)]
     pub long_column_bonus: i32,
     #[pyo3(get, set)]
     pub chain_bonus: i32,
     #[pyo3(get, set)]
+    pub hold_empty_for_king: i32,
+    #[pyo3(get, set)]
+    pub deck_dig_penalty: i32,
+    #[pyo3(get, set)]
+    pub run_move_bonus: i32,
+    #[pyo3(get, set)]
     pub aggressive_coef: i32,
     #[pyo3(get, set)]
     pub conservative_coef: i32,
     #[pyo3(get, set)]
     pub neutral_coef: i32,
+    #[pyo3(get, set)]
+    pub avoid_blocking: bool,
 }
 
 #[pymethods]
@@ -76,6 +138,11 @@ impl HeuristicConfigPy {
         aggressive_coef: Option<i32>,
         conservative_coef: Option<i32>,
         neutral_coef: Option<i32>,
+        hold_empty_for_king: Option<i32>,
+        deck_dig_penalty: Option<i32>,
+        avoid_blocking: Option<bool>,
+        run_move_bonus: Option<i32>,
+        deadlock_lookahead: Option<u8>,
     ) -> Self {
         let d = HeuristicConfig::default();
         Self {
@@ -84,11 +151,16 @@ impl HeuristicConfigPy {
             early_foundation_penalty: early_foundation_penalty.unwrap_or(d.early_foundation_penalty),
             keep_king_bonus: keep_king_bonus.unwrap_or(d.keep_king_bonus),
             deadlock_penalty: deadlock_penalty.unwrap_or(d.deadlock_penalty),
+            deadlock_lookahead: deadlock_lookahead.unwrap_or(d.deadlock_lookahead),
             long_column_bonus: long_column_bonus.unwrap_or(d.long_column_bonus),
             chain_bonus: chain_bonus.unwrap_or(d.chain_bonus),
+            hold_empty_for_king: hold_empty_for_king.unwrap_or(d.hold_empty_for_king),
+            deck_dig_penalty: deck_dig_penalty.unwrap_or(d.deck_dig_penalty),
+            run_move_bonus: run_move_bonus.unwrap_or(d.run_move_bonus),
             aggressive_coef: aggressive_coef.unwrap_or(d.aggressive_coef),
             conservative_coef: conservative_coef.unwrap_or(d.conservative_coef),
             neutral_coef: neutral_coef.unwrap_or(d.neutral_coef),
+            avoid_blocking: avoid_blocking.unwrap_or(d.avoid_blocking),
         }
     }
 }
@@ -101,19 +173,79 @@ impl From<&HeuristicConfigPy> for HeuristicConfig {
             early_foundation_penalty: p.early_foundation_penalty,
             keep_king_bonus: p.keep_king_bonus,
             deadlock_penalty: p.deadlock_penalty,
+            deadlock_lookahead: p.deadlock_lookahead,
             long_column_bonus: p.long_column_bonus,
             chain_bonus: p.chain_bonus,
+            hold_empty_for_king: p.hold_empty_for_king,
+            deck_dig_penalty: p.deck_dig_penalty,
+            run_move_bonus: p.run_move_bonus,
             aggressive_coef: p.aggressive_coef,
             conservative_coef: p.conservative_coef,
             neutral_coef: p.neutral_coef,
+            explain: false,
+            avoid_blocking: p.avoid_blocking,
         }
     }
 }
 
+/// Shared flag to stop a running `solve_py` from another Python thread.
+#[pyclass(name = "CancelToken")]
+#[derive(Clone, Default)]
+pub struct PyCancelToken {
+    token: CancelToken,
+}
+
+#[pymethods]
+impl PyCancelToken {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 pub struct GameState {
     state: PartialState,
+    // the state never changes, so neither does its analysis
+    analysis: OnceCell<StateAnalysis>,
+}
+
+/// Number of `analyze_state` computations, the cached ones aside
+static ANALYSIS_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+impl From<PartialState> for GameState {
+    fn from(state: PartialState) -> Self {
+        Self { state, analysis: OnceCell::new() }
+    }
+}
+
+impl GameState {
+    fn analysis(&self) -> &StateAnalysis {
+        self.analysis.get_or_init(|| {
+            ANALYSIS_RUNS.fetch_add(1, Ordering::Relaxed);
+            analyze_state(&self.state)
+        })
+    }
+}
+
+fn parse_suit(ch: char) -> PyResult<u8> {
+    match ch {
+        'H' | 'h' | '♥' => Ok(0),
+        'D' | 'd' | '♦' => Ok(1),
+        'C' | 'c' | '♣' => Ok(2),
+        'S' | 's' | '♠' => Ok(3),
+        _ => Err(PyValueError::new_err("invalid suit")),
+    }
 }
 
 fn parse_card(s: &str) -> PyResult<Card> {
@@ -134,14 +266,7 @@ fn parse_card(s: &str) -> PyResult<Card> {
         .iter()
         .position(|&r| r.eq_ignore_ascii_case(&rank_str))
         .ok_or_else(|| PyValueError::new_err("invalid rank"))? as u8;
-    let suit = match suit_ch {
-        'H' | 'h' | '♥' => 0,
-        'D' | 'd' | '♦' => 1,
-        'C' | 'c' | '♣' => 2,
-        'S' | 's' | '♠' => 3,
-        _ => return Err(PyValueError::new_err("invalid suit")),
-    };
-    Ok(Card::new(rank, suit))
+    Ok(Card::new(rank, parse_suit(suit_ch)?))
 }
 
 fn parse_move_str(s: &str) -> PyResult<lonelybot::moves::Move> {
@@ -165,7 +290,22 @@ fn parse_move_str(s: &str) -> PyResult<lonelybot::moves::Move> {
 
 fn parse_json_state(txt: &str) -> PyResult<PartialState> {
     let v: Value = serde_json::from_str(txt).map_err(|e| PyValueError::new_err(e.to_string()))?;
-    let draw_step = v.get("draw_step").and_then(|x| x.as_u64()).unwrap_or(1) as u8;
+    let draw_step = match v.get("draw_step") {
+        None => 1,
+        Some(x) => x
+            .as_u64()
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| PyValueError::new_err("invalid draw_step"))?,
+    };
+    // missing or null when the deck can be redealt forever
+    let redeal_limit = match v.get("redeal_limit") {
+        None | Some(Value::Null) => None,
+        Some(x) => Some(
+            x.as_u64()
+                .and_then(|x| u8::try_from(x).ok())
+                .ok_or_else(|| PyValueError::new_err("invalid redeal_limit"))?,
+        ),
+    };
     let mut columns: [PartialColumn;7] = core::array::from_fn(|_| PartialColumn { hidden: Vec::new(), visible: lonelybot::standard::PileVec::new() });
     if let Some(cols) = v.get("columns").and_then(|c| c.as_array()) {
         for (i,col) in cols.iter().enumerate().take(7) {
@@ -202,7 +342,52 @@ fn parse_json_state(txt: &str) -> PyResult<PartialState> {
             }
         }
     }
-    Ok(PartialState { columns, deck, draw_step })
+    // the number of deck cards drawn, the rest being the stock
+    let deck_offset = match v.get("deck_offset") {
+        None => 0,
+        Some(x) => x
+            .as_u64()
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| PyValueError::new_err("invalid deck_offset"))?,
+    };
+    // suit -> height, or the top card of the suit
+    let mut foundations = Foundation::default();
+    if let Some(f) = v.get("foundations").and_then(|f| f.as_object()) {
+        for (key, x) in f {
+            let mut chars = key.chars();
+            let suit = match (chars.next(), chars.next()) {
+                (Some(ch), None) => parse_suit(ch)?,
+                _ => return Err(PyValueError::new_err("invalid suit")),
+            };
+            let height = if let Some(s) = x.as_str() {
+                let card = parse_card(s)?;
+                if card.suit() != suit {
+                    return Err(PyValueError::new_err("invalid foundation"));
+                }
+                card.rank() + 1
+            } else {
+                x.as_u64()
+                    .and_then(|x| u8::try_from(x).ok())
+                    .filter(|&x| x <= N_RANKS)
+                    .ok_or_else(|| PyValueError::new_err("invalid foundation"))?
+            };
+            for rank in foundations.get(suit)..height {
+                foundations.push(Card::new(rank, suit));
+            }
+        }
+    }
+    // go through the builder so that invalid states are rejected here
+    // instead of panicking when the unknown cards get filled
+    columns
+        .iter()
+        .fold(PartialStateBuilder::new(), |b, c| b.column(&c.hidden, &c.visible))
+        .deck(&deck)
+        .deck_offset(deck_offset)
+        .draw_step(draw_step)
+        .redeal_limit(redeal_limit)
+        .foundations(foundations)
+        .build()
+        .map_err(|e| PyValueError::new_err(format!("invalid state: {e:?}")))
 }
 
 #[pymethods]
@@ -213,100 +398,203 @@ impl GameState {
         use core::num::NonZeroU8;
         let deck = default_shuffle(0);
         let std = StandardSolitaire::new(&deck, NonZeroU8::new(1).unwrap());
-        Self { state: PartialState::from(&std) }
+        PartialState::from(&std).into()
     }
 
     #[staticmethod]
     fn from_json(txt: &str) -> PyResult<Self> {
-        Ok(Self { state: parse_json_state(txt)? })
+        Ok(parse_json_state(txt)?.into())
+    }
+
+    /// Serialize the state in the layout read by `from_json`
+    fn to_json(&self) -> String {
+        state_to_json(&self.state).to_string()
+    }
+
+    /// Return the state after playing `mv`, keeping the unknown cards unknown
+    /// (unlike `step_py`, which rebuilds the state from a filled game).
+    fn apply(&self, mv: &MovePy) -> PyResult<Self> {
+        let mut state = self.state.clone();
+        state
+            .apply_move(mv.mv)
+            .map_err(|_| PyValueError::new_err(format!("invalid move `{}`", mv.mv)))?;
+        Ok(state.into())
+    }
+
+    /// The deck cards still to draw, the next one first, unknown cards being
+    /// `"unknown"`
+    fn stock_cards(&self) -> Vec<String> {
+        let (_, stock) = self.state.deck.split_at(self.state.deck_offset.into());
+        stock.iter().map(|&c| card_or_unknown(c)).collect()
+    }
+
+    /// The deck cards already drawn, the current one (drawn last) first, as
+    /// in `stock_cards`
+    fn waste_cards(&self) -> Vec<String> {
+        let (waste, _) = self.state.deck.split_at(self.state.deck_offset.into());
+        waste.iter().rev().map(|&c| card_or_unknown(c)).collect()
     }
-}
 
-fn get_style(style: &str) -> PlayStyle {
-    match style {
-        "aggressive" => PlayStyle::Aggressive,
-        "conservative" => PlayStyle::Conservative,
-        _ => PlayStyle::Neutral,
+    /// The `ranked_moves_py` entry of `mv`, without ranking the other moves
+    #[pyo3(signature = (mv, style, cfg=None))]
+    fn score_move(
+        &self,
+        mv: &MovePy,
+        style: &str,
+        cfg: Option<&HeuristicConfigPy>,
+    ) -> PyResult<PyObject> {
+        let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
+        let m = score_move_from_partial(&self.state, mv.mv, get_style(style)?, &cfg)
+            .ok_or_else(|| PyValueError::new_err(format!("invalid move `{}`", mv.mv)))?;
+        Python::with_gil(|py| ranked_move_dict(py, &m).map(Into::into))
     }
 }
 
-#[pyfunction]
+fn get_style(style: &str) -> PyResult<PlayStyle> {
+    style
+        .parse()
+        .map_err(|_| PyValueError::new_err(format!("unknown play style `{style}`")))
+}
+
+#[pyfunction(signature = (state, style, cfg=None, explain=false))]
 fn ranked_moves_py(
     state: &GameState,
     style: &str,
     cfg: Option<&HeuristicConfigPy>,
+    explain: bool,
 ) -> PyResult<Vec<PyObject>> {
-    let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
-    let moves = ranked_moves_from_partial(&state.state, get_style(style), &cfg);
+    let mut cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
+    cfg.explain = explain;
+    let moves = ranked_moves_from_partial(&state.state, get_style(style)?, &cfg);
 
     Python::with_gil(|py| {
         let mut res = Vec::new();
         for m in moves {
-            let dict = PyDict::new(py);
-            dict.set_item("move", MovePy { mv: m.mv }.into_py(py))?;
-            dict.set_item("heuristic_score", m.heuristic_score)?;
-            dict.set_item("simulation_score", m.simulation_score)?;
-            dict.set_item("will_block", m.will_block)?;
-            let revealed: Vec<String> = m.revealed_cards.iter().map(|c| c.to_string()).collect();
-            dict.set_item("revealed_cards", revealed)?;
-            dict.set_item("columns_freed", m.columns_freed)?;
-            dict.set_item("win_rate", m.win_rate)?;
+            let dict = ranked_move_dict(py, &m)?;
+            if explain {
+                let breakdown = PyDict::new(py);
+                for (name, value) in &m.score_breakdown {
+                    // a heuristic can apply twice, e.g. the long column bonus
+                    let prev: i32 = breakdown.get_item(name)?.map_or(Ok(0), |v| v.extract())?;
+                    breakdown.set_item(name, prev + value)?;
+                }
+                dict.set_item("score_breakdown", breakdown)?;
+            }
             res.push(dict.into());
         }
         Ok(res)
     })
 }
 
-#[pyfunction]
+/// The fields of a ranked move, without its score breakdown
+fn ranked_move_dict<'py>(py: Python<'py>, m: &RankedMove) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("move", MovePy { mv: m.mv }.into_py(py))?;
+    dict.set_item("heuristic_score", m.heuristic_score)?;
+    dict.set_item("simulation_score", m.simulation_score)?;
+    dict.set_item("will_block", m.will_block)?;
+    let revealed: Vec<String> = m.revealed_cards.iter().map(|c| c.to_string()).collect();
+    dict.set_item("revealed_cards", revealed)?;
+    dict.set_item("columns_freed", m.columns_freed)?;
+    dict.set_item("win_rate", m.win_rate)?;
+    dict.set_item("win_rate_ci", m.win_rate_ci)?;
+    dict.set_item("productive", m.productive)?;
+    Ok(dict)
+}
+
+#[pyfunction(signature = (state, style, cfg=None, tiebreak_playouts=None, tiebreak_margin=0, max_depth=100))]
 fn best_move_py(
     state: &GameState,
     style: &str,
     cfg: Option<&HeuristicConfigPy>,
+    tiebreak_playouts: Option<usize>,
+    tiebreak_margin: i32,
+    max_depth: usize,
 ) -> PyResult<Option<MovePy>> {
     let mut rng = SmallRng::seed_from_u64(0);
     let probs = state.state.column_probabilities();
-    let g = state.state.fill_unknowns_weighted(&probs, &mut rng);
+    let g = state.state.fill_unknowns_weighted(&probs, &mut rng).map_err(fill_error)?;
     let solitaire: lonelybot::state::Solitaire = (&g).into();
     let engine: SolitaireEngine<FullPruner> = solitaire.into();
     let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
-    let mv = ranked_moves(&engine, &state.state, get_style(style), &cfg)
-        .into_iter()
-        .next();
+    let mut moves = ranked_moves(&engine, &state.state, get_style(style)?, &cfg);
+    let mv = match tiebreak_playouts {
+        Some(n_playouts) => break_ties(
+            &state.state,
+            &mut moves,
+            tiebreak_margin,
+            n_playouts,
+            max_depth,
+            &mut rng,
+        ),
+        None => moves.into_iter().next(),
+    };
     Ok(mv.map(|m| MovePy { mv: m.mv }))
 }
 
-#[pyfunction(signature = (state, style, n_playouts, max_depth, cfg=None))]
+/// A move drawn at random with the better ones more likely, a `temperature`
+/// of zero always gives the best move
+#[pyfunction(signature = (state, style, temperature, seed=0, cfg=None))]
+fn sample_move_py(
+    state: &GameState,
+    style: &str,
+    temperature: f64,
+    seed: u64,
+    cfg: Option<&HeuristicConfigPy>,
+) -> PyResult<Option<MovePy>> {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let probs = state.state.column_probabilities();
+    let g = state.state.fill_unknowns_weighted(&probs, &mut rng).map_err(fill_error)?;
+    let solitaire: lonelybot::state::Solitaire = (&g).into();
+    let engine: SolitaireEngine<FullPruner> = solitaire.into();
+    let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
+    let moves = ranked_moves(&engine, &state.state, get_style(style)?, &cfg);
+    Ok(sample_move(&moves, temperature, &mut rng).map(|mv| MovePy { mv }))
+}
+
+#[pyfunction(signature = (state, style, n_playouts, max_depth, cfg=None, book=None))]
 fn best_move_mcts_py(
     state: &GameState,
     style: &str,
     n_playouts: usize,
     max_depth: usize,
     cfg: Option<&HeuristicConfigPy>,
+    book: Option<&PyOpeningBook>,
 ) -> PyResult<Option<PyObject>> {
     let mut rng = SmallRng::seed_from_u64(0);
     let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
-    let mv = best_move_mcts(
-        &state.state,
-        get_style(style),
-        &cfg,
-        n_playouts,
-        max_depth,
-        &mut rng,
-    );
+    let params = MctsParams {
+        book: book.map(|b| &b.book),
+        ..MctsParams::new(n_playouts, max_depth)
+    };
+    let mv = best_move_mcts(&state.state, get_style(style)?, &cfg, &params, &mut rng);
+
+    Python::with_gil(|py| mv.map(|m| ranked_move_dict(py, &m).map(Into::into)).transpose())
+}
+
+/// Every root move with its playout results, from the highest win rate down
+#[pyfunction(signature = (state, style, n_playouts, max_depth, cfg=None, book=None))]
+fn rank_moves_mcts_py(
+    state: &GameState,
+    style: &str,
+    n_playouts: usize,
+    max_depth: usize,
+    cfg: Option<&HeuristicConfigPy>,
+    book: Option<&PyOpeningBook>,
+) -> PyResult<Vec<PyObject>> {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let cfg = cfg.map_or_else(HeuristicConfig::default, |c| c.into());
+    let params = MctsParams {
+        book: book.map(|b| &b.book),
+        ..MctsParams::new(n_playouts, max_depth)
+    };
+    let moves = rank_moves_mcts(&state.state, get_style(style)?, &cfg, &params, &mut rng);
 
     Python::with_gil(|py| {
-        Ok(mv.map(|m| {
-            let dict = PyDict::new(py);
-            dict.set_item("move", MovePy { mv: m.mv }.into_py(py)).unwrap();
-            dict.set_item("heuristic_score", m.heuristic_score).unwrap();
-            dict.set_item("simulation_score", m.simulation_score).unwrap();
-            dict.set_item("will_block", m.will_block).unwrap();
-            let revealed: Vec<String> = m.revealed_cards.iter().map(|c| c.to_string()).collect();
-            dict.set_item("revealed_cards", revealed).unwrap();
-            dict.set_item("columns_freed", m.columns_freed).unwrap();
-            dict.set_item("win_rate", m.win_rate).unwrap();
-            dict.into()
-        }))
+        moves
+            .iter()
+            .map(|m| ranked_move_dict(py, m).map(Into::into))
+            .collect()
     })
 }
 
@@ -316,22 +604,136 @@ fn column_probabilities_py(state: &GameState) -> PyResult<Vec<Vec<(String, f64)>
         .map(|col| col.into_iter().map(|(c,p)| (c.to_string(), p)).collect()).collect())
 }
 
+/// Number of draws before the known deck card `card` is the current card,
+/// `None` when it can't be brought up by drawing alone
+#[pyfunction]
+fn plan_to_draw_py(state: &GameState, card: &str) -> PyResult<Option<u8>> {
+    Ok(state.state.plan_to_draw(parse_card(card)?))
+}
+
+/// The cards known nowhere in the state, one list per suit in the order
+/// `HDCS`
+#[pyfunction]
+fn remaining_by_suit_py(state: &GameState) -> PyResult<Vec<Vec<String>>> {
+    Ok(state.state.remaining_by_suit().iter()
+        .map(|cards| cards.iter().map(|c| c.to_string()).collect()).collect())
+}
+
 #[pyfunction]
-fn analyze_state_py(state: &GameState) -> PyResult<(usize, Vec<String>, usize, usize, f64)> {
-    let info: StateAnalysis = analyze_state(&state.state);
+fn analyze_state_py(state: &GameState) -> PyResult<(usize, Vec<String>, usize, usize, f64, usize)> {
+    let info = state.analysis();
     Ok((
         info.unknown_cards,
-        info.remaining_cards.into_iter().map(|c| c.to_string()).collect(),
+        info.remaining_cards.iter().map(|c| c.to_string()).collect(),
         info.blocked_columns,
         info.mobility,
         info.deadlock_risk,
+        info.useful_stock_cards,
     ))
 }
 
+/// Number of times `analyze_state_py` computed an analysis instead of reusing
+/// the one cached in its `GameState`
 #[pyfunction]
-fn collect_training_data_py(n_games: usize) -> PyResult<()> {
-    lonecli::training::collect_training_data(n_games)
-        .map_err(|e| PyIOError::new_err(e.to_string()))
+fn analysis_runs_py() -> usize {
+    ANALYSIS_RUNS.load(Ordering::Relaxed)
+}
+
+/// `progress(done, total)` is called after every game; returning a falsy
+/// value (other than `None`) stops the generation. `format` is `"jsonl"` or
+/// `"csv"`, the output goes to `path` or `training_data.<format>`.
+#[pyfunction(signature = (n_games, progress=None, path=None, format="jsonl"))]
+fn collect_training_data_py(
+    py: Python<'_>,
+    n_games: usize,
+    progress: Option<PyObject>,
+    path: Option<&str>,
+    format: &str,
+) -> PyResult<()> {
+    use lonecli::training::{collect_training_data_to, print_progress, OutputFormat};
+
+    let format: OutputFormat = format.parse().map_err(PyValueError::new_err)?;
+    let path = path.map_or_else(
+        || format!("training_data.{}", format.extension()),
+        str::to_string,
+    );
+    let path = std::path::Path::new(&path);
+
+    let Some(progress) = progress else {
+        return collect_training_data_to(path, n_games, format, print_progress)
+            .map_err(|e| PyIOError::new_err(e.to_string()));
+    };
+
+    let mut err = None;
+    collect_training_data_to(path, n_games, format, |done, total| {
+        match progress
+            .call1(py, (done, total))
+            .and_then(|r| Ok(r.is_none(py) || r.is_true(py)?))
+        {
+            Ok(keep_going) => keep_going,
+            Err(e) => {
+                err = Some(e);
+                false
+            }
+        }
+    })
+    .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    err.map_or(Ok(()), Err)
+}
+
+/// Solve a fully known state, returning the search result and the winning
+/// moves if any. The GIL is released during the search so that another
+/// thread can call `cancel.cancel()`.
+#[pyfunction(signature = (state, cancel=None))]
+fn solve_py(
+    py: Python<'_>,
+    state: &GameState,
+    cancel: Option<&PyCancelToken>,
+) -> PyResult<(String, Option<Vec<String>>)> {
+    if !state.state.is_fully_known() {
+        return Err(PyValueError::new_err("cannot solve a state with unknown cards"));
+    }
+    let std = state
+        .state
+        .fill_unknowns_randomly(&mut SmallRng::seed_from_u64(0))
+        .map_err(fill_error)?;
+    let mut game: lonelybot::state::Solitaire = (&std).into();
+    let token = cancel.map(|c| c.token.clone()).unwrap_or_default();
+
+    let (res, history) =
+        py.allow_threads(|| solve_with_tracking(&mut game, &EmptySearchStats {}, &token));
+    Ok((
+        search_result_str(&res).to_string(),
+        history.map(|h| h.iter().map(|m| m.to_string()).collect()),
+    ))
+}
+
+fn search_result_str(res: &SearchResult) -> &'static str {
+    match res {
+        SearchResult::Solved => "solved",
+        SearchResult::Unsolvable => "unsolvable",
+        SearchResult::Terminated => "terminated",
+        SearchResult::Crashed => "crashed",
+    }
+}
+
+/// Solve the deals of `seeds` in parallel, returning for each seed the result
+/// (see `solve_py`) and the length of the solution if any.
+#[pyfunction(signature = (seeds, draw_step=1, node_limit=None, n_threads=1))]
+fn solve_seeds_py(
+    py: Python<'_>,
+    seeds: Vec<u64>,
+    draw_step: u8,
+    node_limit: Option<usize>,
+    n_threads: usize,
+) -> PyResult<Vec<(u64, String, Option<usize>)>> {
+    let draw_step = NonZeroU8::new(draw_step)
+        .ok_or_else(|| PyValueError::new_err("draw_step must be at least 1"))?;
+    let results = py.allow_threads(|| solve_seeds(&seeds, draw_step, node_limit, n_threads));
+    Ok(results
+        .into_iter()
+        .map(|(seed, res, len)| (seed, search_result_str(&res).to_string(), len))
+        .collect())
 }
 
 #[pyfunction]
@@ -344,16 +746,22 @@ fn generate_random_state_py() -> PyResult<GameState> {
     let mut rng = SmallRng::seed_from_u64(seed);
     let solitaire = lonelybot::state::Solitaire::deal_with_rng(&mut rng);
     let std: StandardSolitaire = (&solitaire).into();
-    Ok(GameState {
-        state: PartialState::from(&std),
-    })
+    Ok(PartialState::from(&std).into())
 }
 
-fn to_engine(state: &PartialState) -> SolitaireEngine<FullPruner> {
+fn card_or_unknown(c: Option<Card>) -> String {
+    c.map_or_else(|| "unknown".into(), |c| c.to_string())
+}
+
+fn fill_error(e: FillError) -> PyErr {
+    PyValueError::new_err(format!("inconsistent state: {e:?}"))
+}
+
+fn to_engine(state: &PartialState) -> PyResult<SolitaireEngine<FullPruner>> {
     let mut rng = SmallRng::seed_from_u64(0);
-    let std = state.fill_unknowns_randomly(&mut rng);
+    let std = state.fill_unknowns_randomly(&mut rng).map_err(fill_error)?;
     let sol: lonelybot::state::Solitaire = (&std).into();
-    sol.into()
+    Ok(sol.into())
 }
 
 fn move_to_action_idx(engine: &SolitaireEngine<FullPruner>, m: &lonelybot::moves::Move) -> PyResult<u8> {
@@ -376,7 +784,7 @@ fn card_from_mask_index(idx: u8) -> Card {
 }
 
 fn action_idx_to_move_str(state: &GameState, idx: u8) -> PyResult<String> {
-    let engine = to_engine(&state.state);
+    let engine = to_engine(&state.state)?;
     Ok(if idx < OFF_PILE_STACK {
         let c = card_from_mask_index(idx);
         format!("DS {c}")
@@ -401,7 +809,7 @@ fn action_idx_to_move_str(state: &GameState, idx: u8) -> PyResult<String> {
 
 #[pyfunction]
 fn legal_actions_py(state: &GameState) -> PyResult<Vec<String>> {
-    let engine = to_engine(&state.state);
+    let engine = to_engine(&state.state)?;
     Ok(engine
         .list_moves_dom()
         .iter()
@@ -411,37 +819,78 @@ fn legal_actions_py(state: &GameState) -> PyResult<Vec<String>> {
 
 #[pyfunction]
 fn is_terminal_py(state: &GameState) -> PyResult<bool> {
-    let engine = to_engine(&state.state);
-    Ok(engine.state().is_win() || engine.list_moves_dom().is_empty())
+    let engine = to_engine(&state.state)?;
+    Ok(engine.status() != GameStatus::Ongoing)
 }
 
 #[pyfunction]
 fn step_py(state: &GameState, mv: &str) -> PyResult<(GameState, bool, i32)> {
-    let mut engine = to_engine(&state.state);
+    let mut engine = to_engine(&state.state)?;
     let parsed = parse_move_str(mv)?;
     let valid = engine.do_move(parsed);
     if !valid {
         return Ok((state.clone(), false, -1));
     }
-    let done = engine.state().is_win() || engine.list_moves_dom().is_empty();
-    let reward = if engine.state().is_win() {
-        100
-    } else if done {
-        -1
-    } else {
-        1
+    let status = engine.status();
+    let reward = match status {
+        GameStatus::Won => 100,
+        GameStatus::Lost => -1,
+        GameStatus::Ongoing => 1,
     };
+    let done = status != GameStatus::Ongoing;
     let st: StandardSolitaire = engine.state().into();
-    let next_state = GameState {
-        state: PartialState::from(&st),
-    };
+    let next_state = GameState::from(PartialState::from(&st));
     Ok((next_state, done, reward))
 }
 
+/// Run a whole episode in Rust, calling `policy(state, legal_moves)` for every
+/// move. The policy returns one of the legal moves or `None` to stop.
+#[pyfunction(signature = (policy, max_steps=1000, seed=0))]
+fn self_play_py(py: Python<'_>, policy: PyObject, max_steps: usize, seed: u64) -> PyResult<PyObject> {
+    let mut err = None;
+    let episode = py.allow_threads(|| {
+        self_play(seed, max_steps, |state, moves| {
+            Python::with_gil(|py| {
+                let legal: Vec<String> = moves.iter().map(|m| m.to_string()).collect();
+                let picked = policy
+                    .call1(py, (GameState::from(state.clone()), legal))
+                    .and_then(|r| r.extract::<Option<String>>(py))
+                    .and_then(|r| r.map(|s| parse_move_str(&s)).transpose())
+                    .and_then(|m| match m {
+                        Some(m) if !moves.contains(&m) => {
+                            Err(PyValueError::new_err(format!("illegal move: {m}")))
+                        }
+                        m => Ok(m),
+                    });
+                picked.unwrap_or_else(|e| {
+                    err = Some(e);
+                    None
+                })
+            })
+        })
+    });
+    if let Some(e) = err {
+        return Err(e);
+    }
+
+    let dict = PyDict::new(py);
+    let states: Vec<PyObject> = episode
+        .states
+        .into_iter()
+        .map(|state| GameState::from(state).into_py(py))
+        .collect();
+    dict.set_item("states", states)?;
+    let moves: Vec<String> = episode.moves.iter().map(|m| m.to_string()).collect();
+    dict.set_item("moves", moves)?;
+    dict.set_item("reward", episode.reward)?;
+    dict.set_item("win", episode.win)?;
+    Ok(dict.into())
+}
+
 #[pyfunction]
 fn encode_observation_py(state: &GameState) -> PyResult<Vec<i32>> {
     let mut rng = SmallRng::seed_from_u64(0);
-    let std = state.state.fill_unknowns_randomly(&mut rng);
+    let std = state.state.fill_unknowns_randomly(&mut rng).map_err(fill_error)?;
     let mut obs: Vec<i32> = Vec::with_capacity(100);
     for col in &std.get_piles()[..] {
         for i in 0..13 {
@@ -476,7 +925,7 @@ fn reset_py(py: Python<'_>) -> PyResult<(GameState, Py<PyArray2<i8>>)> {
 
 #[pyfunction]
 fn get_valid_actions_py(state: &GameState) -> PyResult<Vec<u8>> {
-    let engine = to_engine(&state.state);
+    let engine = to_engine(&state.state)?;
     Ok(engine
         .list_moves_dom()
         .iter()
@@ -496,14 +945,12 @@ fn step_action_py(py: Python<'_>, state: &GameState, action: u8) -> PyResult<(Ga
 
 #[pyfunction]
 fn get_game_result_py(state: &GameState) -> PyResult<i8> {
-    let engine = to_engine(&state.state);
-    if engine.state().is_win() {
-        Ok(1)
-    } else if engine.list_moves_dom().is_empty() {
-        Ok(-1)
-    } else {
-        Ok(0)
-    }
+    let engine = to_engine(&state.state)?;
+    Ok(match engine.status() {
+        GameStatus::Won => 1,
+        GameStatus::Lost => -1,
+        GameStatus::Ongoing => 0,
+    })
 }
 
 #[pyfunction]
@@ -528,12 +975,21 @@ fn lonelybot_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<GameState>()?;
     m.add_class::<MovePy>()?;
     m.add_class::<HeuristicConfigPy>()?;
+    m.add_class::<PyCancelToken>()?;
+    m.add_class::<PyOpeningBook>()?;
     m.add_function(wrap_pyfunction!(ranked_moves_py, m)?)?;
     m.add_function(wrap_pyfunction!(best_move_py, m)?)?;
+    m.add_function(wrap_pyfunction!(sample_move_py, m)?)?;
     m.add_function(wrap_pyfunction!(best_move_mcts_py, m)?)?;
+    m.add_function(wrap_pyfunction!(rank_moves_mcts_py, m)?)?;
     m.add_function(wrap_pyfunction!(column_probabilities_py, m)?)?;
     m.add_function(wrap_pyfunction!(analyze_state_py, m)?)?;
+    m.add_function(wrap_pyfunction!(remaining_by_suit_py, m)?)?;
+    m.add_function(wrap_pyfunction!(analysis_runs_py, m)?)?;
+    m.add_function(wrap_pyfunction!(plan_to_draw_py, m)?)?;
     m.add_function(wrap_pyfunction!(collect_training_data_py, m)?)?;
+    m.add_function(wrap_pyfunction!(solve_py, m)?)?;
+    m.add_function(wrap_pyfunction!(solve_seeds_py, m)?)?;
     m.add_function(wrap_pyfunction!(generate_random_state_py, m)?)?;
     m.add_function(wrap_pyfunction!(step_py, m)?)?;
     m.add_function(wrap_pyfunction!(legal_actions_py, m)?)?;
@@ -546,6 +1002,7 @@ fn lonelybot_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(get_board_size_py, m)?)?;
     m.add_function(wrap_pyfunction!(get_action_size_py, m)?)?;
     m.add_function(wrap_pyfunction!(get_canonical_board_py, m)?)?;
+    m.add_function(wrap_pyfunction!(self_play_py, m)?)?;
     Ok(())
 }
 
@@ -558,4 +1015,116 @@ mod tests {
         let data = r#"{"columns":[{"hidden":["ZZ"],"visible":[]}],"deck":[]}"#;
         assert!(parse_json_state(data).is_err());
     }
+
+    #[test]
+    fn json_round_trip() {
+        let txt = include_str!("../../example_state.json");
+        let state = GameState::from_json(txt).unwrap();
+        let back = GameState::from_json(&state.to_json()).unwrap();
+        assert_eq!(back.state, state.state);
+
+        let txt = txt.replace("\"draw_step\": 1", "\"draw_step\": 3, \"redeal_limit\": 2");
+        let state = GameState::from_json(&txt).unwrap();
+        assert_eq!(state.state.redeal_limit, Some(2));
+        let back = GameState::from_json(&state.to_json()).unwrap();
+        assert_eq!(back.state, state.state);
+
+        let txt = txt.replace("\"deck\"", "\"foundations\": {\"H\": \"3H\", \"S\": 4}, \"deck\"");
+        let state = GameState::from_json(&txt).unwrap();
+        assert_eq!(state.state.foundations.get(0), 3);
+        assert_eq!(state.state.foundations.get(3), 4);
+        let back = GameState::from_json(&state.to_json()).unwrap();
+        assert_eq!(back.state, state.state);
+        assert!(GameState::from_json(&txt.replace("\"3H\"", "\"3D\"")).is_err());
+
+        let drawn = txt.replace("\"deck\"", "\"deck_offset\": 1, \"deck\"");
+        let state = GameState::from_json(&drawn).unwrap();
+        assert_eq!(state.state.deck_offset, 1);
+        let back = GameState::from_json(&state.to_json()).unwrap();
+        assert_eq!(back.state, state.state);
+        assert!(GameState::from_json(&drawn.replace("\"deck_offset\": 1", "\"deck_offset\": 2")).is_err());
+    }
+
+    #[test]
+    fn opening_book_round_trip() {
+        let state = GameState::from_json(include_str!("../../example_state.json")).unwrap();
+        let mut book = PyOpeningBook::new();
+        assert!(book.insert(&state, "X KH").is_err());
+        assert_eq!(book.insert(&state, "R KH").unwrap(), None);
+        let booked = lonelybot::moves::Move::Reveal(Card::new(12, 0)).to_string();
+        assert_eq!(book.lookup(&state), Some(booked.clone()));
+
+        let path = std::env::temp_dir().join("lonelybot_py_test_opening_book.bin");
+        let path = path.to_str().unwrap();
+        book.save(path).unwrap();
+        let loaded = PyOpeningBook::load(path).unwrap();
+        assert_eq!(loaded.lookup(&state), Some(booked));
+        assert_eq!(loaded.__len__(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn over_specified_state_errors() {
+        let deck = lonelybot::shuffler::default_shuffle(0);
+        let game = StandardSolitaire::new(&deck, NonZeroU8::new(1).unwrap());
+        let mut state = PartialState::from(&game);
+        // every card is known but one more is unknown
+        state.deck.push(None);
+        assert!(encode_observation_py(&GameState::from(state)).is_err());
+    }
+
+    #[test]
+    fn fresh_deck_split() {
+        let state = GameState::new();
+        assert_eq!(state.stock_cards().len() + state.waste_cards().len(), 24);
+
+        let txt = include_str!("../../example_state.json");
+        assert_eq!(GameState::from_json(txt).unwrap().stock_cards(), ["unknown"]);
+
+        // two draws of three cards in
+        let draw_step = core::num::NonZeroU8::new(3).unwrap();
+        let mut std = StandardSolitaire::new(&lonelybot::shuffler::default_shuffle(0), draw_step);
+        std.draw_stock().unwrap();
+        std.draw_stock().unwrap();
+        let deck: Vec<String> = std.get_deck().iter().map(|c| c.to_string()).collect();
+        let state = GameState::from(PartialState::from(&std));
+        let (stock, waste) = (state.stock_cards(), state.waste_cards());
+        assert_eq!((stock.len(), waste.len()), (18, 6));
+        assert_eq!(stock[0], deck[6]);
+        assert_eq!(waste[0], deck[5]);
+        assert_eq!(waste[5], deck[0]);
+    }
+
+    #[test]
+    fn score_move_matches_ranking() {
+        let state = GameState::new();
+        let ranked = ranked_moves_py(&state, "neutral", None, false).unwrap();
+        assert!(!ranked.is_empty());
+        Python::with_gil(|py| {
+            for entry in &ranked {
+                let entry: &PyDict = entry.downcast(py).unwrap();
+                let mv: MovePy = entry.get_item("move").unwrap().unwrap().extract().unwrap();
+                let score = state.score_move(&mv, "neutral", None).unwrap();
+                assert_eq!(
+                    score.as_ref(py).str().unwrap().to_string(),
+                    entry.str().unwrap().to_string()
+                );
+            }
+        });
+        // not a move of the fresh deal
+        let mv = MovePy { mv: lonelybot::moves::Move::PileStack(Card::new(12, 0)) };
+        assert!(state.score_move(&mv, "neutral", None).is_err());
+    }
+
+    #[test]
+    fn analysis_is_computed_once() {
+        let state = GameState::new();
+        let runs = analysis_runs_py();
+        let first = state.analysis().clone();
+        assert_eq!(state.analysis(), &first);
+        assert_eq!(analysis_runs_py(), runs + 1);
+        // a clone shares the cached analysis
+        let _ = state.clone().analysis();
+        assert_eq!(analysis_runs_py(), runs + 1);
+    }
 }
\ No newline at end of file
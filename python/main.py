@@ -113,6 +113,11 @@ def main():
                 weights.get("aggressive_coef"),
                 weights.get("conservative_coef"),
                 weights.get("neutral_coef"),
+                weights.get("hold_empty_for_king"),
+                weights.get("deck_dig_penalty"),
+                weights.get("avoid_blocking"),
+                weights.get("run_move_bonus"),
+                weights.get("deadlock_lookahead"),
             )
             print("heuristics loaded", path)
             continue
@@ -3,14 +3,22 @@
 //! This module provides a very small set of expert inspired heuristics and
 //! facilities to rank legal moves of a game state.
 
-use crate::engine::SolitaireEngine;
+use core::cmp::Ordering;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::engine::{GameStatus, SolitaireEngine};
+use crate::formatter::ParseError;
 use crate::moves::Move;
 use crate::partial::PartialState;
 use crate::pruning::FullPruner;
-use crate::card::{Card, N_CARDS};
+use crate::card::{Card, N_SUITS};
 use crate::state::{Solitaire, ExtraInfo};
-use crate::deck::N_PILES;
-use rand::SeedableRng;
+use crate::shape::N_PILES;
+use crate::solver::{solve_with_tracking, SearchResult};
+use crate::tracking::NodeLimit;
+use crate::utils::{exp, round};
+use rand::{Rng, SeedableRng};
 use rand::rngs::SmallRng;
 use alloc::collections::BTreeSet;
 
@@ -19,30 +27,85 @@ use alloc::vec::Vec;
 
 const LONG_COLUMN_THRESHOLD: u8 = 3;
 
+/// Cards below this rank sent to the foundation get the early foundation penalty
+const EARLY_FOUNDATION_RANK: u8 = 5;
+
 /// Player style used to influence the evaluation of moves.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PlayStyle {
     Conservative,
     Neutral,
     Aggressive,
 }
 
+const PLAY_STYLES: [PlayStyle; 3] =
+    [PlayStyle::Conservative, PlayStyle::Neutral, PlayStyle::Aggressive];
+
+impl PlayStyle {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Conservative => "conservative",
+            Self::Neutral => "neutral",
+            Self::Aggressive => "aggressive",
+        }
+    }
+}
+
+impl fmt::Display for PlayStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PlayStyle {
+    type Err = ParseError;
+
+    /// Parse the lowercase name written by `Display`, ignoring ASCII case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PLAY_STYLES
+            .into_iter()
+            .find(|style| style.name().eq_ignore_ascii_case(s))
+            .ok_or(ParseError)
+    }
+}
+
 /// Weights for the different heuristics used during evaluation.
 #[derive(Clone, Debug)]
 pub struct HeuristicConfig {
     pub reveal_bonus: i32,
+    /// Added for every column freed by a move and for a king moved into an
+    /// empty column.
     pub empty_column_bonus: i32,
     pub early_foundation_penalty: i32,
     pub keep_king_bonus: i32,
     /// Penalty applied when a move leaves the engine with no mobility.
     pub deadlock_penalty: i32,
+    /// Number of forced moves, the only ones left, played after a move before
+    /// looking for a deadlock. Zero only looks at the state right after the
+    /// move.
+    pub deadlock_lookahead: u8,
     pub long_column_bonus: i32,
     pub chain_bonus: i32,
+    /// Added when a king from the deck or the foundation takes the last empty
+    /// column while a known king is buried on top of hidden cards.
+    pub hold_empty_for_king: i32,
+    /// Added for every draw needed before a deck move, see [`Move::deck_cost`].
+    pub deck_dig_penalty: i32,
+    /// Added for every card of a run kept together when moving it, see
+    /// [`run_move_score`].
+    pub run_move_bonus: i32,
     /// Style coefficients multiply the final heuristic score based on the
     /// selected play style.
     pub aggressive_coef: i32,
     pub conservative_coef: i32,
     pub neutral_coef: i32,
+    /// Fill [`RankedMove::score_breakdown`] with the contribution of every
+    /// heuristic.
+    pub explain: bool,
+    /// Rank the moves with [`RankedMove::will_block`] set after all the
+    /// others, whatever their heuristic score. Off, the moves are in raw
+    /// heuristic order.
+    pub avoid_blocking: bool,
 }
 
 impl Default for HeuristicConfig {
@@ -53,11 +116,17 @@ impl Default for HeuristicConfig {
             early_foundation_penalty: -3,
             keep_king_bonus: 1,
             deadlock_penalty: -10,
+            deadlock_lookahead: 0,
             long_column_bonus: 3,
             chain_bonus: 2,
+            hold_empty_for_king: -2,
+            deck_dig_penalty: 0,
+            run_move_bonus: 1,
             aggressive_coef: 1,
             conservative_coef: 1,
             neutral_coef: 1,
+            explain: false,
+            avoid_blocking: true,
         }
     }
 }
@@ -67,21 +136,165 @@ impl Default for HeuristicConfig {
 pub struct RankedMove {
     pub mv: Move,
     pub heuristic_score: i32,
+    /// Win rate of the playouts in per mille, see
+    /// [`simulation_score`](crate::game_theory::simulation_score)
     pub simulation_score: i32,
     pub will_block: bool,
     pub revealed_cards: Vec<Card>,
+    /// Columns emptied by the move, each worth
+    /// [`HeuristicConfig::empty_column_bonus`]
     pub columns_freed: usize,
     pub win_rate: f64,
+    /// 95% Wilson score interval around `win_rate`
+    pub win_rate_ci: (f64, f64),
+    /// See [`SolitaireEngine::is_productive`]
+    pub productive: bool,
+    /// The heuristics applied to the move with their contribution after the
+    /// style scaling, summing to `heuristic_score`. The `"probability"` entry
+    /// accounts for the weighting of the reveals and draws of unknown cards. Only
+    /// filled when [`HeuristicConfig::explain`] is set.
+    pub score_breakdown: Vec<(&'static str, i32)>,
+}
+
+/// Value used to order [`RankedMove`]s, see [`RankedMove::cmp_by`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RankKey {
+    Heuristic,
+    Simulation,
+    WinRate,
+    /// `w * heuristic_score + (1 - w) * simulation_score`
+    Blended(f64),
+}
+
+impl RankedMove {
+    fn key_value(&self, key: RankKey) -> f64 {
+        match key {
+            RankKey::Heuristic => f64::from(self.heuristic_score),
+            RankKey::Simulation => f64::from(self.simulation_score),
+            RankKey::WinRate => self.win_rate,
+            RankKey::Blended(w) => {
+                w * f64::from(self.heuristic_score) + (1.0 - w) * f64::from(self.simulation_score)
+            }
+        }
+    }
+
+    /// Compare two moves by decreasing `key`, the better move being `Less`.
+    ///
+    /// `moves.sort_by(|a, b| a.cmp_by(b, key))` puts the best move first and,
+    /// being stable, keeps the current order of the moves with equal values.
+    #[must_use]
+    pub fn cmp_by(&self, other: &Self, key: RankKey) -> Ordering {
+        other.key_value(key).total_cmp(&self.key_value(key))
+    }
+}
+
+/// Sort `moves` from the best to the worst according to `key`
+pub fn sort_ranked_moves(moves: &mut [RankedMove], key: RankKey) {
+    moves.sort_by(|a, b| a.cmp_by(b, key));
+}
+
+/// Same as [`sort_ranked_moves`] with the moves that block the game after the
+/// others, so a losing move is never ranked above a safe one
+pub fn sort_ranked_moves_avoiding_blocks(moves: &mut [RankedMove], key: RankKey) {
+    moves.sort_by(|a, b| a.will_block.cmp(&b.will_block).then(a.cmp_by(b, key)));
+}
+
+/// Pick one of `ranked` at random, the higher heuristic scores more likely
+///
+/// The scores go through a softmax at `temperature`, so a temperature of zero
+/// always picks the first move of `ranked` and a high temperature picks nearly
+/// uniformly. The moves with [`RankedMove::will_block`] set are only picked
+/// when every move blocks the game. `None` when there is no move.
+#[must_use]
+pub fn sample_move<R: Rng>(ranked: &[RankedMove], temperature: f64, rng: &mut R) -> Option<Move> {
+    let first = ranked.first()?;
+    if temperature <= 0.0 {
+        return Some(first.mv);
+    }
+    let all_block = ranked.iter().all(|m| m.will_block);
+    let candidates: Vec<&RankedMove> =
+        ranked.iter().filter(|m| all_block || !m.will_block).collect();
+    let best = candidates.iter().map(|m| m.heuristic_score).max()?;
+    let weights: Vec<f64> = candidates
+        .iter()
+        .map(|m| exp(f64::from(m.heuristic_score - best) / temperature))
+        .collect();
+    let mut r = rng.random::<f64>() * weights.iter().sum::<f64>();
+    for (m, w) in candidates.iter().zip(&weights) {
+        if r < *w {
+            return Some(m.mv);
+        }
+        r -= w;
+    }
+    // rounding left `r` past the last weight
+    candidates.last().map(|m| m.mv)
 }
 
 /// Basic information about a partial game state.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct StateAnalysis {
     pub unknown_cards: usize,
     pub remaining_cards: Vec<Card>,
     pub blocked_columns: usize,
     pub mobility: usize,
     pub deadlock_risk: f64,
+    /// Suits whose lowest known card left on the board can never be freed,
+    /// see [`blocked_suits`].
+    pub blocked_suits: Vec<u8>,
+    /// Known deck cards that could go to the foundation or on a column right
+    /// now, if they were drawn
+    pub useful_stock_cards: usize,
+    /// The moves counted by `mobility`, only kept by
+    /// [`analyze_state_with_moves`] when asked for.
+    pub sampled_moves: Option<Vec<Move>>,
+}
+
+/// Suits that can't be completed because the next card they need is stuck
+///
+/// The next card of a suit is its lowest known card left in the columns or
+/// the deck. It is stuck when a higher card of the same suit lies on it in
+/// its column, and that card can't be moved away either: it isn't a king and
+/// both cards it could be stacked on are under it in the same column. This is
+/// conservative, a suit not listed can still be deadlocked.
+#[must_use]
+pub fn blocked_suits(state: &PartialState) -> Vec<u8> {
+    let known = |c: Card| {
+        state.deck.contains(&Some(c))
+            || state
+                .columns
+                .iter()
+                .any(|col| col.visible.contains(&c) || col.hidden.contains(&Some(c)))
+    };
+
+    (0..N_SUITS)
+        .filter(|&suit| {
+            let Some(next) = Card::all_of_suit(suit).find(|&c| known(c)) else {
+                return false;
+            };
+            state.columns.iter().any(|col| {
+                // from the bottom of the column to its top
+                let cards: Vec<Option<Card>> = col
+                    .hidden
+                    .iter()
+                    .copied()
+                    .chain(col.visible.iter().map(|&c| Some(c)))
+                    .collect();
+                let Some(pos) = cards.iter().position(|&c| c == Some(next)) else {
+                    return false;
+                };
+                cards.iter().enumerate().skip(pos + 1).any(|(i, &above)| {
+                    above.is_some_and(|above| {
+                        let under = &cards[..i];
+                        let parent = above.increase_rank_swap_color();
+                        above.suit() == suit
+                            && !above.is_king()
+                            && under.contains(&Some(parent))
+                            && under.contains(&Some(parent.swap_suit()))
+                    })
+                })
+            })
+        })
+        .collect()
 }
 
 fn move_enables_chain(engine: &SolitaireEngine<FullPruner>, m: Move, col: u8) -> bool {
@@ -104,12 +317,87 @@ fn move_enables_chain(engine: &SolitaireEngine<FullPruner>, m: Move, col: u8) ->
     }
 }
 
+/// Score of a move relocating several visible cards: the run moved by a
+/// reveal, or the cards split off a run to send one of its cards to the
+/// foundation
+///
+/// A run moved off hidden cards earns [`HeuristicConfig::run_move_bonus`] for
+/// every card it keeps together, twice as much when the card it exposes can
+/// be played right away. A run moved without exposing anything or making room
+/// for a king loses as much, and so does a run broken up to send a card that
+/// reveals nothing to the foundation. Single cards are left to the other
+/// heuristics.
+#[must_use]
+pub fn run_move_score(engine: &SolitaireEngine<FullPruner>, m: Move, cfg: &HeuristicConfig) -> i32 {
+    let (Move::Reveal(c) | Move::PileStack(c)) = m else {
+        return 0;
+    };
+    let piles = engine.state().compute_visible_piles();
+    let Some((col, pos)) = piles
+        .iter()
+        .enumerate()
+        .find_map(|(i, p)| Some((i, p.iter().position(|&v| v == c)?)))
+    else {
+        return 0;
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let (run_len, above) = (piles[col].len() as i32, (piles[col].len() - pos - 1) as i32);
+    #[allow(clippy::cast_possible_truncation)]
+    let col = col as u8;
+    let exposes = engine.state().get_hidden().len(col) > 1;
+
+    match m {
+        Move::Reveal(_) if run_len > 1 => {
+            let value = if !engine.is_productive(&m) {
+                -1
+            } else if !exposes {
+                0
+            } else if move_enables_chain(engine, m, col) {
+                2
+            } else {
+                1
+            };
+            cfg.run_move_bonus * run_len * value
+        }
+        // the bottom card of a column exposes the card under it or frees it
+        Move::PileStack(_) if pos > 0 => -cfg.run_move_bonus * above,
+        _ => 0,
+    }
+}
+
+/// A known king is waiting for an empty column: either at the bottom of the
+/// visible cards of a column with hidden cards under it, or known to be hidden
+fn has_buried_king(state: &PartialState) -> bool {
+    state.columns.iter().any(|col| {
+        !col.hidden.is_empty()
+            && (col.visible.first().is_some_and(|c| c.is_king())
+                || col.hidden.iter().flatten().any(|c| c.is_king()))
+    })
+}
+
+/// Sum of the heuristic terms of a move, keeping each of them when explaining
+struct Score<'a> {
+    total: i32,
+    terms: Option<&'a mut Vec<(&'static str, i32)>>,
+}
+
+impl Score<'_> {
+    fn add(&mut self, name: &'static str, value: i32) {
+        self.total += value;
+        if let Some(terms) = &mut self.terms {
+            terms.push((name, value));
+        }
+    }
+}
+
 fn evaluate_move(
     style: PlayStyle,
     engine: &SolitaireEngine<FullPruner>,
     state: &PartialState,
     m: Move,
+    columns_freed: usize,
     cfg: &HeuristicConfig,
+    breakdown: &mut Vec<(&'static str, i32)>,
 ) -> i32 {
     let coeff = match style {
         PlayStyle::Aggressive => cfg.aggressive_coef,
@@ -118,67 +406,89 @@ fn evaluate_move(
     };
 
     let hidden = engine.state().get_hidden();
-    let has_empty = (0..N_PILES).any(|i| hidden.len(i as u8) == 0);
-    let mut score = 0;
+    let has_empty = (0..N_PILES).any(|i| hidden.len(i) == 0);
+    let mut score = Score {
+        total: 0,
+        terms: cfg.explain.then_some(breakdown),
+    };
+    // every column freed by the move, or the empty column used by a king
+    let king_fill = has_empty
+        && matches!(m, Move::Reveal(c) | Move::DeckPile(c) | Move::StackPile(c) if c.is_king());
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let empty_columns = columns_freed as i32 + i32::from(king_fill);
+    if empty_columns > 0 {
+        score.add("empty_column_bonus", cfg.empty_column_bonus * empty_columns);
+    }
     match m {
         Move::Reveal(c) => {
-            score += cfg.reveal_bonus;
+            score.add("reveal_bonus", cfg.reveal_bonus);
             let col = hidden.find(c);
             let down = hidden.len(col).saturating_sub(1);
             if down > LONG_COLUMN_THRESHOLD {
-                score += cfg.long_column_bonus;
-            }
-            if has_empty && c.is_king() {
-                score += cfg.empty_column_bonus;
+                score.add("long_column_bonus", cfg.long_column_bonus);
             }
             if move_enables_chain(engine, m, col) {
-                score += cfg.chain_bonus;
+                score.add("chain_bonus", cfg.chain_bonus);
             }
         }
         Move::PileStack(c) => {
-            if c.rank() < 5 {
-                score += cfg.early_foundation_penalty;
+            if c.rank() < EARLY_FOUNDATION_RANK {
+                score.add("early_foundation_penalty", cfg.early_foundation_penalty);
             }
             let col = hidden.find(c);
             let down = hidden.len(col).saturating_sub(1);
-            if down > 0 && down > LONG_COLUMN_THRESHOLD {
-                score += cfg.long_column_bonus;
+            if down > LONG_COLUMN_THRESHOLD {
+                score.add("long_column_bonus", cfg.long_column_bonus);
             }
             if move_enables_chain(engine, m, col) {
-                score += cfg.chain_bonus;
+                score.add("chain_bonus", cfg.chain_bonus);
             }
         }
         Move::DeckPile(c) | Move::StackPile(c) => {
-            if c.is_king() && has_empty {
-                score += cfg.empty_column_bonus;
-            }
             if c.is_king() && hidden.len(6) == 0 {
-                score += cfg.keep_king_bonus;
+                score.add("keep_king_bonus", cfg.keep_king_bonus);
+            }
+            let n_empty = (0..N_PILES).filter(|&i| hidden.len(i) == 0).count();
+            if c.is_king() && n_empty == 1 && has_buried_king(state) {
+                score.add("hold_empty_for_king", cfg.hold_empty_for_king);
             }
         }
         _ => {}
     }
 
-    // Penalize moves that immediately lead to no available follow-up moves.
-    // This prevents ranking moves highly if they would dead-end the game state.
+    let run = run_move_score(engine, m, cfg);
+    if run != 0 {
+        score.add("run_move_bonus", run);
+    }
+
+    if let Move::DeckPile(c) | Move::DeckStack(c) = m {
+        let draws = engine.state().get_deck().draws_to(c).unwrap_or(0);
+        if draws > 0 {
+            score.add("deck_dig_penalty", cfg.deck_dig_penalty * i32::from(draws));
+        }
+    }
+
+    // Penalize moves that lead to no available follow-up moves, within the
+    // lookahead. This prevents ranking moves highly if they would dead-end the
+    // game state.
     let mut next: SolitaireEngine<FullPruner> = engine.state().clone().into();
-    if next.do_move(m) && next.list_moves_dom().is_empty() {
-        score += cfg.deadlock_penalty;
+    if next.do_move(m) && is_dead_end(next, cfg.deadlock_lookahead) {
+        score.add("deadlock_penalty", cfg.deadlock_penalty);
     }
 
     // Bonus/penalité par style
-    score += match style {
-        PlayStyle::Aggressive => 1,
-        PlayStyle::Conservative => -1,
-        PlayStyle::Neutral => 0,
-    };
+    match style {
+        PlayStyle::Aggressive => score.add("style_bonus", 1),
+        PlayStyle::Conservative => score.add("style_bonus", -1),
+        PlayStyle::Neutral => {}
+    }
 
     // Poids de probabilité
     let probabilities = state.column_probabilities();
     let prob = match m {
         Move::Reveal(c) => {
             let idx = hidden.find(c) as usize;
-            if state.columns[idx].hidden.iter().any(|h| *h == Some(c)) {
+            if state.columns[idx].hidden.contains(&Some(c)) {
                 1.0
             } else {
                 probabilities
@@ -191,14 +501,36 @@ fn evaluate_move(
                     .unwrap_or(0.0)
             }
         }
+        Move::DeckPile(c) | Move::DeckStack(c) if state.is_deck_card_locked(c) => 0.0,
+        Move::DeckPile(c) | Move::DeckStack(c) => state.deck_card_probability(c),
         _ => 1.0,
     };
 
+    let scaled = score.total * coeff;
+    let res = round(f64::from(scaled) * prob);
+    if let Some(terms) = score.terms {
+        for term in terms.iter_mut() {
+            term.1 *= coeff;
+        }
+        if res != scaled {
+            terms.push(("probability", res - scaled));
+        }
+    }
+    res
+}
 
-    // round() may not be available in core for no_std; emulate simple rounding
-
-    let scaled = score * coeff;
-    ((scaled as f64) * prob + 0.5) as i32
+/// Whether `engine` gets stuck short of a win, playing its only move while
+/// there is a single one for at most `depth` moves
+fn is_dead_end(mut engine: SolitaireEngine<FullPruner>, depth: u8) -> bool {
+    for _ in 0..depth {
+        match engine.list_moves_dom()[..] {
+            [m] => {
+                engine.do_move(m);
+            }
+            _ => break,
+        }
+    }
+    engine.status() == GameStatus::Lost
 }
 
 fn count_empty_columns(game: &Solitaire) -> usize {
@@ -221,62 +553,278 @@ pub fn ranked_moves(
     style: PlayStyle,
     cfg: &HeuristicConfig,
 ) -> Vec<RankedMove> {
-    let moves = engine.list_moves_dom();
+    ranked_moves_probed(engine, state, style, cfg, &mut ())
+}
+
+/// Same as [`ranked_moves`], adding the time spent in each phase to `timings`
+#[cfg(feature = "std")]
+#[must_use]
+pub fn ranked_moves_timed(
+    engine: &SolitaireEngine<FullPruner>,
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    timings: &mut RankTimings,
+) -> Vec<RankedMove> {
+    ranked_moves_probed(engine, state, style, cfg, timings)
+}
+
+/// Measure the phases of [`ranked_moves`], the unit probe records nothing
+trait RankProbe {
+    fn enumerate<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    fn heuristic<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+}
+
+impl RankProbe for () {}
+
+/// Time spent by [`ranked_moves_timed`], accumulated over the calls
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RankTimings {
+    /// listing the moves, playing them and checking for follow-up moves
+    pub enumeration: std::time::Duration,
+    /// evaluating the heuristics
+    pub heuristic: std::time::Duration,
+    pub n_moves: usize,
+}
+
+#[cfg(feature = "std")]
+impl RankTimings {
+    #[must_use]
+    pub fn total(&self) -> std::time::Duration {
+        self.enumeration + self.heuristic
+    }
+}
+
+#[cfg(feature = "std")]
+impl RankProbe for RankTimings {
+    fn enumerate<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = std::time::Instant::now();
+        let res = f();
+        self.enumeration += start.elapsed();
+        res
+    }
+
+    fn heuristic<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = std::time::Instant::now();
+        let res = f();
+        self.heuristic += start.elapsed();
+        self.n_moves += 1;
+        res
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for RankTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} moves in {:?} (enumeration {:?}, heuristic {:?})",
+            self.n_moves,
+            self.total(),
+            self.enumeration,
+            self.heuristic
+        )
+    }
+}
+
+fn rank_move<P: RankProbe>(
+    engine: &SolitaireEngine<FullPruner>,
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    m: Move,
+    base_empty: usize,
+    probe: &mut P,
+) -> RankedMove {
+    let mut st = engine.state().clone();
+    let (_, (_, extra)) = probe.enumerate(|| st.do_move(m));
+    let columns_freed = count_empty_columns(&st).saturating_sub(base_empty);
+
+    let revealed_cards = match extra {
+        ExtraInfo::Card(c) => alloc::vec![c],
+        _ => Vec::new(),
+    };
+
+    let mut score_breakdown = Vec::new();
+    let heuristic_score = probe
+        .heuristic(|| evaluate_move(style, engine, state, m, columns_freed, cfg, &mut score_breakdown));
+
+    // Determine if this move leaves the game with no legal follow-up
+    // moves short of a win. This information is surfaced so clients
+    // can avoid moves that dead-end the game state.
+    let will_block = probe.enumerate(|| {
+        let tmp_engine: SolitaireEngine<FullPruner> = st.clone().into();
+        !st.is_win() && tmp_engine.list_moves_dom().is_empty()
+    });
+
+    RankedMove {
+        mv: m,
+        heuristic_score,
+        simulation_score: 0,
+        will_block,
+        revealed_cards,
+        columns_freed,
+        win_rate: 0.0,
+        win_rate_ci: (0.0, 0.0),
+        productive: engine.is_productive(&m),
+        score_breakdown,
+    }
+}
+
+fn ranked_moves_probed<P: RankProbe>(
+    engine: &SolitaireEngine<FullPruner>,
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    probe: &mut P,
+) -> Vec<RankedMove> {
+    let moves = probe.enumerate(|| engine.list_moves_dom());
     let base_empty = count_empty_columns(engine.state());
     let mut res: Vec<RankedMove> = moves
         .iter()
-        .map(|&m| {
-            let mut st = engine.state().clone();
-            let (_, (_, extra)) = st.do_move(m);
-            let columns_freed = count_empty_columns(&st).saturating_sub(base_empty);
-
-            let revealed_cards = match extra {
-                ExtraInfo::Card(c) => alloc::vec![c],
-                _ => Vec::new(),
-            };
-
-            let heuristic_score = evaluate_move(style, engine, state, m, cfg);
-
-            // Determine if this move leaves the game with no legal follow-up
-            // moves. This information is surfaced so clients can avoid moves
-            // that dead-end the game state.
-            let tmp_engine: SolitaireEngine<FullPruner> = st.clone().into();
-            let will_block = tmp_engine.list_moves_dom().is_empty();
-
-            RankedMove {
-                mv: m,
-                heuristic_score,
-                simulation_score: 0,
-                will_block,
-                revealed_cards,
-                columns_freed,
-                win_rate: 0.0,
-            }
-        })
+        .map(|&m| rank_move(engine, state, style, cfg, m, base_empty, probe))
         .collect();
-    res.sort_by_key(|m| -m.heuristic_score);
+    if cfg.avoid_blocking {
+        sort_ranked_moves_avoiding_blocks(&mut res, RankKey::Heuristic);
+    } else {
+        sort_ranked_moves(&mut res, RankKey::Heuristic);
+    }
     res
 }
 
 /// Convenience wrapper that builds the engine from a partial state using
 /// weighted probabilities.
+///
+/// The unknown cards are filled with a fixed seed so the result is
+/// deterministic, see [`ranked_moves_from_partial_with_rng`] to sample them
+/// differently.
 #[must_use]
 pub fn ranked_moves_from_partial(
     state: &PartialState,
     style: PlayStyle,
     cfg: &HeuristicConfig,
 ) -> Vec<RankedMove> {
-    let probs = state.column_probabilities();
     let mut rng = SmallRng::seed_from_u64(0);
-    let filled = state.fill_unknowns_weighted(&probs, &mut rng);
+    ranked_moves_from_partial_with_rng(state, style, cfg, &mut rng)
+}
+
+/// Fill the unknown cards of `state` from `column_probabilities` using `rng`
+/// and rank the moves of the resulting game with [`ranked_moves`], so the
+/// probability weighting and sorting are the same.
+///
+/// A state whose unknown cards can't be filled (see
+/// [`FillError`](crate::partial::FillError)) has no moves.
+#[must_use]
+pub fn ranked_moves_from_partial_with_rng<R: Rng>(
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    rng: &mut R,
+) -> Vec<RankedMove> {
+    let probs = state.column_probabilities();
+    let Ok(filled) = state.fill_unknowns_weighted(&probs, rng) else {
+        return Vec::new();
+    };
     let solitaire: Solitaire = (&filled).into();
     let engine: SolitaireEngine<FullPruner> = solitaire.into();
     ranked_moves(&engine, state, style, cfg)
 }
 
+/// The entry of `m` in [`ranked_moves`], without ranking the other moves.
+/// `None` when `m` is not one of them.
+#[must_use]
+pub fn score_move(
+    engine: &SolitaireEngine<FullPruner>,
+    state: &PartialState,
+    m: Move,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+) -> Option<RankedMove> {
+    if !engine.list_moves_dom().contains(&m) {
+        return None;
+    }
+    let base_empty = count_empty_columns(engine.state());
+    Some(rank_move(engine, state, style, cfg, m, base_empty, &mut ()))
+}
+
+/// The entry of `m` in [`ranked_moves_from_partial`], the unknown cards
+/// being filled the same way.
+#[must_use]
+pub fn score_move_from_partial(
+    state: &PartialState,
+    m: Move,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+) -> Option<RankedMove> {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let probs = state.column_probabilities();
+    let filled = state.fill_unknowns_weighted(&probs, &mut rng).ok()?;
+    let solitaire: Solitaire = (&filled).into();
+    score_move(&solitaire.into(), state, m, style, cfg)
+}
+
+/// Number of analyses kept by an [`AnalysisSession`].
+pub const SESSION_CACHE_SIZE: usize = 16;
+
+/// Cache of the most recent [`analyze_state`] results, for front-ends that
+/// analyze the position after every move and often come back to it.
+#[derive(Default)]
+pub struct AnalysisSession {
+    // most recently used last
+    recent: Vec<(PartialState, StateAnalysis)>,
+    hits: usize,
+}
+
+impl AnalysisSession {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as [`analyze_state`], reusing the result of a recent call on an
+    /// equal state.
+    pub fn analyze(&mut self, state: &PartialState) -> StateAnalysis {
+        if let Some(pos) = self.recent.iter().position(|(s, _)| s == state) {
+            self.hits += 1;
+            let entry = self.recent.remove(pos);
+            self.recent.push(entry);
+        } else {
+            if self.recent.len() >= SESSION_CACHE_SIZE {
+                self.recent.remove(0);
+            }
+            self.recent.push((state.clone(), analyze_state(state)));
+        }
+        self.recent.last().unwrap().1.clone()
+    }
+
+    /// Number of analyses answered from the cache.
+    #[must_use]
+    pub const fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn clear(&mut self) {
+        self.recent.clear();
+        self.hits = 0;
+    }
+}
+
 /// Analyze a partial state and return basic metrics.
 #[must_use]
 pub fn analyze_state(state: &PartialState) -> StateAnalysis {
+    analyze_state_with_moves(state, false)
+}
+
+/// Same as [`analyze_state`], also returning the moves of the sampled board
+/// used to compute the mobility when `keep_moves` is set.
+#[must_use]
+pub fn analyze_state_with_moves(state: &PartialState, keep_moves: bool) -> StateAnalysis {
     let mut used = BTreeSet::new();
     let mut unknown = 0usize;
     for col in &state.columns {
@@ -300,42 +848,39 @@ pub fn analyze_state(state: &PartialState) -> StateAnalysis {
             None => unknown += 1,
         }
     }
-    let remaining_cards: Vec<Card> = (0..N_CARDS)
-        .filter(|i| !used.contains(i))
-        .map(Card::from_mask_index)
+    for card in state.foundation_cards() {
+        used.insert(card.mask_index());
+    }
+    let remaining_cards: Vec<Card> = Card::all()
+        .filter(|c| !used.contains(&c.mask_index()))
         .collect();
 
     let mut rng = SmallRng::seed_from_u64(0);
-    let filled = state.fill_unknowns_randomly(&mut rng);
-    let solitaire: crate::state::Solitaire = (&filled).into();
-    let engine: SolitaireEngine<FullPruner> = solitaire.into();
-    let mobility = engine.list_moves_dom().len();
+    // an inconsistent state is analyzed as if no move could be played
+    let moves = state.fill_unknowns_randomly(&mut rng).map_or_else(
+        |_| Vec::new(),
+        |filled| {
+            let solitaire: crate::state::Solitaire = (&filled).into();
+            let engine: SolitaireEngine<FullPruner> = solitaire.into();
+            engine.list_moves_dom().to_vec()
+        },
+    );
+    let mobility = moves.len();
+    let sampled_moves = keep_moves.then_some(moves);
 
     let mut blocked = 0usize;
     for (i, col) in state.columns.iter().enumerate() {
-        let top = col.visible.last().copied();
-        if top.is_none() {
+        let Some(top) = col.top_visible() else {
             if !col.hidden.is_empty() {
                 blocked += 1;
             }
             continue;
-        }
-        let top = top.unwrap();
-        let mut movable = false;
-        for (j, other) in state.columns.iter().enumerate() {
-            if i == j {
-                continue;
-            }
-            let dest = other.visible.last().copied();
-            if top.go_after(dest) {
-                movable = true;
-                break;
-            }
-            if dest.is_none() && top.is_king() {
-                movable = true;
-                break;
-            }
-        }
+        };
+        let movable = state
+            .columns
+            .iter()
+            .enumerate()
+            .any(|(j, other)| i != j && other.accepts(top));
         if !movable {
             blocked += 1;
         }
@@ -346,11 +891,54 @@ pub fn analyze_state(state: &PartialState) -> StateAnalysis {
         blocked as f64 / state.columns.len() as f64
     };
 
+    let useful_stock_cards = state
+        .deck
+        .iter()
+        .flatten()
+        .filter(|&&c| state.foundations.can_accept(c) || state.columns.iter().any(|col| col.accepts(c)))
+        .count();
+
     StateAnalysis {
         unknown_cards: unknown,
         remaining_cards,
         blocked_columns: blocked,
         mobility,
         deadlock_risk,
+        blocked_suits: blocked_suits(state),
+        useful_stock_cards,
+        sampled_moves,
+    }
+}
+
+/// Chance that `state` can't be won, measured by solving `samples`
+/// completions of its unknown cards (see [`PartialState::sample_completions`])
+///
+/// Each search stops after `node_limit` states, a completion not solved by
+/// then counts as lost, so a small limit overestimates the risk. A completion
+/// that can't be filled (see [`FillError`](crate::partial::FillError)) counts
+/// as lost too. Much slower than the [`StateAnalysis::deadlock_risk`]
+/// heuristic but an actual probability. Zero samples give a risk of zero.
+#[must_use]
+pub fn deadlock_risk_calibrated<R: Rng>(
+    state: &PartialState,
+    samples: usize,
+    node_limit: usize,
+    rng: &mut R,
+) -> f64 {
+    if samples == 0 {
+        return 0.0;
     }
+    let solved = state
+        .sample_completions(rng)
+        .take(samples)
+        .filter(|filled| {
+            let Ok(filled) = filled else {
+                return false;
+            };
+            let mut game: Solitaire = filled.into();
+            let limit = NodeLimit::new(node_limit);
+            solve_with_tracking(&mut game, &limit, &limit).0 == SearchResult::Solved
+        })
+        .count();
+    1.0 - solved as f64 / samples as f64
 }
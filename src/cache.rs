@@ -0,0 +1,314 @@
+//! Memoization of per-state values, e.g. for agents caching a learned value
+//! of the states they visit, and opening books of precomputed moves.
+
+use hashbrown::HashMap;
+
+use crate::{
+    card::{Card, N_CARDS},
+    moves::Move,
+    partial::PartialState,
+    state::{Encode, Solitaire},
+    utils::MixHasherBuilder,
+};
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+/// A map from game states to values keyed by [`Solitaire::encode`]
+///
+/// States with the same encoding share their entry, so the hidden cards
+/// order of the piles doesn't split the cache.
+pub struct StateValueCache<V> {
+    map: HashMap<Encode, V, MixHasherBuilder>,
+}
+
+impl<V> Default for StateValueCache<V> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::default(),
+        }
+    }
+}
+
+impl<V> StateValueCache<V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn get(&self, game: &Solitaire) -> Option<&V> {
+        self.get_encoded(game.encode_cached())
+    }
+
+    #[must_use]
+    pub fn get_encoded(&self, encode: Encode) -> Option<&V> {
+        self.map.get(&encode)
+    }
+
+    /// Return the previous value of the state if any
+    pub fn insert(&mut self, game: &Solitaire, value: V) -> Option<V> {
+        self.insert_encoded(game.encode_cached(), value)
+    }
+
+    pub fn insert_encoded(&mut self, encode: Encode, value: V) -> Option<V> {
+        self.map.insert(encode, value)
+    }
+
+    /// Return the cached value of the state, computing it with `f` when missing
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, game: &Solitaire, f: F) -> &V {
+        self.map.entry(game.encode_cached()).or_insert_with(f)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Every cached encoding with its value, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (Encode, &V)> + '_ {
+        self.map.iter().map(|(&k, v)| (k, v))
+    }
+}
+
+/// Error returned by [`OpeningBook::from_bytes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookError {
+    /// The bytes don't hold a whole number of entries.
+    Truncated,
+    /// An entry holds an invalid move kind or card.
+    InvalidMove(u8, u8),
+}
+
+/// Bytes of an entry: the encoding in little endian, the move kind and card
+const BOOK_ENTRY_BYTES: usize = 10;
+
+/// Moves to play in given states, consulted before any search
+///
+/// See [`best_move_mcts`](crate::game_theory::best_move_mcts). The states are
+/// keyed by what the player sees, see [`OpeningBook::key`].
+#[derive(Default)]
+pub struct OpeningBook {
+    moves: StateValueCache<Move>,
+}
+
+impl OpeningBook {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Key of `state`, a hash of the bytes of its [`PartialState::canonical`]
+    /// form, so the order of the columns doesn't split the book
+    #[must_use]
+    pub fn key(state: &PartialState) -> Encode {
+        // 64 bits FNV-1a
+        let fnv = |h: Encode, &b: &u8| (h ^ Encode::from(b)).wrapping_mul(0x100_0000_01b3);
+        state.canonical().to_bytes().iter().fold(0xcbf2_9ce4_8422_2325, fnv)
+    }
+
+    /// Return the previously booked move of the state if any
+    pub fn insert(&mut self, state: &PartialState, m: Move) -> Option<Move> {
+        self.insert_encoded(Self::key(state), m)
+    }
+
+    pub fn insert_encoded(&mut self, encode: Encode, m: Move) -> Option<Move> {
+        self.moves.insert_encoded(encode, m)
+    }
+
+    #[must_use]
+    pub fn lookup(&self, state: &PartialState) -> Option<Move> {
+        self.lookup_encoded(Self::key(state))
+    }
+
+    #[must_use]
+    pub fn lookup_encoded(&self, encode: Encode) -> Option<Move> {
+        self.moves.get_encoded(encode).copied()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Binary encoding, sorted by state: for every entry the encoding in
+    /// little endian, the move kind (`DS`, `PS`, `DP`, `SP`, `R`) and the mask
+    /// index of the card
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<(Encode, Move)> = self.moves.iter().map(|(k, &m)| (k, m)).collect();
+        entries.sort_unstable_by_key(|e| e.0);
+
+        let mut res = Vec::with_capacity(entries.len() * BOOK_ENTRY_BYTES);
+        for (encode, m) in entries {
+            let (kind, card) = match m {
+                Move::DeckStack(c) => (0, c),
+                Move::PileStack(c) => (1, c),
+                Move::DeckPile(c) => (2, c),
+                Move::StackPile(c) => (3, c),
+                Move::Reveal(c) => (4, c),
+            };
+            res.extend_from_slice(&encode.to_le_bytes());
+            res.extend_from_slice(&[kind, card.mask_index()]);
+        }
+        res
+    }
+
+    /// Decode the bytes written by [`Self::to_bytes`]
+    ///
+    /// # Errors
+    ///
+    /// Return a `BookError` when the bytes are cut in the middle of an entry
+    /// or hold an invalid move
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if !bytes.len().is_multiple_of(BOOK_ENTRY_BYTES) {
+            return Err(BookError::Truncated);
+        }
+        let mut book = Self::new();
+        for entry in bytes.chunks_exact(BOOK_ENTRY_BYTES) {
+            let (encode, m) = entry.split_at(8);
+            let mut key = [0; 8];
+            key.copy_from_slice(encode);
+            let (kind, card) = (m[0], m[1]);
+            if card >= N_CARDS {
+                return Err(BookError::InvalidMove(kind, card));
+            }
+            let c = Card::from_mask_index(card);
+            let m = match kind {
+                0 => Move::DeckStack(c),
+                1 => Move::PileStack(c),
+                2 => Move::DeckPile(c),
+                3 => Move::StackPile(c),
+                4 => Move::Reveal(c),
+                _ => return Err(BookError::InvalidMove(kind, card)),
+            };
+            book.insert_encoded(Encode::from_le_bytes(key), m);
+        }
+        Ok(book)
+    }
+
+    /// Read a book written by [`Self::save`]
+    ///
+    /// # Errors
+    ///
+    /// Return the IO error, or `InvalidData` when the file isn't a book
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, alloc::format!("{e:?}"))
+        })
+    }
+
+    /// Write the book in the format of [`Self::to_bytes`]
+    ///
+    /// # Errors
+    ///
+    /// Return the IO error
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+    use crate::shuffler::default_shuffle;
+
+    #[test]
+    fn test_cache_by_encode() {
+        let mut game = Solitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+        let mut cache = StateValueCache::new();
+        assert!(cache.get(&game).is_none());
+
+        assert_eq!(cache.insert(&game, 0.25), None);
+        assert_eq!(cache.get(&game), Some(&0.25));
+        assert_eq!(cache.get_encoded(game.encode()), Some(&0.25));
+
+        // a differently ordered hidden layout has the same key
+        let mut shuffled = game.clone();
+        shuffled.hidden_shuffle(&mut SmallRng::seed_from_u64(1));
+        assert_eq!(cache.get(&shuffled), Some(&0.25));
+
+        let m = game.gen_moves::<true>().to_vec::<8>()[0];
+        game.do_move(m);
+        assert!(cache.get(&game).is_none());
+        assert_eq!(*cache.get_or_insert_with(&game, || 0.5), 0.5);
+        assert_eq!(*cache.get_or_insert_with(&game, || 1.0), 0.5);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_opening_book_bytes() {
+        let mut game = Solitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+        let mut book = OpeningBook::new();
+        for _ in 0..10 {
+            let moves = game.gen_moves::<true>().to_vec::<64>();
+            let Some(&m) = moves.last() else {
+                break;
+            };
+            let state = PartialState::from_blind(&game);
+            assert_eq!(book.insert(&state, m), None);
+            assert_eq!(book.lookup(&state), Some(m));
+            game.do_move(m);
+        }
+        assert!(book.lookup(&PartialState::from_blind(&game)).is_none());
+
+        // the same state with its columns in another order
+        let fresh = Solitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+        let mut state = PartialState::from_blind(&fresh);
+        let booked = book.lookup(&state);
+        assert!(booked.is_some());
+        state.columns.swap(0, 6);
+        assert_eq!(book.lookup(&state), booked);
+
+        let bytes = book.to_bytes();
+        assert_eq!(bytes.len(), book.len() * BOOK_ENTRY_BYTES);
+        let decoded = OpeningBook::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        assert!(matches!(OpeningBook::from_bytes(&bytes[1..]), Err(BookError::Truncated)));
+        let mut invalid = bytes;
+        invalid[8] = 5;
+        assert!(matches!(OpeningBook::from_bytes(&invalid), Err(BookError::InvalidMove(5, _))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_opening_book_file() {
+        let game = Solitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+        let m = game.gen_moves::<true>().to_vec::<64>()[0];
+        let state = PartialState::from_blind(&game);
+        let mut book = OpeningBook::new();
+        book.insert(&state, m);
+
+        let path = std::env::temp_dir().join("lonelybot_test_opening_book.bin");
+        book.save(&path).unwrap();
+        let loaded = OpeningBook::load(&path).unwrap();
+        assert_eq!(loaded.lookup(&state), Some(m));
+
+        std::fs::write(&path, [0; 3]).unwrap();
+        let err = OpeningBook::load(&path).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
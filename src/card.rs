@@ -1,6 +1,7 @@
 pub const N_SUITS: u8 = 4;
 pub const N_RANKS: u8 = 13;
 pub const N_CARDS: u8 = N_SUITS * N_RANKS;
+pub const ACE_RANK: u8 = 0;
 pub const KING_RANK: u8 = N_RANKS - 1;
 
 pub(crate) const SUIT_MASK: [u64; N_SUITS as usize] = [
@@ -36,6 +37,16 @@ impl Card {
         Self(suit_xor_color(value))
     }
 
+    /// Every card, by increasing rank
+    pub fn all() -> impl Iterator<Item = Self> {
+        (0..N_CARDS).map(Self::from_mask_index)
+    }
+
+    /// The cards of `suit` from the ace to the king
+    pub fn all_of_suit(suit: u8) -> impl Iterator<Item = Self> {
+        (ACE_RANK..=KING_RANK).map(move |rank| Self::new(rank, suit))
+    }
+
     #[must_use]
     pub const fn rank(self) -> u8 {
         self.0 / N_SUITS
@@ -79,6 +90,16 @@ impl Card {
         Self(self.0.saturating_sub(N_SUITS))
     }
 
+    /// The card one rank higher of the same suit, `None` for a king
+    #[must_use]
+    pub const fn next_in_suit(self) -> Option<Self> {
+        if self.is_king() {
+            None
+        } else {
+            Some(Self::new(self.rank() + 1, self.suit()))
+        }
+    }
+
     #[must_use]
     pub const fn go_after(self, other: Option<Self>) -> bool {
         if let Some(other) = other {
@@ -116,3 +137,55 @@ impl Card {
         }
     }
 }
+
+impl PartialOrd for Card {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Cards are ordered by suit then by rank, following the foundations
+impl Ord for Card {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.suit(), self.rank()).cmp(&(other.suit(), other.rank()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_cards() {
+        assert_eq!(Card::all().count(), N_CARDS as usize);
+        let mask = Card::all().fold(0u64, |mask, c| {
+            assert_eq!(mask & c.mask(), 0);
+            mask | c.mask()
+        });
+        assert_eq!(mask.count_ones(), u32::from(N_CARDS));
+        assert!(Card::all().zip(Card::all().skip(1)).all(|(a, b)| a.rank() <= b.rank()));
+
+        for suit in 0..N_SUITS {
+            let cards: Vec<Card> = Card::all_of_suit(suit).collect();
+            assert_eq!(cards.len(), N_RANKS as usize);
+            assert_eq!(cards[0], Card::new(ACE_RANK, suit));
+            assert!(cards.last().unwrap().is_king());
+            assert!(cards.iter().all(|c| c.suit() == suit));
+        }
+    }
+
+    #[test]
+    fn test_next_in_suit() {
+        assert_eq!(Card::new(ACE_RANK, 0).next_in_suit(), Some(Card::new(1, 0)));
+        assert_eq!(Card::new(KING_RANK, 0).next_in_suit(), None);
+        for suit in 0..N_SUITS {
+            let cards: Vec<Card> = Card::all_of_suit(suit).collect();
+            assert!(cards.windows(2).all(|w| w[0].next_in_suit() == Some(w[1]) && w[0] < w[1]));
+        }
+
+        let mut sorted: Vec<Card> = Card::all().collect();
+        sorted.sort();
+        let by_suit: Vec<Card> = (0..N_SUITS).flat_map(Card::all_of_suit).collect();
+        assert_eq!(sorted, by_suit);
+    }
+}
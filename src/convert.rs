@@ -1,6 +1,8 @@
 use crate::{
-    moves::Move,
+    card::Card,
+    moves::{Move, MoveMask},
     standard::{InvalidMove, MoveResult, Pos, StandardHistoryVec, StandardMove, StandardSolitaire},
+    state::Solitaire,
 };
 
 /// # Errors
@@ -10,11 +12,43 @@ pub(crate) fn convert_move(
     game: &StandardSolitaire,
     m: Move,
     move_seq: &mut StandardHistoryVec,
+) -> MoveResult<()> {
+    convert_move_with(game, m, None, move_seq)
+}
+
+/// Convert `m` to standard moves putting the moved cards on the pile `dest`
+///
+/// A `Move` doesn't say where the cards go: the engine doesn't distinguish the
+/// two piles with a card of the same rank and color, and `convert_moves` uses
+/// the first one (see [`StandardSolitaire::free_piles`] for the choices).
+/// For `Move::PileStack`, `dest` is where the cards on top of the card go.
+/// # Errors
+///
+/// Return `InvalidMove` when the move is not valid or the cards can't go to `dest`
+pub fn convert_move_to(game: &StandardSolitaire, m: Move, dest: u8) -> MoveResult<StandardHistoryVec> {
+    let mut move_seq = StandardHistoryVec::new();
+    convert_move_with(game, m, Some(dest), &mut move_seq)?;
+    Ok(move_seq)
+}
+
+fn free_pile(game: &StandardSolitaire, card: Card, dest: Option<u8>) -> MoveResult<u8> {
+    match dest {
+        None => game.find_free_pile(card),
+        Some(dest) => game.free_piles(card).find(|&p| p == dest),
+    }
+    .ok_or(InvalidMove {})
+}
+
+fn convert_move_with(
+    game: &StandardSolitaire,
+    m: Move,
+    dest: Option<u8>,
+    move_seq: &mut StandardHistoryVec,
 ) -> MoveResult<()> {
     match m {
         Move::DeckPile(c) => {
             let cnt = game.find_deck_card(c).ok_or(InvalidMove {})?;
-            let pile = game.find_free_pile(c).ok_or(InvalidMove {})?;
+            let pile = free_pile(game, c, dest)?;
             for _ in 0..cnt {
                 move_seq.push(StandardMove::DRAW_NEXT);
             }
@@ -22,7 +56,7 @@ pub(crate) fn convert_move(
             move_seq.push(StandardMove::new(Pos::Deck, Pos::Pile(pile), c));
         }
         Move::DeckStack(c) => {
-            if c.rank() != game.get_stack().get(c.suit()) {
+            if !game.get_stack().can_accept(c) {
                 return Err(InvalidMove {});
             }
 
@@ -34,15 +68,15 @@ pub(crate) fn convert_move(
             move_seq.push(StandardMove::new(Pos::Deck, Pos::Stack(c.suit()), c));
         }
         Move::StackPile(c) => {
-            if c.rank() + 1 != game.get_stack().get(c.suit()) {
+            if game.get_stack().top(c.suit()) != Some(c) {
                 return Err(InvalidMove {});
             }
-            let pile = game.find_free_pile(c).ok_or(InvalidMove {})?;
+            let pile = free_pile(game, c, dest)?;
             move_seq.push(StandardMove::new(Pos::Stack(c.suit()), Pos::Pile(pile), c));
         }
         Move::Reveal(c) => {
             let pile_from = game.find_top_card(c).ok_or(InvalidMove {})?;
-            let pile_to = game.find_free_pile(c).ok_or(InvalidMove {})?;
+            let pile_to = free_pile(game, c, dest)?;
 
             if pile_to == pile_from {
                 return Err(InvalidMove {});
@@ -55,12 +89,12 @@ pub(crate) fn convert_move(
             ));
         }
         Move::PileStack(c) => {
-            if c.rank() != game.get_stack().get(c.suit()) {
+            if !game.get_stack().can_accept(c) {
                 return Err(InvalidMove {});
             }
             let (pile, cards) = game.find_card(c).ok_or(InvalidMove {})?;
             if let Some(&move_card) = cards.get(1) {
-                let pile_other = game.find_free_pile(move_card).ok_or(InvalidMove {})?;
+                let pile_other = free_pile(game, move_card, dest)?;
 
                 if pile == pile_other {
                     return Err(InvalidMove {});
@@ -99,6 +133,61 @@ pub fn convert_moves(game: &mut StandardSolitaire, m: &[Move]) -> MoveResult<Sta
     Ok(move_seq)
 }
 
+/// Recognize the move played by a sequence of standard moves, i.e. the
+/// inverse of `convert_move`
+///
+/// The placement can be preceded by any number of draws.
+/// Return None when the sequence isn't valid from `game` or doesn't amount to a single `Move`
+#[must_use]
+pub fn recognize_move(game: &StandardSolitaire, seq: &[StandardMove]) -> Option<Move> {
+    let mut after_draws = game.clone();
+    let n_draws = seq
+        .iter()
+        .take_while(|m| **m == StandardMove::DRAW_NEXT)
+        .count();
+    for m in &seq[..n_draws] {
+        after_draws.do_move(m).ok()?;
+    }
+    let placement = &seq[n_draws..];
+
+    let m = match placement {
+        [m] => match (m.from, m.to) {
+            (Pos::Deck, Pos::Pile(_)) => Move::DeckPile(m.card),
+            (Pos::Deck, Pos::Stack(_)) => Move::DeckStack(m.card),
+            (Pos::Stack(_), Pos::Pile(_)) => Move::StackPile(m.card),
+            (Pos::Pile(_), Pos::Stack(_)) => Move::PileStack(m.card),
+            (Pos::Pile(from), Pos::Pile(_))
+                if after_draws.get_piles()[usize::from(from)].first() == Some(&m.card) =>
+            {
+                Move::Reveal(m.card)
+            }
+            _ => return None,
+        },
+        // moving the cards on top away to put the card to the stack
+        [above, m] => match (above.from, above.to, m.from, m.to) {
+            (Pos::Pile(from), Pos::Pile(_), Pos::Pile(from_2), Pos::Stack(_))
+                if from == from_2
+                    && after_draws
+                        .find_card(m.card)
+                        .is_some_and(|(pile, cards)| {
+                            pile == from && cards.get(1) == Some(&above.card)
+                        }) =>
+            {
+                Move::PileStack(m.card)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    for m in placement {
+        after_draws.do_move(m).ok()?;
+    }
+
+    let valid = Solitaire::from(game).gen_moves::<false>();
+    MoveMask::from(m).filter(&valid).is_empty().then_some(m)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -162,4 +251,116 @@ mod tests {
             do_test_convert(seed);
         }
     }
+
+    #[test]
+    fn test_random_play_consistency() {
+        use rand::{rngs::SmallRng, seq::IndexedRandom, SeedableRng};
+
+        use crate::moves::N_MOVES_MAX;
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        for draw_step in [1, 3] {
+            let draw_step = NonZeroU8::new(draw_step).unwrap();
+            for seed in 0..50 {
+                let cards = default_shuffle(seed);
+                let mut game = Solitaire::new(&cards, draw_step);
+                let mut std_game = StandardSolitaire::new(&cards, draw_step);
+                for _ in 0..300 {
+                    let moves = game.gen_moves::<false>().to_vec::<N_MOVES_MAX>();
+                    let Some(&m) = moves.choose(&mut rng) else {
+                        break;
+                    };
+                    game.do_move(m);
+                    assert!(convert_moves(&mut std_game, &[m]).is_ok(), "{seed} {m}");
+                    assert!(game.equivalent_to(&From::from(&std_game)), "{seed} {m}");
+                    if game.is_win() {
+                        assert!(std_game.is_win());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_recognize_move() {
+        for seed in 12..16 {
+            let cards = default_shuffle(seed);
+            let mut game = StandardSolitaire::new(&cards, NonZeroU8::new(3).unwrap());
+            let Some(moves) = solve(&mut From::from(&game)).1 else {
+                continue;
+            };
+
+            let mut his = StandardHistoryVec::new();
+            for &m in &moves {
+                his.clear();
+                convert_move(&game, m, &mut his).unwrap();
+                assert_eq!(recognize_move(&game, &his), Some(m));
+                for sm in &his {
+                    assert!(game.do_move(sm).is_ok());
+                }
+            }
+        }
+
+        let game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(3).unwrap());
+        assert_eq!(recognize_move(&game, &[]), None);
+        assert_eq!(recognize_move(&game, &[StandardMove::DRAW_NEXT]), None);
+    }
+
+    #[test]
+    fn test_convert_move_to() {
+        use rand::{rngs::SmallRng, seq::IndexedRandom, SeedableRng};
+
+        use crate::moves::N_MOVES_MAX;
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut n_choices = 0;
+        let mut n_same_color = 0;
+        for seed in 0..50 {
+            let mut game = StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(1).unwrap());
+            for _ in 0..100 {
+                let moves = Solitaire::from(&game).gen_moves::<false>().to_vec::<N_MOVES_MAX>();
+                for &m in &moves {
+                    let Move::Reveal(c) = m else {
+                        continue;
+                    };
+                    let from = game.find_top_card(c).unwrap();
+                    let dests: Vec<u8> = game.free_piles(c).filter(|&p| p != from).collect();
+                    if dests.len() < 2 {
+                        continue;
+                    }
+                    n_choices += 1;
+
+                    let mut encodes = Vec::new();
+                    for &dest in &dests {
+                        let his = convert_move_to(&game, m, dest).unwrap();
+                        assert_eq!(his.last().unwrap().to, Pos::Pile(dest));
+                        let mut after = game.clone();
+                        for sm in &his {
+                            assert!(after.do_move(sm).is_ok());
+                        }
+                        assert!(after.get_piles()[usize::from(dest)].contains(&c));
+                        encodes.push(Solitaire::from(&after).encode());
+                    }
+                    // apart from the empty pile a king takes, the engine doesn't
+                    // tell the destinations apart
+                    if !c.is_king() {
+                        n_same_color += 1;
+                        assert!(encodes.windows(2).all(|w| w[0] == w[1]));
+                    }
+                    assert!(convert_move_to(&game, m, from).is_err());
+                }
+
+                let Some(&m) = moves.choose(&mut rng) else {
+                    break;
+                };
+                let mut his = StandardHistoryVec::new();
+                convert_move(&game, m, &mut his).unwrap();
+                for sm in &his {
+                    assert!(game.do_move(sm).is_ok());
+                }
+            }
+        }
+        assert!(n_choices > n_same_color && n_same_color > 0);
+    }
 }
@@ -8,9 +8,8 @@ use crate::{
     utils::{full_mask, min},
 };
 
-pub const N_PILES: u8 = 7;
-pub const N_PILE_CARDS: u8 = N_PILES * (N_PILES + 1) / 2;
-pub const N_DECK_CARDS: u8 = N_CARDS - N_PILE_CARDS;
+// kept here for the existing `deck::N_*` paths
+pub use crate::shape::{N_DECK_CARDS, N_PILES, N_PILE_CARDS};
 
 #[derive(Debug, Clone)]
 pub struct Deck {
@@ -21,6 +20,9 @@ pub struct Deck {
     map: [u8; N_CARDS as usize],
 }
 
+/// Offsets of a deck, from 0 (nothing drawn) to `N_DECK_CARDS`
+pub type OffsetVec = ArrayVec<u8, { N_DECK_CARDS as usize + 1 }>;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Drawable {
     None,
@@ -31,17 +33,30 @@ pub enum Drawable {
 impl Deck {
     #[must_use]
     pub fn new(deck: [Card; N_DECK_CARDS as usize], draw_step: NonZeroU8) -> Self {
+        Self::from_cards(&deck, draw_step, 0)
+    }
+
+    /// A deck of the remaining `cards` of a game, with the first `offset` of
+    /// them already drawn
+    ///
+    /// # Panics
+    ///
+    /// When there are more than `N_DECK_CARDS` cards or `offset` is past them
+    #[must_use]
+    pub(crate) fn from_cards(cards: &[Card], draw_step: NonZeroU8, offset: u8) -> Self {
+        assert!(usize::from(offset) <= cards.len());
         let mut map = [!0u8; N_CARDS as usize];
         #[allow(clippy::cast_possible_truncation)]
-        for (i, c) in deck.iter().enumerate() {
+        for (i, c) in cards.iter().enumerate() {
             map[c.mask_index() as usize] = i as u8;
         }
 
+        #[allow(clippy::cast_possible_truncation)]
         Self {
-            deck: ArrayVec::from(deck),
+            deck: ArrayVec::try_from(cards).unwrap(),
             draw_step,
-            draw_cur: 0,
-            mask: full_mask(N_DECK_CARDS) as u32,
+            draw_cur: offset,
+            mask: full_mask(cards.len() as u8) as u32,
             map,
         }
     }
@@ -126,6 +141,16 @@ impl Deck {
         })
     }
 
+    /// Number of draws needed before `card` is the current card of the deck,
+    /// `None` when drawing never reaches it
+    #[must_use]
+    pub fn draws_to(&self, card: Card) -> Option<u8> {
+        (0..=N_DECK_CARDS).find(|&i| {
+            let offset = self.offset(i);
+            offset > 0 && self.peek(offset - 1) == card
+        })
+    }
+
     #[must_use]
     pub fn peek(&self, pos: u8) -> Card {
         self.deck[pos as usize]
@@ -156,6 +181,21 @@ impl Deck {
         )
     }
 
+    /// Every distinct offset reached by drawing again and again from the current
+    /// one, in the order they are reached and starting with the current offset
+    #[must_use]
+    pub fn reachable_offsets(&self) -> OffsetVec {
+        let mut offsets = OffsetVec::new();
+        for n_step in 0.. {
+            let offset = self.offset(n_step);
+            if offsets.contains(&offset) {
+                break;
+            }
+            offsets.push(offset);
+        }
+        offsets
+    }
+
     #[must_use]
     pub const fn offset_once(&self, cur: u8) -> u8 {
         let len = self.len();
@@ -218,6 +258,14 @@ impl Deck {
         mask
     }
 
+    /// Every remaining card can be drawn by cycling through the deck, so the
+    /// stock behaves as a plain queue. Always the case when drawing one by one
+    #[must_use]
+    pub fn all_drawable(&self) -> bool {
+        self.draw_step.get() == 1
+            || self.compute_mask(false).count_ones() == u32::from(self.len())
+    }
+
     #[must_use]
     pub fn peek_last(&self) -> Option<&Card> {
         self.deck.last()
@@ -241,6 +289,22 @@ impl Deck {
         self.draw_cur += 1;
     }
 
+    /// Take `card` out of the deck keeping the current offset on the same card
+    /// Return false when it's not in the deck
+    pub(crate) fn remove(&mut self, card: Card) -> bool {
+        if self.map[card.mask_index() as usize] >= N_DECK_CARDS {
+            return false;
+        }
+        let (found, pos) = self.find_card(card);
+        if !found {
+            return false;
+        }
+        let offset = self.get_offset();
+        self.draw(pos);
+        self.set_offset(if pos < offset { offset - 1 } else { offset });
+        true
+    }
+
     pub(crate) fn draw(&mut self, id: u8) -> Card {
         self.set_offset(id + 1);
         self.pop_next()
@@ -254,7 +318,7 @@ impl Deck {
     #[must_use]
     pub const fn is_pure(&self) -> bool {
         // this will return true if the deck is pure (when deal repeated it will loop back to the current state)
-        self.draw_cur % self.draw_step.get() == 0 || self.draw_cur == self.len()
+        self.draw_cur.is_multiple_of(self.draw_step.get()) || self.draw_cur == self.len()
     }
 
     #[must_use]
@@ -267,6 +331,10 @@ impl Deck {
         }
     }
 
+    /// The remaining cards keep the relative order of the original deck so the mask
+    /// identifies them, and every offset with the same drawable cards normalizes to
+    /// the same value. Hence, for decks coming from the same original deck,
+    /// `equivalent_to` holds exactly when the encodes are equal.
     #[must_use]
     pub const fn encode(&self) -> u32 {
         const_assert!(((N_DECK_CARDS - 1).ilog2() + 1 + N_DECK_CARDS as u32) <= 32);
@@ -393,4 +461,174 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_draws_to() {
+        let cards: [Card; N_DECK_CARDS as usize] =
+            default_shuffle(3)[..N_DECK_CARDS as usize].try_into().unwrap();
+
+        let deck = Deck::new(cards, NonZeroU8::new(1).unwrap());
+        for (i, &c) in cards.iter().enumerate() {
+            assert_eq!(deck.draws_to(c), Some(i as u8 + 1));
+        }
+
+        let mut deck = Deck::new(cards, NonZeroU8::new(3).unwrap());
+        assert_eq!(deck.draws_to(cards[2]), Some(1));
+        assert_eq!(deck.draws_to(cards[5]), Some(2));
+        // the second card only shows up once the third one is played
+        assert_eq!(deck.draws_to(cards[1]), None);
+        deck.draw(2);
+        assert_eq!(deck.draws_to(cards[1]), Some(0));
+        assert_eq!(deck.draws_to(cards[2]), None);
+    }
+
+    #[test]
+    fn test_all_drawable() {
+        let cards: [Card; N_DECK_CARDS as usize] =
+            default_shuffle(3)[..N_DECK_CARDS as usize].try_into().unwrap();
+
+        assert!(Deck::new(cards, NonZeroU8::new(1).unwrap()).all_drawable());
+
+        let mut deck = Deck::new(cards, NonZeroU8::new(3).unwrap());
+        assert!(!deck.all_drawable());
+        // only every third card can be drawn until the deck becomes short
+        while !deck.all_drawable() {
+            deck.draw(0);
+        }
+        let mask = deck.compute_mask(false);
+        assert!(deck.iter().all(|c| mask & c.mask() != 0));
+        assert!(deck.len() < N_DECK_CARDS);
+    }
+
+    #[test]
+    fn test_reachable_offsets() {
+        let cards: [Card; N_DECK_CARDS as usize] =
+            default_shuffle(5)[..N_DECK_CARDS as usize].try_into().unwrap();
+        let mut deck = Deck::new(cards, NonZeroU8::new(3).unwrap());
+
+        let fresh: OffsetVec = (0..=N_DECK_CARDS).step_by(3).collect();
+        assert_eq!(deck.reachable_offsets(), fresh);
+
+        // after taking the 2nd card the waste isn't aligned on the draw step anymore
+        deck.draw(1);
+        let len = N_DECK_CARDS - 1;
+        let mut expected: OffsetVec = (1..len).step_by(3).collect();
+        expected.push(len);
+        expected.extend((0..len).step_by(3));
+        assert_eq!(deck.reachable_offsets(), expected);
+
+        // same as dealing until coming back to an offset already seen
+        let mut rng = SmallRng::seed_from_u64(5);
+        for _ in 0..100 {
+            let draw_step = NonZeroU8::new(rng.random_range(1..5)).unwrap();
+            let mut deck = Deck::new(cards, draw_step);
+            for _ in 0..rng.random_range(0..10) {
+                let len = deck.len();
+                if len > 0 {
+                    deck.draw(rng.random_range(0..len));
+                }
+            }
+            let offsets = deck.reachable_offsets();
+
+            let mut dealt = OffsetVec::new();
+            while !dealt.contains(&deck.get_offset()) {
+                dealt.push(deck.get_offset());
+                deck.deal_once();
+            }
+            assert_eq!(offsets, dealt);
+        }
+    }
+
+    #[test]
+    fn test_set_offset_fuzz() {
+        let mut rng = SmallRng::seed_from_u64(37);
+
+        for i in 0..200 {
+            let cards: [Card; N_DECK_CARDS as usize] =
+                default_shuffle(i)[..N_DECK_CARDS as usize].try_into().unwrap();
+            // includes draw steps larger than the whole stock
+            let draw_step = NonZeroU8::new(rng.random_range(1..N_DECK_CARDS + 4)).unwrap();
+            let org = Deck::new(cards, draw_step);
+            let mut deck = org.clone();
+
+            while !deck.is_empty() {
+                let offset = rng.random_range(0..=deck.len());
+                deck.set_offset(offset);
+                assert_eq!(deck.get_offset(), offset);
+                assert_eq!(deck.offset(0), offset);
+                assert_eq!(deck.waste_iter().len(), usize::from(offset));
+                assert_eq!(deck.deck_iter().len(), usize::from(deck.len() - offset));
+
+                let all: Vec<_> = deck.iter_all().collect();
+                assert_eq!(all.len(), usize::from(deck.len()));
+                for (i, &(pos, card, _)) in all.iter().enumerate() {
+                    assert_eq!(usize::from(pos), i);
+                    assert_eq!(deck.peek(pos), card);
+                }
+                assert_eq!(deck.peek_current(), offset.checked_sub(1).map(|p| deck.peek(p)));
+                if let Some(c) = deck.peek_current() {
+                    assert_eq!(deck.draws_to(c), Some(0));
+                }
+
+                // the cards and the drawable ones survive a round trip
+                let mut decoded = org.clone();
+                decoded.decode(deck.encode());
+                assert_eq!(decoded.len(), deck.len());
+                assert_eq!(decoded.encode(), deck.encode());
+                assert!(decoded.equivalent_to(&deck));
+
+                if offset > 0 && rng.random_bool(0.3) {
+                    let card = deck.draw_current().unwrap();
+                    assert_eq!(deck.get_offset(), offset - 1);
+                    if rng.random_bool(0.5) {
+                        deck.push(card);
+                        assert_eq!(deck.get_offset(), offset);
+                        assert_eq!(deck.peek_current(), Some(card));
+                    }
+                } else if rng.random_bool(0.3) {
+                    let pos = rng.random_range(0..deck.len());
+                    deck.draw(pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_equivalent_encode() {
+        let mut rng = SmallRng::seed_from_u64(14);
+
+        for i in 0..50 {
+            let deck = default_shuffle(12 + i);
+            let deck = deck[..N_DECK_CARDS as usize].try_into().unwrap();
+
+            let draw_step = NonZeroU8::new(rng.random_range(1..5)).unwrap();
+            let mut deck = Deck::new(deck, draw_step);
+
+            while !deck.is_empty() {
+                // every offset of the same cards, including the ones that can't be
+                // reached by dealing, equivalent decks should encode the same
+                for a in 0..=deck.len() {
+                    let mut deck_a = deck.clone();
+                    deck_a.set_offset(a);
+                    for b in 0..=deck.len() {
+                        let mut deck_b = deck.clone();
+                        deck_b.set_offset(b);
+
+                        assert_eq!(
+                            deck_a.equivalent_to(&deck_b),
+                            deck_a.encode() == deck_b.encode()
+                        );
+                    }
+                }
+
+                let step = rng.random_range(1..10);
+                for _ in 0..step {
+                    deck.deal_once();
+                }
+                if deck.get_offset() > 0 {
+                    deck.pop_next();
+                }
+            }
+        }
+    }
 }
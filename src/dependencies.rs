@@ -1,8 +1,8 @@
 use core::mem::swap;
 
 use crate::{
-    card::{Card, N_CARDS, N_SUITS},
-    deck::N_PILES,
+    card::{Card, ACE_RANK, N_CARDS, N_SUITS},
+    shape::N_PILES,
     moves::{Move, MoveMask},
     state::{ExtraInfo, Solitaire},
 };
@@ -51,9 +51,8 @@ impl DependencyEngine {
             }
         }
         for suit in 0..N_SUITS {
-            let rank = state.get_stack().get(suit);
-            if rank > 0 {
-                cards[Card::new(rank - 1, suit).mask_index() as usize] = 0;
+            if let Some(card) = state.get_stack().top(suit) {
+                cards[card.mask_index() as usize] = 0;
             }
         }
 
@@ -161,7 +160,7 @@ impl DependencyEngine {
                 self.add_dep(self.last_draw);
                 self.last_draw = self.n_moves;
 
-                if card.rank() > 0 {
+                if card.rank() > ACE_RANK {
                     let other = Card::new(card.rank() - 1, card.suit());
                     self.add_dep(self.cards_to[other.mask_index() as usize]);
                     self.cards_to[other.mask_index() as usize] = usize::MAX;
@@ -174,7 +173,7 @@ impl DependencyEngine {
                 let from = self.get_move_lock(card);
                 self.add_dep(from);
 
-                if card.rank() > 0 {
+                if card.rank() > ACE_RANK {
                     let other = Card::new(card.rank() - 1, card.suit());
                     self.add_dep(self.cards_to[other.mask_index() as usize]);
                     self.cards_to[other.mask_index() as usize] = usize::MAX;
@@ -194,7 +193,7 @@ impl DependencyEngine {
                 self.cards_from[card.mask_index() as usize] = self.n_moves;
             }
             Move::StackPile(card) => {
-                if card.rank() > 0 {
+                if card.rank() > ACE_RANK {
                     let lower = Card::new(card.rank() - 1, card.suit());
                     self.cards_from[lower.mask_index() as usize] = self.n_moves;
                     self.cards_to[lower.mask_index() as usize] = self.n_moves;
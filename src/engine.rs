@@ -1,6 +1,7 @@
 use crate::{
     moves::{Move, MoveMask, N_MOVES_MAX},
     pruning::Pruner,
+    standard::StandardSolitaire,
     state::{Encode, Solitaire, UndoInfo},
 };
 pub type MoveVec = ArrayVec<Move, N_MOVES_MAX>;
@@ -9,6 +10,15 @@ extern crate alloc;
 use alloc::vec::Vec;
 use arrayvec::ArrayVec;
 
+/// Whether a game is over, see [`SolitaireEngine::status`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Won,
+    /// No move left short of a win
+    Lost,
+    Ongoing,
+}
+
 pub struct SolitaireEngine<P: Pruner> {
     state: Solitaire,
     pruner: P,
@@ -33,6 +43,18 @@ impl<P: Pruner + Default> SolitaireEngine<P> {
         }
     }
 
+    /// Set up an engine from an arbitrary position
+    /// Return None when the position is not a valid game state
+    #[must_use]
+    pub fn from_standard(game: &StandardSolitaire) -> Option<Self> {
+        let state = Solitaire::from(game);
+        if state.is_valid() {
+            Some(Self::new(state))
+        } else {
+            None
+        }
+    }
+
     #[must_use]
     pub const fn state(&self) -> &Solitaire {
         &self.state
@@ -48,6 +70,24 @@ impl<P: Pruner + Default> SolitaireEngine<P> {
         &self.pruner
     }
 
+    /// Whether the move makes progress: it moves a card to the foundation,
+    /// reveals a hidden card, moves a king into an empty column or empties a
+    /// column that a king can fill right after
+    ///
+    /// Taking a card back from the foundation is never productive on its own
+    #[must_use]
+    pub fn is_productive(&self, m: &Move) -> bool {
+        match *m {
+            Move::PileStack(_) | Move::DeckStack(_) => true,
+            Move::StackPile(_) => false,
+            Move::DeckPile(c) => c.is_king(),
+            Move::Reveal(c) => {
+                let hidden = self.state.get_hidden();
+                hidden.len(hidden.find(c)) > 1 || self.state.idle_empty_reveals() & c.mask() == 0
+            }
+        }
+    }
+
     #[must_use]
     pub fn is_valid(&self, m: Move) -> bool {
         MoveMask::from(m).filter(&self.valid_moves).is_empty()
@@ -80,7 +120,7 @@ impl<P: Pruner + Default> SolitaireEngine<P> {
 
     #[must_use]
     pub fn encode(&self) -> Encode {
-        self.state.encode()
+        self.state.encode_cached()
     }
 
     // it will reset everything :) so use carefully
@@ -117,4 +157,72 @@ impl<P: Pruner + Default> SolitaireEngine<P> {
     pub fn list_moves(&self) -> MoveVec {
         self.list_moves_generics::<false>()
     }
+
+    /// Same as `!self.list_moves_dom().is_empty()` without building the list
+    #[must_use]
+    pub fn has_any_move(&self) -> bool {
+        !self
+            .state
+            .gen_moves::<true>()
+            .filter(&self.pruner.prune_moves(&self.state))
+            .is_empty()
+    }
+
+    #[must_use]
+    pub fn status(&self) -> GameStatus {
+        if self.state.is_win() {
+            GameStatus::Won
+        } else if self.has_any_move() {
+            GameStatus::Ongoing
+        } else {
+            GameStatus::Lost
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use crate::{card::Card, pruning::NoPruner, shuffler::default_shuffle};
+
+    use super::*;
+
+    #[test]
+    fn test_from_standard_scenario() {
+        let mut game = StandardSolitaire::new(&default_shuffle(1), NonZeroU8::new(1).unwrap());
+        assert!(game.force_foundation(0, 4));
+        assert!(game.force_foundation(3, 2));
+
+        let engine = SolitaireEngine::<NoPruner>::from_standard(&game).unwrap();
+        assert!(engine.state().is_valid());
+        assert_eq!(engine.state().get_stack().get(0), 4);
+        assert_eq!(engine.state().get_stack().get(3), 2);
+
+        let moves = engine.list_moves();
+        // the top of the built foundation can come back down
+        assert!(moves.contains(&Move::StackPile(Card::new(3, 0))));
+        // the other foundations are still empty
+        assert!(moves.contains(&Move::DeckStack(Card::new(0, 2))));
+        assert!(!moves.contains(&Move::StackPile(Card::new(1, 3))));
+
+        // taking the card back down only shuffles it around
+        assert!(!engine.is_productive(&Move::StackPile(Card::new(3, 0))));
+        assert!(engine.is_productive(&Move::DeckStack(Card::new(0, 2))));
+    }
+
+    #[test]
+    fn test_productive_reveal() {
+        let game = Solitaire::new(&default_shuffle(2), NonZeroU8::new(1).unwrap());
+        let engine: SolitaireEngine<NoPruner> = game.into();
+        let hidden = engine.state().get_hidden();
+
+        let reveals: MoveVec = engine
+            .list_moves()
+            .into_iter()
+            .filter(|m| matches!(m, Move::Reveal(c) if hidden.len(hidden.find(*c)) > 1))
+            .collect();
+        assert!(!reveals.is_empty());
+        assert!(reveals.iter().all(|m| engine.is_productive(m)));
+    }
 }
@@ -1,20 +1,184 @@
-use core::fmt;
+use core::fmt::{self, Write};
+use core::str::FromStr;
 
 use crate::card::{Card, N_RANKS, N_SUITS};
-use crate::standard::{Pos, StandardMove};
+use crate::moves::Move;
+use crate::shape::N_PILES;
+use crate::standard::{Pos, StandardMove, StandardSolitaire};
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 pub const SYMBOLS: [&str; N_SUITS as usize] = ["♥", "♦", "♣", "♠"];
+/// ASCII replacements of [`SYMBOLS`]
+pub const LETTERS: [&str; N_SUITS as usize] = ["H", "D", "C", "S"];
 pub const NUMBERS: [&str; N_RANKS as usize] = [
     "A", "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K",
 ];
 
+/// `A♥`, or `AH` with the alternate flag (`{:#}`) for ASCII-only output
 impl fmt::Display for Card {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let (u, v) = self.split();
-        write!(f, "{}{}", NUMBERS[u as usize], SYMBOLS[v as usize])
+        let suits = if f.alternate() { LETTERS } else { SYMBOLS };
+        write!(f, "{}{}", NUMBERS[u as usize], suits[v as usize])
+    }
+}
+
+impl Card {
+    /// The card written with the suit letters `HDCS`, e.g. `10H`
+    #[must_use]
+    pub fn to_ascii(&self) -> String {
+        alloc::format!("{self:#}")
     }
 }
 
+/// Error returned when a card, a move, a move history or a play style can't be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError;
+
+impl FromStr for Card {
+    type Err = ParseError;
+
+    /// Parse a card as written by `Display` (`10♥`), the suit can also be
+    /// one of the letters `HDCS`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let suit = match chars.next_back().ok_or(ParseError)? {
+            '♥' | 'H' | 'h' => 0,
+            '♦' | 'D' | 'd' => 1,
+            '♣' | 'C' | 'c' => 2,
+            '♠' | 'S' | 's' => 3,
+            _ => return Err(ParseError),
+        };
+        let rank = chars.as_str();
+        let rank = NUMBERS
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(rank))
+            .ok_or(ParseError)?;
+        Ok(Self::new(rank as u8, suit))
+    }
+}
+
+impl FromStr for Move {
+    type Err = ParseError;
+
+    /// Parse a move as written by `Display` (`DS A♥`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, card) = s.trim().split_once(' ').ok_or(ParseError)?;
+        parse_move(kind, card.trim())
+    }
+}
+
+fn parse_move(kind: &str, card: &str) -> Result<Move, ParseError> {
+    let card = card.parse()?;
+    match kind {
+        "DS" => Ok(Move::DeckStack(card)),
+        "PS" => Ok(Move::PileStack(card)),
+        "DP" => Ok(Move::DeckPile(card)),
+        "SP" => Ok(Move::StackPile(card)),
+        "R" => Ok(Move::Reveal(card)),
+        _ => Err(ParseError),
+    }
+}
+
+/// Format a move history as a numbered transcript `1.DS AH 2.R 7S ...`,
+/// ASCII-only to be safe in logs
+#[must_use]
+pub fn format_history(moves: &[Move]) -> String {
+    let mut res = String::new();
+    for (i, m) in moves.iter().enumerate() {
+        if i > 0 {
+            res.push(' ');
+        }
+        // writing into a String never fails
+        let _ = write!(res, "{}.{m:#}", i + 1);
+    }
+    res
+}
+
+/// Parse a transcript produced by [`format_history`]
+///
+/// # Errors
+///
+/// Return `ParseError` when a move is malformed or numbered out of order
+pub fn parse_history(s: &str) -> Result<Vec<Move>, ParseError> {
+    let mut tokens = s.split_whitespace();
+    let mut moves = Vec::new();
+    while let Some(head) = tokens.next() {
+        let (idx, kind) = head.split_once('.').ok_or(ParseError)?;
+        if idx.parse::<usize>() != Ok(moves.len() + 1) {
+            return Err(ParseError);
+        }
+        let card = tokens.next().ok_or(ParseError)?;
+        moves.push(parse_move(kind, card)?);
+    }
+    Ok(moves)
+}
+
+/// A card as a JSON string for Solvitaire, `"10H"` face up and `"10h"` face
+/// down
+struct SolvitaireCard(Card, bool);
+
+impl fmt::Display for SolvitaireCard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (rank, suit) = self.0.split();
+        let suit = LETTERS[suit as usize];
+        if self.1 {
+            write!(f, r#""{}{}""#, NUMBERS[rank as usize], suit.to_ascii_lowercase())
+        } else {
+            write!(f, r#""{}{}""#, NUMBERS[rank as usize], suit)
+        }
+    }
+}
+
+fn write_cards(res: &mut String, cards: impl Iterator<Item = SolvitaireCard>) {
+    res.push('[');
+    for (i, c) in cards.enumerate() {
+        if i > 0 {
+            res.push(',');
+        }
+        let _ = write!(res, "{c}");
+    }
+    res.push(']');
+}
+
+/// The whole board in the JSON format of the Solvitaire solver: the tableau
+/// piles from the bottom with the face down cards in lowercase, the stock from
+/// its next card, the waste and the foundations
+///
+/// The draw step isn't part of the board for Solvitaire, it is given to the
+/// solver on its command line (`--klondike-deal-count`).
+#[must_use]
+pub fn to_solvitaire_json(game: &StandardSolitaire) -> String {
+    let mut res = String::from(r#"{"tableau piles": ["#);
+    for i in 0..N_PILES as usize {
+        if i > 0 {
+            res.push(',');
+        }
+        res.push('\n');
+        let hidden = game.get_hidden()[i].iter().map(|&c| SolvitaireCard(c, true));
+        let visible = game.get_piles()[i].iter().map(|&c| SolvitaireCard(c, false));
+        write_cards(&mut res, hidden.chain(visible));
+    }
+    res.push_str("\n],\"stock\": ");
+    let deck = game.get_deck();
+    write_cards(&mut res, deck.deck_iter().rev().map(|c| SolvitaireCard(c, false)));
+    res.push_str(",\"waste\": ");
+    write_cards(&mut res, deck.waste_iter().map(|c| SolvitaireCard(c, false)));
+    res.push_str(",\n\"foundation\": [");
+    for suit in 0..N_SUITS {
+        if suit > 0 {
+            res.push(',');
+        }
+        let height = game.get_stack().get(suit);
+        write_cards(&mut res, (0..height).map(|rank| SolvitaireCard(Card::new(rank, suit), false)));
+    }
+    res.push_str("]}");
+    res
+}
+
 impl fmt::Display for Pos {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -34,3 +198,80 @@ impl fmt::Display for StandardMove {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use crate::{shuffler::default_shuffle, solver::solve, state::Solitaire};
+
+    #[test]
+    fn test_card_round_trip() {
+        for i in 0..N_SUITS * N_RANKS {
+            let c = Card::from_mask_index(i);
+            assert_eq!(c.to_string().parse(), Ok(c));
+        }
+        assert_eq!("10h".parse(), Ok(Card::new(9, 0)));
+        assert_eq!("Ks".parse(), Ok(Card::new(12, 3)));
+        assert_eq!("1♥".parse::<Card>(), Err(ParseError));
+        assert_eq!("♥".parse::<Card>(), Err(ParseError));
+        assert_eq!("AX".parse::<Card>(), Err(ParseError));
+    }
+
+    #[test]
+    fn test_solvitaire_json() {
+        let game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(3).unwrap());
+        let json = to_solvitaire_json(&game);
+        let expected = r#"{"tableau piles": [
+["8S"],
+["Ah","2D"],
+["5s","5d","4S"],
+["4c","Jh","7h","7D"],
+["8d","8c","9s","Ad","6D"],
+["7s","4h","2c","10d","3s","9C"],
+["Qd","As","Qh","10c","Kh","8h","QC"]
+],"stock": ["3D","5H","5C","3C","KD","4D","JS","2S","9D","JD","3H","QS","2H","6H","6S","JC","KC","7C","AC","KS","6C","10H","10S","9H"],"waste": [],
+"foundation": [[],[],[],[]]}"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn test_ascii_card() {
+        assert_eq!(format!("{:#}", Card::new(0, 0)), "AH");
+        assert_eq!(format!("{}", Card::new(0, 0)), "A♥");
+        assert_eq!(Card::new(9, 3).to_ascii(), "10S");
+        assert_eq!(format!("{:#}", Move::Reveal(Card::new(6, 2))), "R 7C");
+        for c in Card::all() {
+            assert_eq!(c.to_ascii().parse(), Ok(c));
+        }
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let (_, history) = solve(&mut game);
+        let history = history.unwrap();
+
+        let text = format_history(&history);
+        assert!(text.starts_with("1."));
+
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        assert_eq!(tokens.len(), history.len() * 2);
+        let parsed: Vec<Move> = tokens
+            .chunks(2)
+            .enumerate()
+            .map(|(i, t)| {
+                let kind = t[0].strip_prefix(&format!("{}.", i + 1)).unwrap();
+                format!("{kind} {}", t[1]).parse().unwrap()
+            })
+            .collect();
+        assert_eq!(parsed, history.as_slice());
+        assert_eq!(parse_history(&text), Ok(parsed));
+
+        assert!(text.is_ascii());
+        assert_eq!(format_history(&[]), "");
+        assert_eq!(parse_history("2.DS A♥"), Err(ParseError));
+        assert_eq!(parse_history("1.XX A♥"), Err(ParseError));
+    }
+}
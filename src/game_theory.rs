@@ -1,72 +1,702 @@
 //! Simplified MCTS based move selection working on partial information.
 
+use core::num::NonZeroU8;
+
 use rand::prelude::*;
 
-use crate::analysis::{ranked_moves, HeuristicConfig, PlayStyle, RankedMove};
-use crate::engine::SolitaireEngine;
-use crate::pruning::FullPruner;
+use crate::analysis::{
+    ranked_moves, ranked_moves_from_partial, sort_ranked_moves, HeuristicConfig, PlayStyle, RankKey,
+    RankedMove,
+};
+use crate::cache::OpeningBook;
+use crate::engine::{GameStatus, SolitaireEngine};
+use crate::card::{Card, N_CARDS};
+use crate::pruning::{FullPruner, NoPruner};
 use crate::partial::PartialState;
+use crate::moves::Move;
+use crate::shuffler::default_shuffle;
+use crate::solver::{exact_move_values, N_PLY_MAX};
+use crate::state::Solitaire;
+use crate::utils::{round, sqrt};
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+/// Mixed into the base seed to derive the rollout streams from the world stream,
+/// the rollout of a playout is seeded with the result plus the playout index.
+const ROLLOUT_SEED_MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Fully known states with at most this many cards off the foundation are
+/// solved exactly instead of sampled.
+pub const EXACT_MAX_CARDS: u8 = 12;
+
+/// A win rate of one is a simulation score of a thousand
+const SIMULATION_SCALE: f64 = 1000.0;
+
+/// z-score of the 95% confidence level
+const Z_95: f64 = 1.96;
+
+/// [`RankedMove::simulation_score`] of a move winning `win_rate` of its playouts
+#[must_use]
+pub fn simulation_score(win_rate: f64) -> i32 {
+    round(win_rate * SIMULATION_SCALE)
+}
+
+/// 95% Wilson score interval of a win rate of `wins` out of `n` playouts
+///
+/// Return `(0.0, 0.0)` when there is no playout.
+#[must_use]
+pub fn wilson_interval(wins: usize, n: usize) -> (f64, f64) {
+    if n == 0 {
+        return (0.0, 0.0);
+    }
+    let n = n as f64;
+    let p = wins as f64 / n;
+    let z2 = Z_95 * Z_95;
+    let denom = 1.0 + z2 / n;
+    let center = (p + z2 / (2.0 * n)) / denom;
+    let half = Z_95 / denom * sqrt(p * (1.0 - p) / n + z2 / (4.0 * n * n));
+    ((center - half).max(0.0), (center + half).min(1.0))
+}
+
+/// Settings of the Monte Carlo searches, see [`rank_moves_mcts`]
+#[derive(Clone, Copy)]
+pub struct MctsParams<'a> {
+    /// Number of playouts of every root move
+    pub n_playouts: usize,
+    /// Number of random moves played at most after the root move
+    pub max_depth: usize,
+    /// Number of threads running the playouts of [`best_move_mcts_parallel`],
+    /// the other searches run on the calling thread
+    pub n_threads: usize,
+    /// Moves checked before any playout
+    pub book: Option<&'a OpeningBook>,
+}
+
+impl MctsParams<'_> {
+    /// `n_playouts` playouts of at most `max_depth` moves on a single thread,
+    /// without an opening book
+    #[must_use]
+    pub const fn new(n_playouts: usize, max_depth: usize) -> Self {
+        Self {
+            n_playouts,
+            max_depth,
+            n_threads: 1,
+            book: None,
+        }
+    }
+}
 
 /// Run a light Monte Carlo tree search to pick the best move.
+///
+/// This is the first move of [`rank_moves_mcts`], so the move of the book of
+/// `params` when it has a legal one for `state`.
 #[must_use]
 pub fn best_move_mcts<R: Rng>(
     state: &PartialState,
     style: PlayStyle,
     cfg: &HeuristicConfig,
+    params: &MctsParams,
+    rng: &mut R,
+) -> Option<RankedMove> {
+    rank_moves_mcts(state, style, cfg, params, rng).into_iter().next()
+}
+
+/// Run a light Monte Carlo tree search on every root move, returning them
+/// from the highest win rate down with their simulation score, win rate and
+/// confidence interval filled in.
+///
+/// The moves with the same win rate keep their heuristic order. Every root
+/// move is evaluated against the same sequence of sampled worlds (common
+/// random numbers) derived from a single seed drawn from `rng`, so the win
+/// rates of different moves are directly comparable.
+///
+/// Small fully known endgames (see [`EXACT_MAX_CARDS`]) skip the playouts and
+/// label every move with its exact outcome instead. A state whose unknown
+/// cards can't be filled (see [`FillError`](crate::partial::FillError)) has
+/// no moves.
+///
+/// When the book of `params` has a legal move for `state`, the moves keep
+/// their heuristic order with the booked one first: no playout is run and
+/// nothing is drawn from `rng`.
+#[must_use]
+pub fn rank_moves_mcts<R: Rng>(
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    params: &MctsParams,
+    rng: &mut R,
+) -> Vec<RankedMove> {
+    rank_moves_with(state, style, cfg, params.book, rng, |probs, moves, base_seed| {
+        let worlds = sample_worlds(state, probs, params.n_playouts, base_seed);
+        for m in moves {
+            simulate_move(&worlds, m, params.max_depth, base_seed);
+        }
+    })
+}
+
+/// [`rank_moves_mcts`] with `simulate` running the playouts of the root moves
+fn rank_moves_with<R: Rng>(
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    book: Option<&OpeningBook>,
+    rng: &mut R,
+    simulate: impl FnOnce(&[Vec<(Card, f64)>], &mut [RankedMove], u64),
+) -> Vec<RankedMove> {
+    if let Some(moves) = book.and_then(|book| booked_moves(book, state, style, cfg)) {
+        return moves;
+    }
+    let probs = state.column_probabilities();
+    let Ok(filled) = state.fill_unknowns_weighted(&probs, rng) else {
+        return Vec::new();
+    };
+    let solitaire: Solitaire = (&filled).into();
+    let engine: SolitaireEngine<FullPruner> = solitaire.clone().into();
+    let mut moves = ranked_moves(&engine, state, style, cfg);
+
+    if state.is_fully_known() && N_CARDS - solitaire.get_stack().len() <= EXACT_MAX_CARDS {
+        let values = exact_move_values(&solitaire);
+        for m in &mut moves {
+            let win = values.iter().any(|&(mv, w)| w && mv == m.mv);
+            m.win_rate = if win { 1.0 } else { 0.0 };
+            m.win_rate_ci = (m.win_rate, m.win_rate);
+            m.simulation_score = simulation_score(m.win_rate);
+        }
+    } else {
+        let base_seed: u64 = rng.random();
+        simulate(&probs, &mut moves, base_seed);
+    }
+    sort_ranked_moves(&mut moves, RankKey::WinRate);
+    moves
+}
+
+/// The moves of `state` in heuristic order with the move of `book` first,
+/// `None` when `book` has no legal move for `state`
+fn booked_moves(
+    book: &OpeningBook,
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+) -> Option<Vec<RankedMove>> {
+    let booked = book.lookup(state)?;
+    // the legal moves don't depend on the unknown cards
+    let filled = state.fill_unknowns_randomly(&mut SmallRng::seed_from_u64(0)).ok()?;
+    let engine: SolitaireEngine<FullPruner> = Solitaire::from(&filled).into();
+    let mut moves = ranked_moves(&engine, state, style, cfg);
+    let pos = moves.iter().position(|m| m.mv == booked)?;
+    moves[..=pos].rotate_right(1);
+    Some(moves)
+}
+
+/// Pick among the moves whose heuristic score is within `margin` of the best
+/// one by their win rate over `n_playouts` playouts
+///
+/// `moves` must be sorted from the best heuristic score down, as returned by
+/// [`ranked_moves`]. The playouts fill in the simulation score and win rate of
+/// the tied moves, the earliest one wins among equal win rates. Without a tie
+/// this is the first move and no playout is run.
+#[must_use]
+pub fn break_ties<R: Rng>(
+    state: &PartialState,
+    moves: &mut [RankedMove],
+    margin: i32,
     n_playouts: usize,
     max_depth: usize,
     rng: &mut R,
 ) -> Option<RankedMove> {
+    let best = moves.first()?.heuristic_score;
+    let n_tied = moves
+        .iter()
+        .take_while(|m| m.heuristic_score >= best - margin)
+        .count();
+    if n_tied < 2 {
+        return moves.first().cloned();
+    }
+
     let probs = state.column_probabilities();
-    let filled = state.fill_unknowns_weighted(&probs, rng);
-    let solitaire: crate::state::Solitaire = (&filled).into();
-    let engine: SolitaireEngine<FullPruner> = solitaire.into();
-    let mut moves = ranked_moves(&engine, state, style, cfg);
+    let base_seed: u64 = rng.random();
+    let worlds = sample_worlds(state, &probs, n_playouts, base_seed);
+    let mut pick = 0;
+    for i in 0..n_tied {
+        simulate_move(&worlds, &mut moves[i], max_depth, base_seed);
+        if moves[i].win_rate > moves[pick].win_rate {
+            pick = i;
+        }
+    }
+    Some(moves[pick].clone())
+}
 
-    let mut best: Option<(RankedMove, f64)> = None;
-
-    for m in &mut moves {
-        let mut total = 0f64;
-        let mut wins = 0usize;
-
-        // Monte Carlo playouts with weighted unknowns
-        for _ in 0..n_playouts {
-            let filled = state.fill_unknowns_weighted(&probs, rng);
-            let solitaire_child: crate::state::Solitaire = (&filled).into();
-            let mut child: SolitaireEngine<FullPruner> = solitaire_child.into();
-            child.do_move(m.mv);
-
-            let mut tmp: SolitaireEngine<FullPruner> = child.state().clone().into();
-            let mut depth = 0usize;
-            while depth < max_depth {
-                let list = tmp.list_moves_dom();
-                if list.is_empty() {
-                    break;
-                }
-                let mv = *list.choose(rng).unwrap();
-                tmp.do_move(mv);
-                depth += 1;
-                if tmp.state().is_win() {
-                    wins += 1;
-                    total += 10.0;
-                    break;
-                }
+/// Sample the worlds of `n_playouts` playouts from a stream seeded with
+/// `base_seed`, `None` for a world whose unknowns can't be filled
+fn sample_worlds(
+    state: &PartialState,
+    probs: &[Vec<(Card, f64)>],
+    n_playouts: usize,
+    base_seed: u64,
+) -> Vec<Option<Solitaire>> {
+    // Monte Carlo playouts with weighted unknowns, one determinization each
+    let mut world_rng = SmallRng::seed_from_u64(base_seed);
+    (0..n_playouts)
+        .map(|_| {
+            let filled = state.fill_unknowns_weighted(probs, &mut world_rng).ok()?;
+            Some((&filled).into())
+        })
+        .collect()
+}
+
+/// Run the playouts of a single root move and fill in its simulation score,
+/// win rate and confidence interval.
+///
+/// Every root move plays in the same `worlds` and every playout uses its own
+/// rollout stream seeded from `base_seed` and its index, so the n-th playout
+/// of every move sees the same world no matter how many random choices the
+/// other rollouts consumed, or which thread ran them.
+fn simulate_move(
+    worlds: &[Option<Solitaire>],
+    m: &mut RankedMove,
+    max_depth: usize,
+    base_seed: u64,
+) {
+    let wins = playout_wins(worlds, 0, m.mv, max_depth, base_seed);
+    set_playout_results(m, wins, worlds.len());
+}
+
+/// Number of the playouts of `mv` won in `worlds`, the first of them being the
+/// playout number `first`
+fn playout_wins(
+    worlds: &[Option<Solitaire>],
+    first: usize,
+    mv: Move,
+    max_depth: usize,
+    base_seed: u64,
+) -> usize {
+    let rollout_seed = base_seed ^ ROLLOUT_SEED_MIX;
+    (first as u64..)
+        .zip(worlds)
+        .filter(|&(index, world)| {
+            world.as_ref().is_some_and(|world| {
+                let mut rollout_rng = SmallRng::seed_from_u64(rollout_seed.wrapping_add(index));
+                rollout(world.clone(), mv, max_depth, &mut rollout_rng).is_win()
+            })
+        })
+        .count()
+}
+
+fn set_playout_results(m: &mut RankedMove, wins: usize, n_playouts: usize) {
+    m.win_rate = if n_playouts == 0 { 0.0 } else { wins as f64 / n_playouts as f64 };
+    m.simulation_score = simulation_score(m.win_rate);
+    m.win_rate_ci = wilson_interval(wins, n_playouts);
+}
+
+/// Same as [`best_move_mcts`] with the playouts of every root move split in
+/// chunks shared between the threads of `params`, started once for the whole
+/// search
+///
+/// The worlds are sampled once and every playout runs on its own rollout
+/// stream, so the result is the one of [`best_move_mcts`] with the same `rng`
+/// whatever the number of threads.
+#[cfg(feature = "std")]
+#[must_use]
+pub fn best_move_mcts_parallel<R: Rng>(
+    state: &PartialState,
+    style: PlayStyle,
+    cfg: &HeuristicConfig,
+    params: &MctsParams,
+    rng: &mut R,
+) -> Option<RankedMove> {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    let MctsParams { n_playouts, max_depth, n_threads, book } = *params;
+    let n_threads = n_threads.max(1);
+    let simulate = |probs: &[Vec<(Card, f64)>], moves: &mut [RankedMove], base_seed: u64| {
+        let worlds = sample_worlds(state, probs, n_playouts, base_seed);
+        let chunk_len = n_playouts.div_ceil(n_threads).max(1);
+        let chunks: Vec<(usize, &[Option<Solitaire>])> =
+            (0..).step_by(chunk_len).zip(worlds.chunks(chunk_len)).collect();
+        let n_chunks = chunks.len().max(1);
+        let root: Vec<Move> = moves.iter().map(|m| m.mv).collect();
+        let wins: Vec<AtomicUsize> = root.iter().map(|_| AtomicUsize::new(0)).collect();
+        let next = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..n_threads {
+                // every thread takes the next chunk of playouts of a root move
+                // until none is left
+                scope.spawn(|| loop {
+                    let job = next.fetch_add(1, Ordering::Relaxed);
+                    let (i, j) = (job / n_chunks, job % n_chunks);
+                    let (Some(&mv), Some(&(first, chunk))) = (root.get(i), chunks.get(j)) else {
+                        break;
+                    };
+                    let n = playout_wins(chunk, first, mv, max_depth, base_seed);
+                    wins[i].fetch_add(n, Ordering::Relaxed);
+                });
             }
+        });
+        for (m, w) in moves.iter_mut().zip(wins) {
+            set_playout_results(m, w.into_inner(), n_playouts);
         }
+    };
+    rank_moves_with(state, style, cfg, book, rng, simulate).into_iter().next()
+}
 
-        let avg = if n_playouts == 0 { 0.0 } else { total / n_playouts as f64 };
-        // round() may not be available in core for no_std; emulate simple rounding
-        m.simulation_score = (avg + 0.5) as i32;
-        m.win_rate = if n_playouts == 0 { 0.0 } else { wins as f64 / n_playouts as f64 };
-        if let Some((_, best_score)) = &mut best {
-            if avg > *best_score {
-                *best_score = avg;
-                best = Some((m.clone(), avg));
-            }
+/// Results of [`evaluate_policy`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PolicyStats {
+    pub games: usize,
+    pub wins: usize,
+    /// Moves played over every game
+    pub moves: usize,
+}
+
+impl PolicyStats {
+    /// Share of the games won, zero without any game
+    #[must_use]
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            0.0
         } else {
-            best = Some((m.clone(), avg));
+            self.wins as f64 / self.games as f64
+        }
+    }
+
+    /// Number of moves played per game, zero without any game
+    #[must_use]
+    pub fn average_moves(&self) -> f64 {
+        if self.games == 0 {
+            0.0
+        } else {
+            self.moves as f64 / self.games as f64
+        }
+    }
+}
+
+/// Play the game dealt from each of `seeds` (see
+/// [`default_shuffle`]) with `policy` picking every move from what the player
+/// sees, and count the wins
+///
+/// A game ends when it is won, when there is no move left, when `policy`
+/// returns `None` or a move that isn't legal, or after [`N_PLY_MAX`] moves.
+/// The policy doesn't see the moves played before, so no move is pruned from
+/// the history of the game.
+pub fn evaluate_policy<F: Fn(&PartialState) -> Option<Move>>(
+    seeds: impl IntoIterator<Item = u64>,
+    policy: F,
+    draw_step: NonZeroU8,
+) -> PolicyStats {
+    let mut stats = PolicyStats::default();
+    for seed in seeds {
+        let game = Solitaire::new(&default_shuffle(seed), draw_step);
+        let mut engine: SolitaireEngine<NoPruner> = game.into();
+        let mut n_moves = 0;
+        while n_moves < N_PLY_MAX && engine.status() == GameStatus::Ongoing {
+            let Some(mv) = policy(&PartialState::from_blind(engine.state())) else {
+                break;
+            };
+            if !engine.do_move(mv) {
+                break;
+            }
+            n_moves += 1;
+        }
+        stats.games += 1;
+        stats.wins += usize::from(engine.state().is_win());
+        stats.moves += n_moves;
+    }
+    stats
+}
+
+/// Policy for [`evaluate_policy`] playing the move ranked first by
+/// [`ranked_moves_from_partial`]
+pub fn greedy_policy(
+    style: PlayStyle,
+    cfg: HeuristicConfig,
+) -> impl Fn(&PartialState) -> Option<Move> {
+    move |state| ranked_moves_from_partial(state, style, &cfg).first().map(|m| m.mv)
+}
+
+/// Policy for [`evaluate_policy`] playing the move of [`best_move_mcts`]
+///
+/// The search of every move starts from the same `seed`, so the policy always
+/// picks the same move in the same state.
+pub fn mcts_policy(
+    style: PlayStyle,
+    cfg: HeuristicConfig,
+    n_playouts: usize,
+    max_depth: usize,
+    seed: u64,
+) -> impl Fn(&PartialState) -> Option<Move> {
+    move |state| {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let params = MctsParams::new(n_playouts, max_depth);
+        best_move_mcts(state, style, &cfg, &params, &mut rng).map(|m| m.mv)
+    }
+}
+
+/// Play `first` then random moves on a single determinized `world`
+///
+/// The hidden cards are fixed by `world` for the whole rollout, every reveal
+/// uncovers the card the determinization put there instead of a resampled one.
+fn rollout<R: Rng>(world: Solitaire, first: Move, max_depth: usize, rng: &mut R) -> Solitaire {
+    let mut child: SolitaireEngine<FullPruner> = world.into();
+    child.do_move(first);
+
+    let mut tmp: SolitaireEngine<FullPruner> = child.into_state().into();
+    for _ in 0..max_depth {
+        let list = tmp.list_moves_dom();
+        let Some(&mv) = list.choose(rng) else {
+            break;
+        };
+        tmp.do_move(mv);
+        if tmp.state().is_win() {
+            break;
         }
     }
+    tmp.into_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use crate::shuffler::default_shuffle;
+
+    fn blind_state(seed: u64) -> PartialState {
+        let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(1).unwrap());
+        let mut engine: SolitaireEngine<FullPruner> = game.into();
+        // skip the forced moves so that there is something to compare
+        while let [m] = engine.list_moves_dom()[..] {
+            engine.do_move(m);
+        }
+        PartialState::from_blind(engine.state())
+    }
+
+    #[test]
+    fn test_evaluate_policy() {
+        let draw_step = NonZeroU8::new(1).unwrap();
+        let cfg = HeuristicConfig::default();
+        let rng = core::cell::RefCell::new(SmallRng::seed_from_u64(0));
+        let random = evaluate_policy(
+            0..4,
+            |state| {
+                let moves = ranked_moves_from_partial(state, PlayStyle::Neutral, &cfg);
+                moves.choose(&mut *rng.borrow_mut()).map(|m| m.mv)
+            },
+            draw_step,
+        );
+        let mcts = evaluate_policy(0..4, mcts_policy(PlayStyle::Neutral, cfg.clone(), 10, 50, 0), draw_step);
+        let greedy = evaluate_policy(0..4, greedy_policy(PlayStyle::Neutral, cfg), draw_step);
+        assert_eq!((random.games, mcts.games, greedy.games), (4, 4, 4));
+        assert!(mcts.average_moves() > 0.0);
+        // the policies play on the actual board, so they do win some games
+        assert!(mcts.wins > 0 && greedy.wins > 0);
+        assert!(mcts.wins >= random.wins);
+    }
 
-    best.map(|b| b.0)
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_best_move_mcts_parallel() {
+        let state = blind_state(27);
+        let cfg = HeuristicConfig::default();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let params = MctsParams::new(30, 100);
+        let serial = rank_moves_mcts(&state, PlayStyle::Neutral, &cfg, &params, &mut rng);
+        assert!(serial.iter().any(|m| m.win_rate > 0.0));
+        for n_threads in [1, 3, 8] {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let params = MctsParams { n_threads, ..params };
+            let best = best_move_mcts_parallel(&state, PlayStyle::Neutral, &cfg, &params, &mut rng);
+            let best = best.unwrap();
+            assert_eq!((best.mv, best.win_rate), (serial[0].mv, serial[0].win_rate));
+        }
+    }
+
+    #[test]
+    fn test_wilson_interval() {
+        assert_eq!(wilson_interval(0, 0), (0.0, 0.0));
+
+        let (lo, hi) = wilson_interval(3, 5);
+        assert!(lo < 0.6 && 0.6 < hi);
+        let (lo2, hi2) = wilson_interval(60, 100);
+        assert!(lo2 < 0.6 && 0.6 < hi2);
+        // same empirical rate, more playouts
+        assert!(lo < lo2 && hi2 < hi);
+
+        let (lo, hi) = wilson_interval(0, 20);
+        assert_eq!(lo, 0.0);
+        assert!(hi > 0.0 && hi < 0.2);
+        let (lo, hi) = wilson_interval(20, 20);
+        assert!(lo > 0.8 && hi == 1.0);
+        // reference value
+        let (lo, hi) = wilson_interval(50, 100);
+        assert!((lo - 0.4038).abs() < 1e-4 && (hi - 0.5962).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_common_random_numbers() {
+        let state = blind_state(7);
+        let probs = state.column_probabilities();
+        let filled = state.fill_unknowns_weighted(&probs, &mut SmallRng::seed_from_u64(0)).unwrap();
+        let solitaire: Solitaire = (&filled).into();
+        let engine: SolitaireEngine<FullPruner> = solitaire.into();
+        let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
+        assert!(moves.len() > 1);
+
+        let worlds = sample_worlds(&state, &probs, 20, 42);
+        let evaluate = |moves: &[RankedMove]| -> Vec<(Move, f64)> {
+            moves
+                .iter()
+                .map(|m| {
+                    let mut m = m.clone();
+                    simulate_move(&worlds, &mut m, 30, 42);
+                    (m.mv, m.win_rate)
+                })
+                .collect()
+        };
+
+        let first = evaluate(&moves);
+        assert_eq!(first, evaluate(&moves));
+
+        // the playouts don't depend on how they are split
+        let mv = moves[0].mv;
+        let (head, tail) = worlds.split_at(7);
+        assert_eq!(
+            playout_wins(&worlds, 0, mv, 30, 42),
+            playout_wins(head, 0, mv, 30, 42) + playout_wins(tail, 7, mv, 30, 42)
+        );
+
+        // the evaluation of a move doesn't depend on the other root moves
+        let mut reversed: Vec<RankedMove> = moves.clone();
+        reversed.reverse();
+        let mut second = evaluate(&reversed);
+        second.reverse();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_stable_hidden_within_determinization() {
+        let state = blind_state(7);
+        let probs = state.column_probabilities();
+        let filled = state.fill_unknowns_weighted(&probs, &mut SmallRng::seed_from_u64(0)).unwrap();
+        let world: Solitaire = (&filled).into();
+        let engine: SolitaireEngine<FullPruner> = world.clone().into();
+        let first = engine.list_moves_dom()[0];
+
+        let mut revealed = 0;
+        for seed in 0..20 {
+            let played = rollout(world.clone(), first, 100, &mut SmallRng::seed_from_u64(seed));
+            // identical rollouts end up in the same place
+            let again = rollout(world.clone(), first, 100, &mut SmallRng::seed_from_u64(seed));
+            assert_eq!(played.encode(), again.encode());
+
+            // hidden piles only shrink and the uncovered card is the one fixed by the world
+            for pos in 0..crate::shape::N_PILES {
+                let (before, after) = (world.get_hidden().get(pos), played.get_hidden().get(pos));
+                assert_eq!(after, &before[..after.len()]);
+                if after.len() < before.len() {
+                    revealed += 1;
+                }
+            }
+        }
+        assert!(revealed > 0);
+    }
+
+    #[test]
+    fn test_rank_moves_mcts() {
+        let state = blind_state(27);
+        let cfg = HeuristicConfig::default();
+        let ranked = |seed| {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            rank_moves_mcts(&state, PlayStyle::Neutral, &cfg, &MctsParams::new(30, 100), &mut rng)
+        };
+        let moves = ranked(0);
+
+        let probs = state.column_probabilities();
+        let filled = state.fill_unknowns_weighted(&probs, &mut SmallRng::seed_from_u64(0)).unwrap();
+        let engine: SolitaireEngine<FullPruner> = Solitaire::from(&filled).into();
+        assert_eq!(moves.len(), engine.list_moves_dom().len());
+        assert!(moves.windows(2).all(|w| w[0].win_rate >= w[1].win_rate));
+        assert!(moves[0].win_rate > 0.0);
+        // the win rate in per mille
+        for m in &moves {
+            assert_eq!(m.simulation_score, (m.win_rate * 1000.0).round() as i32);
+        }
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let params = MctsParams::new(30, 100);
+        let best = best_move_mcts(&state, PlayStyle::Neutral, &cfg, &params, &mut rng).unwrap();
+        assert_eq!(best.mv, moves[0].mv);
+        assert_eq!(best.win_rate, moves[0].win_rate);
+    }
+
+    #[test]
+    fn test_best_move_mcts_booked() {
+        let mut engine: SolitaireEngine<FullPruner> =
+            Solitaire::new(&default_shuffle(27), NonZeroU8::new(1).unwrap()).into();
+        while let [m] = engine.list_moves_dom()[..] {
+            engine.do_move(m);
+        }
+        let state = PartialState::from_blind(engine.state());
+        let cfg = HeuristicConfig::default();
+        let search = |book: &OpeningBook| {
+            let mut rng = SmallRng::seed_from_u64(2);
+            let params = MctsParams { book: Some(book), ..MctsParams::new(20, 50) };
+            let picked = best_move_mcts(&state, PlayStyle::Neutral, &cfg, &params, &mut rng);
+            (picked.unwrap(), rng.random::<u64>())
+        };
+
+        let mut book = OpeningBook::new();
+        let (searched, after_search) = search(&book);
+        let moves = engine.list_moves_dom();
+        let other = *moves.iter().find(|&&m| m != searched.mv).unwrap();
+
+        book.insert(&state, other);
+        let (booked, after_book) = search(&book);
+        assert_eq!(booked.mv, other);
+        // no playout and no random draw
+        assert_eq!(booked.win_rate, 0.0);
+        assert_ne!(after_book, after_search);
+        assert_eq!(after_book, SmallRng::seed_from_u64(2).random::<u64>());
+
+        let mut rng = SmallRng::seed_from_u64(2);
+        let params = MctsParams { book: Some(&book), ..MctsParams::new(20, 50) };
+        let ranked = rank_moves_mcts(&state, PlayStyle::Neutral, &cfg, &params, &mut rng);
+        assert_eq!(ranked[0].mv, other);
+        assert_eq!(ranked.len(), moves.len());
+
+        // an illegal booked move falls through to the search
+        let illegal = Move::DeckStack(Card::new(12, 0));
+        assert!(!moves.contains(&illegal));
+        book.insert(&state, illegal);
+        assert_eq!(search(&book).0.mv, searched.mv);
+    }
+
+    #[test]
+    fn test_break_ties() {
+        let state = blind_state(27);
+        let probs = state.column_probabilities();
+        let filled = state.fill_unknowns_weighted(&probs, &mut SmallRng::seed_from_u64(0)).unwrap();
+        let engine: SolitaireEngine<FullPruner> = Solitaire::from(&filled).into();
+        let mut moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
+        assert!(moves.len() > 1);
+
+        // no tie, no playout
+        let mut untied = moves.clone();
+        untied[0].heuristic_score = untied[1].heuristic_score + 10;
+        let picked = break_ties(&state, &mut untied, 0, 100, 300, &mut SmallRng::seed_from_u64(1));
+        assert_eq!(picked.unwrap().mv, untied[0].mv);
+        assert!(untied.iter().all(|m| m.win_rate == 0.0));
+
+        // every move tied, the playouts prefer a later move
+        for m in &mut moves {
+            m.heuristic_score = 0;
+        }
+        let picked = break_ties(&state, &mut moves, 0, 100, 300, &mut SmallRng::seed_from_u64(1)).unwrap();
+        let best = moves.iter().map(|m| m.win_rate).fold(0.0, f64::max);
+        for m in &moves[..] {
+            assert_eq!(m.simulation_score, (m.win_rate * 1000.0).round() as i32);
+        }
+        assert_eq!(picked.win_rate, best);
+        assert!(picked.mv != moves[0].mv && picked.win_rate > moves[0].win_rate);
+    }
 }
@@ -5,10 +5,14 @@ use crate::{
     state::{Encode, Solitaire},
     tracking::{DefaultTerminateSignal, EmptySearchStats, SearchStatistics, TerminateSignal},
     traverse::{traverse, Callback, Control, TpTable},
+    utils::MixHasherBuilder,
 };
 
 extern crate alloc;
-use alloc::vec::Vec;
+use alloc::{collections::BinaryHeap, vec::Vec};
+use core::cmp::Reverse;
+
+use hashbrown::HashMap;
 
 #[derive(Clone, Copy, Debug)]
 pub enum EdgeType {
@@ -20,14 +24,70 @@ pub enum EdgeType {
     Reveal,
 }
 
+impl EdgeType {
+    /// Cost of the move for [`shortest_win`]. Taking a card from the deck
+    /// might need a few draws first, so it costs more than the other moves
+    #[must_use]
+    pub const fn weight(&self) -> u32 {
+        match self {
+            Self::DeckPile | Self::DeckStack => 2,
+            Self::PileStack | Self::PileStackReveal | Self::StackPile | Self::Reveal => 1,
+        }
+    }
+}
+
+/// Encode of the winning state in a [`Graph`]
+pub const WIN_ENCODE: Encode = !0;
+
 pub type Edge = (Encode, Encode, EdgeType);
 pub type Graph = Vec<Edge>;
 
-struct BuilderCallback<'a, S: SearchStatistics, T: TerminateSignal> {
-    graph: Graph,
+/// Receive the edges of the graph one by one while it is traversed
+///
+/// A `Graph` collects every edge, a custom visitor can process them without
+/// storing the whole graph, for example counting the edges by type:
+///
+/// ```
+/// use core::num::NonZeroU8;
+/// use lonelybot::graph::{traverse_graph, Edge, GraphCallback};
+/// use lonelybot::shuffler::default_shuffle;
+/// use lonelybot::state::Solitaire;
+/// use lonelybot::tracking::NodeLimit;
+/// use lonelybot::traverse::Control;
+///
+/// #[derive(Default)]
+/// struct CountByType([usize; 6]);
+///
+/// impl GraphCallback for CountByType {
+///     fn on_edge(&mut self, edge: Edge) -> Control {
+///         self.0[edge.2 as usize] += 1;
+///         Control::Ok
+///     }
+/// }
+///
+/// let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+/// let mut counts = CountByType::default();
+/// let limit = NodeLimit::new(10_000);
+/// traverse_graph(&mut game, &mut counts, &limit, &limit);
+/// assert!(counts.0.iter().sum::<usize>() > 0);
+/// ```
+pub trait GraphCallback {
+    fn on_edge(&mut self, edge: Edge) -> Control;
+}
+
+impl GraphCallback for Graph {
+    fn on_edge(&mut self, edge: Edge) -> Control {
+        self.push(edge);
+        Control::Ok
+    }
+}
+
+struct BuilderCallback<'a, V: GraphCallback, S: SearchStatistics, T: TerminateSignal> {
+    graph: &'a mut V,
     stats: &'a S,
     sign: &'a T,
     depth: usize,
+    max_depth: usize,
     prev_enc: Encode,
     last_move: Move,
     rev_move: Option<Move>,
@@ -44,13 +104,14 @@ const fn get_edge_type(m: Move, rm: Option<Move>) -> EdgeType {
     }
 }
 
-impl<'a, S: SearchStatistics, T: TerminateSignal> BuilderCallback<'a, S, T> {
-    fn new(g: &Solitaire, stats: &'a S, sign: &'a T) -> Self {
+impl<'a, V: GraphCallback, S: SearchStatistics, T: TerminateSignal> BuilderCallback<'a, V, S, T> {
+    fn new(g: &Solitaire, graph: &'a mut V, stats: &'a S, sign: &'a T, max_depth: usize) -> Self {
         Self {
-            graph: Graph::new(),
+            graph,
             stats,
             sign,
             depth: 0,
+            max_depth,
             prev_enc: g.encode(),
             last_move: Move::DeckPile(Card::DEFAULT),
             rev_move: None,
@@ -58,17 +119,18 @@ impl<'a, S: SearchStatistics, T: TerminateSignal> BuilderCallback<'a, S, T> {
     }
 }
 
-impl<S: SearchStatistics, T: TerminateSignal> Callback for BuilderCallback<'_, S, T> {
+impl<V: GraphCallback, S: SearchStatistics, T: TerminateSignal> Callback
+    for BuilderCallback<'_, V, S, T>
+{
     type Pruner = FullPruner;
 
     fn on_win(&mut self, _: &Solitaire) -> Control {
         // win state
-        self.graph.push((
+        self.graph.on_edge((
             self.prev_enc,
-            !0,
+            WIN_ENCODE,
             get_edge_type(self.last_move, self.rev_move),
-        ));
-        Control::Ok
+        ))
     }
 
     fn on_visit(&mut self, _: &Solitaire, e: Encode) -> Control {
@@ -77,13 +139,11 @@ impl<S: SearchStatistics, T: TerminateSignal> Callback for BuilderCallback<'_, S
         }
 
         self.stats.hit_a_state(self.depth);
-        self.graph.push((
+        self.graph.on_edge((
             self.prev_enc,
             e,
             get_edge_type(self.last_move, self.rev_move),
-        ));
-
-        Control::Ok
+        ))
     }
 
     fn on_move_gen(&mut self, m: &MoveMask, _: Encode) -> Control {
@@ -92,6 +152,9 @@ impl<S: SearchStatistics, T: TerminateSignal> Callback for BuilderCallback<'_, S
     }
 
     fn on_do_move(&mut self, _: &Solitaire, m: Move, e: Encode, prune: &FullPruner) -> Control {
+        if self.depth >= self.max_depth {
+            return Control::Skip;
+        }
         self.last_move = m;
         self.rev_move = prune.rev_move();
         self.prev_enc = e;
@@ -105,18 +168,188 @@ impl<S: SearchStatistics, T: TerminateSignal> Callback for BuilderCallback<'_, S
     }
 }
 
-pub fn graph_with_tracking<S: SearchStatistics, T: TerminateSignal>(
+/// Traverse the game graph and hand every edge to `graph` instead of storing it
+pub fn traverse_graph<V: GraphCallback, S: SearchStatistics, T: TerminateSignal>(
     g: &mut Solitaire,
+    graph: &mut V,
     stats: &S,
     sign: &T,
-) -> (Control, Graph) {
+) -> Control {
+    traverse_graph_to_depth(g, graph, stats, sign, usize::MAX)
+}
+
+/// Same as [`traverse_graph`], without playing any move past `max_depth`
+/// moves from `g`, to look at the neighborhood of deals too hard to traverse
+/// entirely
+///
+/// A state is only expanded the first time it's reached, so a state first
+/// reached near the limit keeps its few edges even when it's also closer.
+pub fn traverse_graph_to_depth<V: GraphCallback, S: SearchStatistics, T: TerminateSignal>(
+    g: &mut Solitaire,
+    graph: &mut V,
+    stats: &S,
+    sign: &T,
+    max_depth: usize,
+) -> Control {
     let mut tp = TpTable::default();
-    let mut callback = BuilderCallback::new(g, stats, sign);
+    let mut callback = BuilderCallback::new(g, graph, stats, sign, max_depth);
 
-    let finished = traverse(g, FullPruner::default(), &mut tp, &mut callback);
-    (finished, callback.graph)
+    traverse(g, FullPruner::default(), &mut tp, &mut callback)
+}
+
+pub fn graph_with_tracking<S: SearchStatistics, T: TerminateSignal>(
+    g: &mut Solitaire,
+    stats: &S,
+    sign: &T,
+) -> (Control, Graph) {
+    let mut graph = Graph::new();
+    let finished = traverse_graph(g, &mut graph, stats, sign);
+    (finished, graph)
 }
 
 pub fn graph(g: &mut Solitaire) -> (Control, Graph) {
     graph_with_tracking(g, &EmptySearchStats {}, &DefaultTerminateSignal {})
 }
+
+/// The graph of the states within `max_depth` moves of `g`, see
+/// [`traverse_graph_to_depth`]
+pub fn graph_to_depth(g: &mut Solitaire, max_depth: usize) -> (Control, Graph) {
+    let mut graph = Graph::new();
+    let finished = traverse_graph_to_depth(
+        g,
+        &mut graph,
+        &EmptySearchStats {},
+        &DefaultTerminateSignal {},
+        max_depth,
+    );
+    (finished, graph)
+}
+
+/// Cost of the cheapest path from `start` to a win in `graph` where every
+/// edge costs `weight(edge type)`, None when no win is reachable
+///
+/// `|_| 1` counts the moves, [`EdgeType::weight`] also accounts for the draws.
+#[must_use]
+pub fn shortest_win<F: Fn(EdgeType) -> u32>(graph: &Graph, start: Encode, weight: F) -> Option<u32> {
+    let mut adjacent: HashMap<Encode, Vec<(Encode, u32)>, MixHasherBuilder> =
+        HashMap::default();
+    for &(from, to, edge) in graph {
+        if from != to {
+            adjacent.entry(from).or_default().push((to, weight(edge)));
+        }
+    }
+
+    let mut dist: HashMap<Encode, u32, MixHasherBuilder> = HashMap::default();
+    let mut heap = BinaryHeap::new();
+    dist.insert(start, 0);
+    heap.push(Reverse((0, start)));
+    while let Some(Reverse((d, node))) = heap.pop() {
+        if node == WIN_ENCODE {
+            return Some(d);
+        }
+        if dist.get(&node).is_some_and(|&best| best < d) {
+            continue;
+        }
+        for &(next, w) in adjacent.get(&node).into_iter().flatten() {
+            let nd = d + w;
+            if dist.get(&next).is_none_or(|&best| nd < best) {
+                dist.insert(next, nd);
+                heap.push(Reverse((nd, next)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use crate::{engine::SolitaireEngine, shuffler::default_shuffle, solver::solve};
+
+    // the last part of a won game to keep the graph small
+    fn endgame() -> Solitaire {
+        let game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let moves = solve(&mut game.clone()).1.unwrap();
+        let mut engine: SolitaireEngine<FullPruner> = game.into();
+        for &m in &moves[..moves.len() - 40] {
+            engine.do_move(m);
+        }
+        engine.into_state()
+    }
+
+    #[test]
+    fn test_shortest_win() {
+        let mut game = endgame();
+        let start = game.encode();
+        let (control, g) = graph(&mut game);
+        assert_eq!(control, Control::Ok);
+
+        let n_moves = shortest_win(&g, start, |_| 1).unwrap();
+        let weighted = shortest_win(&g, start, |e| e.weight()).unwrap();
+        assert!(n_moves <= 40);
+        assert!(weighted >= n_moves && weighted <= 2 * n_moves);
+        assert_eq!(shortest_win(&g, start, |_| 0), Some(0));
+        assert_eq!(shortest_win(&g, WIN_ENCODE - 1, |_| 1), None);
+    }
+
+    #[derive(Default)]
+    struct CountEdges {
+        n_edges: usize,
+        n_wins: usize,
+    }
+
+    impl GraphCallback for CountEdges {
+        fn on_edge(&mut self, edge: Edge) -> Control {
+            self.n_edges += 1;
+            self.n_wins += usize::from(edge.1 == WIN_ENCODE);
+            Control::Ok
+        }
+    }
+
+    #[test]
+    fn test_traverse_graph() {
+        let game = endgame();
+        let (control, g) = graph(&mut game.clone());
+
+        let mut counts = CountEdges::default();
+        let streamed = traverse_graph(
+            &mut game.clone(),
+            &mut counts,
+            &EmptySearchStats,
+            &DefaultTerminateSignal,
+        );
+        assert_eq!(streamed, control);
+        assert_eq!(counts.n_edges, g.len());
+        assert_eq!(counts.n_wins, g.iter().filter(|e| e.1 == WIN_ENCODE).count());
+    }
+
+    #[test]
+    fn test_graph_to_depth() {
+        let game = endgame();
+        let start = game.encode();
+        let (_, full) = graph(&mut game.clone());
+        let (control, g) = graph_to_depth(&mut game.clone(), 3);
+        assert_eq!(control, Control::Ok);
+        assert!(!g.is_empty() && g.len() < full.len());
+
+        // moves from the start to every state of the graph
+        let mut dist: HashMap<Encode, usize, MixHasherBuilder> = HashMap::default();
+        dist.insert(start, 0);
+        let mut frontier = alloc::vec![start];
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for &(from, to, _) in &g {
+                if frontier.contains(&from) && !dist.contains_key(&to) {
+                    dist.insert(to, dist[&from] + 1);
+                    next.push(to);
+                }
+            }
+            frontier = next;
+        }
+        assert!(g.iter().all(|e| dist.contains_key(&e.1)));
+        assert!(dist.values().all(|&d| d <= 3));
+        assert!(dist.values().any(|&d| d == 3));
+    }
+}
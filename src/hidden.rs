@@ -4,10 +4,22 @@ use rand::RngCore;
 use arrayvec::ArrayVec;
 
 use crate::card::{Card, N_CARDS};
-use crate::deck::{N_PILES, N_PILE_CARDS};
+use crate::shape::{N_PILES, N_PILE_CARDS};
 
 use crate::standard::HiddenVec;
 
+/// Weight of the hidden count of the pile `pos` in [`Hidden::encode`]
+#[must_use]
+pub(crate) const fn encode_weight(pos: u8) -> u16 {
+    let mut weight = 1;
+    let mut i = 0;
+    while i < pos {
+        weight *= i as u16 + 2;
+        i += 1;
+    }
+    weight
+}
+
 #[derive(Debug, Clone)]
 pub struct Hidden {
     hidden_piles: [Card; N_PILE_CARDS as usize],
@@ -263,6 +275,26 @@ impl Hidden {
         self.update_invariant();
     }
 
+    /// Whether the hidden cards are in the order [`Self::clear`] puts them
+    ///
+    /// The top card of a pile is left out, it's the card revealed next so
+    /// its place is part of the state.
+    #[must_use]
+    pub fn is_canonical(&self) -> bool {
+        let mut last = None;
+        for pos in 0..N_PILES {
+            if let Some((_, pile_map)) = self.get(pos).split_last() {
+                for c in pile_map {
+                    if last >= Some(c.mask_index()) {
+                        return false;
+                    }
+                    last = Some(c.mask_index());
+                }
+            }
+        }
+        true
+    }
+
     pub fn shuffle<R: RngCore>(&mut self, rng: &mut R) {
         let mut all_stuff = ArrayVec::<Card, { N_PILE_CARDS as usize }>::new();
         for pos in 0..N_PILES {
@@ -312,3 +344,45 @@ impl Hidden {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use rand::prelude::*;
+
+    use crate::{moves::N_MOVES_MAX, shuffler::default_shuffle, state::Solitaire};
+
+    #[test]
+    fn test_clear_is_canonical() {
+        let fresh = Solitaire::new(&default_shuffle(0), NonZeroU8::new(3).unwrap());
+        assert!(!fresh.get_hidden().is_canonical());
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        for seed in 0..20 {
+            let mut game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+            for _ in 0..rng.random_range(0..30) {
+                let moves = game.gen_moves::<true>().to_vec::<N_MOVES_MAX>();
+                let Some(&m) = moves.choose(&mut rng) else {
+                    break;
+                };
+                game.do_move(m);
+            }
+            let mask = game.get_hidden().mask();
+
+            game.hidden_clear();
+            assert!(game.get_hidden().is_canonical());
+            assert!(game.get_hidden().is_valid());
+            assert_eq!(game.get_hidden().mask(), mask);
+            let cleared = game.get_hidden().to_piles();
+
+            // the arrangement depends only on the set of hidden cards
+            let mut shuffled = game.clone();
+            shuffled.hidden_shuffle(&mut rng);
+            assert_eq!(shuffled.get_hidden().mask(), mask);
+            shuffled.hidden_clear();
+            assert_eq!(shuffled.get_hidden().to_piles(), cleared);
+            assert_eq!(shuffled.encode(), game.encode());
+        }
+    }
+}
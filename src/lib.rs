@@ -1,4 +1,8 @@
 #![cfg_attr(not(test), no_std)]
+#[cfg(all(feature = "std", not(test)))]
+extern crate std;
+
+pub mod cache;
 pub mod card;
 pub mod convert;
 pub mod deck;
@@ -10,6 +14,7 @@ pub mod hop_solver;
 pub mod mcts_solver;
 pub mod moves;
 pub mod pruning;
+pub mod shape;
 pub mod shuffler;
 pub mod solver;
 pub mod stack;
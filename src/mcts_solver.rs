@@ -2,8 +2,8 @@ use rand::RngCore;
 
 use crate::{
     hop_solver::{hop_solve_game, HopResult},
-    moves::Move,
-    pruning::FullPruner,
+    moves::{Move, MoveMask, N_MOVES_MAX},
+    pruning::{FullPruner, Pruner},
     state::{Encode, Solitaire},
     tracking::TerminateSignal,
     traverse::{traverse, Callback, Control, TpTable},
@@ -174,3 +174,249 @@ pub fn pick_moves<R: RngCore, T: TerminateSignal>(
         // }
     }
 }
+
+/// Statistics of a position of the [`MctsSearcher`] tree
+#[derive(Default)]
+struct Node {
+    wins: usize,
+    visits: usize,
+    expanded: bool,
+    children: Vec<(Move, Node)>,
+}
+
+/// Monte Carlo tree search keeping its tree from one move of a game to the next
+///
+/// Every iteration walks down the tree picking the child of best `pot_fn`,
+/// evaluates the first unvisited move it reaches with [`hop_solve_game`] and
+/// adds the result to every position of the path. [`Self::advance`] makes the
+/// child of the move played the new root, so what was searched under it
+/// counts for the next search.
+pub struct MctsSearcher {
+    game: Solitaire,
+    root: Node,
+    pot_fn: PotientialFn,
+    n_times: usize,
+    limit: usize,
+}
+
+impl MctsSearcher {
+    /// Searcher evaluating a new move with `n_times` determinized searches of
+    /// at most `limit` states, see [`hop_solve_game`]
+    #[must_use]
+    pub fn new(game: Solitaire, pot_fn: PotientialFn, n_times: usize, limit: usize) -> Self {
+        Self {
+            game,
+            root: Node::default(),
+            pot_fn,
+            n_times,
+            limit,
+        }
+    }
+
+    #[must_use]
+    pub const fn game(&self) -> &Solitaire {
+        &self.game
+    }
+
+    /// Number of determinized games played under the current root, `n_times`
+    /// for each evaluation
+    #[must_use]
+    pub const fn visits(&self) -> usize {
+        self.root.visits
+    }
+
+    /// The moves of the root searched so far with their wins and games played
+    #[must_use]
+    pub fn root_moves(&self) -> Vec<(Move, usize, usize)> {
+        self.root
+            .children
+            .iter()
+            .map(|(m, node)| (*m, node.wins, node.visits))
+            .collect()
+    }
+
+    /// The most visited move of the root
+    #[must_use]
+    pub fn best_move(&self) -> Option<Move> {
+        self.root
+            .children
+            .iter()
+            .filter(|(_, node)| node.visits > 0)
+            .max_by_key(|(_, node)| node.visits)
+            .map(|(m, _)| *m)
+    }
+
+    /// Run `iterations` more iterations, adding to the statistics of the tree
+    pub fn search<R: RngCore, T: TerminateSignal>(
+        &mut self,
+        iterations: usize,
+        rng: &mut R,
+        sign: &T,
+    ) {
+        let mut ctx = SearchContext {
+            rng,
+            sign,
+            pot_fn: self.pot_fn,
+            n_times: self.n_times,
+            limit: self.limit,
+        };
+        for _ in 0..iterations {
+            if sign.is_terminated() {
+                break;
+            }
+            ctx.iterate(&mut self.root, &mut self.game, FullPruner::default());
+        }
+    }
+
+    /// Play `m` and keep its subtree as the new root
+    ///
+    /// Return false and change nothing when `m` isn't a legal move.
+    pub fn advance(&mut self, m: Move) -> bool {
+        if !MoveMask::from(m)
+            .filter(&self.game.gen_moves::<false>())
+            .is_empty()
+        {
+            return false;
+        }
+        self.game.do_move(m);
+        let pos = self.root.children.iter().position(|(mv, _)| *mv == m);
+        self.root = pos.map_or_else(Node::default, |pos| self.root.children.swap_remove(pos).1);
+        true
+    }
+}
+
+struct SearchContext<'a, R: RngCore, T: TerminateSignal> {
+    rng: &'a mut R,
+    sign: &'a T,
+    pot_fn: PotientialFn,
+    n_times: usize,
+    limit: usize,
+}
+
+impl<R: RngCore, T: TerminateSignal> SearchContext<'_, R, T> {
+    // the (wins, played) of the evaluation, already added along the path
+    fn iterate(
+        &mut self,
+        node: &mut Node,
+        game: &mut Solitaire,
+        pruner: FullPruner,
+    ) -> (usize, usize) {
+        if !node.expanded {
+            node.expanded = true;
+            node.children = game
+                .gen_moves::<true>()
+                .filter(&pruner.prune_moves(game))
+                .to_vec::<N_MOVES_MAX>()
+                .into_iter()
+                .map(|m| (m, Node::default()))
+                .collect();
+        }
+
+        let res = if node.children.is_empty() {
+            (if game.is_win() { self.n_times } else { 0 }, self.n_times)
+        } else {
+            let total = node.visits;
+            let pot_fn = self.pot_fn;
+            let (m, child) = node
+                .children
+                .iter_mut()
+                .max_by(|a, b| {
+                    pot_fn(a.1.wins, a.1.visits, total)
+                        .total_cmp(&pot_fn(b.1.wins, b.1.visits, total))
+                })
+                .unwrap();
+            let m = *m;
+
+            if child.visits == 0 {
+                let res = hop_solve_game(
+                    game,
+                    m,
+                    self.rng,
+                    self.n_times,
+                    self.limit,
+                    self.sign,
+                    &pruner,
+                );
+                // a sure outcome is reported with saturated counts
+                let res = (res.wins.min(self.n_times), res.played.min(self.n_times));
+                child.wins += res.0;
+                child.visits += res.1;
+                res
+            } else {
+                let (rev_m, (undo, extra)) = game.do_move(m);
+                let res = self.iterate(child, game, pruner.update(m, rev_m, extra));
+                game.undo_move(m, undo);
+                res
+            }
+        };
+
+        node.wins += res.0;
+        node.visits += res.1;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::{shuffler::default_shuffle, tracking::DefaultTerminateSignal};
+
+    #[allow(clippy::cast_precision_loss)]
+    fn ucb1(n_sucess: usize, n_visit: usize, n_total: usize) -> f64 {
+        if n_visit == 0 {
+            f64::INFINITY
+        } else {
+            n_sucess as f64 / n_visit as f64 + 2.0 * ((n_total as f64).ln() / n_visit as f64).sqrt()
+        }
+    }
+
+    #[test]
+    fn test_searcher_reuses_subtree() {
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        game.hidden_clear();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut searcher = MctsSearcher::new(game.clone(), ucb1, 1, 200);
+
+        searcher.search(60, &mut rng, &DefaultTerminateSignal);
+        assert_eq!(searcher.visits(), 60);
+        let best = searcher.best_move().unwrap();
+        let (_, _, best_visits) = searcher
+            .root_moves()
+            .into_iter()
+            .find(|r| r.0 == best)
+            .unwrap();
+        assert!(best_visits > 1);
+
+        assert!(searcher.advance(best));
+        // the evaluations under the chosen move are kept
+        assert_eq!(searcher.visits(), best_visits);
+        game.do_move(best);
+        assert_eq!(searcher.game().encode(), game.encode());
+
+        searcher.search(10, &mut rng, &DefaultTerminateSignal);
+        assert_eq!(searcher.visits(), best_visits + 10);
+
+        // the move that was just played can't be played again
+        assert!(!searcher.advance(best));
+        assert_eq!(searcher.visits(), best_visits + 10);
+    }
+
+    #[test]
+    fn test_searcher_counts_games() {
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        game.hidden_clear();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let mut searcher = MctsSearcher::new(game, ucb1, 4, 200);
+
+        searcher.search(15, &mut rng, &DefaultTerminateSignal);
+        // every evaluation plays its 4 games
+        assert_eq!(searcher.visits(), 60);
+        let root_moves = searcher.root_moves();
+        assert_eq!(root_moves.iter().map(|r| r.2).sum::<usize>(), 60);
+        assert!(root_moves.iter().all(|r| r.2 % 4 == 0 && r.1 <= r.2));
+    }
+}
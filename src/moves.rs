@@ -2,7 +2,9 @@ use core::{fmt, ops::ControlFlow};
 
 use crate::{
     card::{Card, N_SUITS},
-    deck::N_PILES,
+    shape::N_PILES,
+    standard::StandardSolitaire,
+    state::Solitaire,
 };
 use arrayvec::ArrayVec;
 
@@ -12,18 +14,75 @@ pub enum Move {
     PileStack(Card),
     DeckPile(Card),
     StackPile(Card),
+    /// Move the run starting at the card off the hidden cards under it, this
+    /// is also how a king (and its run) is relocated to an empty column
     Reveal(Card),
 }
 
+/// A move identified by the place it's played from instead of its card, see
+/// [`Move::abstract_key`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AbstractMove {
+    /// Position of the card in the deck
+    DeckStack(Option<u8>),
+    /// Column of the card
+    PileStack(Option<u8>),
+    /// Position of the card in the deck
+    DeckPile(Option<u8>),
+    /// Suit of the foundation
+    StackPile(u8),
+    /// Column of the run
+    Reveal(Option<u8>),
+}
+
+impl Move {
+    /// The deck position, column or foundation this move is played from on
+    /// `game`
+    ///
+    /// The same move in two completions of the unknown cards of a state has
+    /// the same key even when the cards differ, e.g. to share the statistics
+    /// of the moves between determinizations. A card that isn't in the deck or
+    /// the columns gets no position.
+    #[must_use]
+    pub fn abstract_key(&self, game: &Solitaire) -> AbstractMove {
+        let column = |c: Card| {
+            let piles = game.compute_visible_piles();
+            #[allow(clippy::cast_possible_truncation)]
+            piles.iter().position(|p| p.contains(&c)).map(|i| i as u8)
+        };
+        let deck = |c: Card| game.get_deck().iter_all().find(|x| x.1 == c).map(|x| x.0);
+        match *self {
+            Self::DeckStack(c) => AbstractMove::DeckStack(deck(c)),
+            Self::PileStack(c) => AbstractMove::PileStack(column(c)),
+            Self::DeckPile(c) => AbstractMove::DeckPile(deck(c)),
+            Self::StackPile(c) => AbstractMove::StackPile(c.suit()),
+            Self::Reveal(c) => AbstractMove::Reveal(column(c)),
+        }
+    }
+
+    /// Number of draws needed before playing this deck move on `game`, `None`
+    /// for the other moves or when the card can't be drawn
+    #[must_use]
+    pub fn deck_cost(&self, game: &StandardSolitaire) -> Option<u8> {
+        match *self {
+            Self::DeckPile(c) | Self::DeckStack(c) => game.find_deck_card(c),
+            _ => None,
+        }
+    }
+}
+
+/// `DS A♥`, the alternate flag (`{:#}`) is passed to the card
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::DeckStack(c) => write!(f, "DS {c}"),
-            Self::PileStack(c) => write!(f, "PS {c}"),
-            Self::DeckPile(c) => write!(f, "DP {c}"),
-            Self::StackPile(c) => write!(f, "SP {c}"),
-            Self::Reveal(c) => write!(f, "R {c}"),
-        }
+        let (kind, c) = match self {
+            Self::DeckStack(c) => ("DS", c),
+            Self::PileStack(c) => ("PS", c),
+            Self::DeckPile(c) => ("DP", c),
+            Self::StackPile(c) => ("SP", c),
+            Self::Reveal(c) => ("R", c),
+        };
+        write!(f, "{kind} ")?;
+        c.fmt(f)
     }
 }
 
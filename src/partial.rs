@@ -4,14 +4,21 @@
 //! helpers for filling unknown cards randomly as well as computing simple
 //! probability estimates for hidden columns.
 
+use core::cmp::Ordering;
+use core::num::NonZeroU8;
+
+use rand::rngs::SmallRng;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 
-use crate::card::{Card, N_CARDS};
+use crate::card::{Card, N_CARDS, N_RANKS, N_SUITS};
 use crate::shuffler::CardDeck;
-use crate::standard::{PileVec, StandardSolitaire};
+use crate::moves::Move;
+use crate::stack::Foundation;
+use crate::standard::{HiddenVec, InvalidMove, MoveResult, PileVec, StandardSolitaire};
 use crate::state::Solitaire;
-use crate::deck::Drawable;
+use crate::deck::{Deck, Drawable};
+use crate::shape::{N_DECK_CARDS, N_PILES};
 
 extern crate alloc;
 use alloc::vec;
@@ -19,7 +26,7 @@ use alloc::vec::Vec;
 use alloc::collections::BTreeSet;
 
 /// Representation of a single tableau column with partially known cards.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PartialColumn {
     /// Hidden cards from top to bottom. `None` represents an unknown card.
     pub hidden: Vec<Option<Card>>,
@@ -33,14 +40,226 @@ impl PartialColumn {
     pub fn hidden_len(&self) -> usize {
         self.hidden.iter().filter(|c| c.is_none()).count()
     }
+
+    /// The top visible card, the one cards are stacked on
+    #[must_use]
+    pub fn top_visible(&self) -> Option<Card> {
+        self.visible.last().copied()
+    }
+
+    /// Whether `card` can be put on this column: on a top card of the other
+    /// color and one rank higher, or a king into an empty column
+    ///
+    /// A column with hidden cards but no visible card accepts nothing until
+    /// the next card is revealed.
+    #[must_use]
+    pub fn accepts(&self, card: Card) -> bool {
+        card.go_after(self.top_visible()) && (!self.visible.is_empty() || self.hidden.is_empty())
+    }
 }
 
 /// Representation of a partial Klondike state.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PartialState {
     pub columns: [PartialColumn; 7],
     pub deck: Vec<Option<Card>>, // top of deck is the end of the vec
+    /// Number of deck cards drawn in the current pass: the waste is
+    /// `deck[..deck_offset]`, its last card being the current one, and the
+    /// stock is the rest
+    pub deck_offset: u8,
     pub draw_step: u8,
+    /// Number of times the deck can still be redealt, `None` when unlimited
+    pub redeal_limit: Option<u8>,
+    /// The cards on the foundation, they are known
+    pub foundations: Foundation,
+}
+
+/// Error returned by [`PartialStateBuilder::build`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The number of columns given is not `N_PILES`.
+    ColumnCount(usize),
+    /// A column has more visible cards than a pile can hold.
+    ColumnTooLong(usize),
+    /// The draw step must be at least one.
+    ZeroDrawStep,
+    /// A card on the foundation is also in a column or the deck.
+    FoundationCard(Card),
+    /// More cards are drawn than the deck holds.
+    DeckOffset(u8),
+}
+
+/// Error returned by [`PartialState::merge_observation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObsError {
+    /// There is no hidden card at this column and position.
+    NoSuchSlot,
+    /// The slot is already known to hold another card.
+    SlotKnown(Card),
+    /// The card is already known to be somewhere else.
+    CardKnown,
+}
+
+/// Error returned when the unknown cards of a [`PartialState`] can't be filled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillError {
+    /// There are more unknown cards than cards not yet placed.
+    NotEnoughCards,
+    /// The state holds more than `N_CARDS` cards.
+    TooManyCards,
+    /// A card on the foundation is also in a column or the deck.
+    FoundationCard,
+    /// The draw step must be at least one.
+    ZeroDrawStep,
+    /// The filled board breaks the rules, see [`StandardSolitaire::validate`].
+    InvalidBoard,
+}
+
+/// Seed of a completion drawn by [`PartialState::sample_completion`], given
+/// back to [`PartialState::fill_from_seed`] to rebuild the same board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CompletionSeed(pub u64);
+
+/// Byte written by [`PartialState::to_bytes`] for an unknown card.
+pub const UNKNOWN_BYTE: u8 = 0xFF;
+
+/// Error returned by [`PartialState::from_bytes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes end in the middle of the state.
+    Truncated,
+    /// There are bytes left after the state.
+    TrailingBytes,
+    /// A byte is neither a card nor [`UNKNOWN_BYTE`], or a visible card is unknown.
+    InvalidCard(u8),
+    /// A column has more visible cards than a pile can hold.
+    ColumnTooLong(usize),
+    /// The draw step must be at least one.
+    ZeroDrawStep,
+    /// A foundation holds more than `N_RANKS` cards.
+    InvalidFoundation,
+    /// More cards are drawn than the deck holds.
+    InvalidDeckOffset,
+}
+
+/// Builder for [`PartialState`], mostly useful to set up positions in tests.
+#[derive(Clone, Debug)]
+pub struct PartialStateBuilder {
+    columns: Vec<(Vec<Option<Card>>, Vec<Card>)>,
+    deck: Vec<Option<Card>>,
+    deck_offset: u8,
+    draw_step: u8,
+    redeal_limit: Option<u8>,
+    foundations: Foundation,
+}
+
+impl Default for PartialStateBuilder {
+    fn default() -> Self {
+        Self {
+            columns: Vec::new(),
+            deck: Vec::new(),
+            deck_offset: 0,
+            draw_step: 1,
+            redeal_limit: None,
+            foundations: Foundation::default(),
+        }
+    }
+}
+
+impl PartialStateBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a column with its hidden cards (top to bottom) and visible cards
+    /// (bottom to top).
+    #[must_use]
+    pub fn column(mut self, hidden: &[Option<Card>], visible: &[Card]) -> Self {
+        self.columns.push((hidden.to_vec(), visible.to_vec()));
+        self
+    }
+
+    /// Set the deck, the top of the deck being the last card.
+    #[must_use]
+    pub fn deck(mut self, deck: &[Option<Card>]) -> Self {
+        self.deck = deck.to_vec();
+        self
+    }
+
+    /// Set the number of deck cards already drawn (defaults to 0).
+    #[must_use]
+    pub const fn deck_offset(mut self, deck_offset: u8) -> Self {
+        self.deck_offset = deck_offset;
+        self
+    }
+
+    /// Set the draw step (defaults to 1).
+    #[must_use]
+    pub const fn draw_step(mut self, draw_step: u8) -> Self {
+        self.draw_step = draw_step;
+        self
+    }
+
+    /// Set the number of redeals left (defaults to unlimited).
+    #[must_use]
+    pub const fn redeal_limit(mut self, redeal_limit: Option<u8>) -> Self {
+        self.redeal_limit = redeal_limit;
+        self
+    }
+
+    /// Set the cards on the foundation (defaults to none).
+    #[must_use]
+    pub const fn foundations(mut self, foundations: Foundation) -> Self {
+        self.foundations = foundations;
+        self
+    }
+
+    /// # Errors
+    ///
+    /// Return a `BuildError` when there isn't exactly `N_PILES` columns, a
+    /// column is too long, the draw step is zero, more cards are drawn than
+    /// the deck holds or a foundation card is also in a column or the deck.
+    pub fn build(self) -> Result<PartialState, BuildError> {
+        if self.columns.len() != N_PILES as usize {
+            return Err(BuildError::ColumnCount(self.columns.len()));
+        }
+        if self.draw_step == 0 {
+            return Err(BuildError::ZeroDrawStep);
+        }
+        if usize::from(self.deck_offset) > self.deck.len() {
+            return Err(BuildError::DeckOffset(self.deck_offset));
+        }
+        let in_play = self.columns.iter().flat_map(|(hidden, visible)| {
+            hidden.iter().flatten().chain(visible)
+        });
+        if let Some(&card) = in_play
+            .chain(self.deck.iter().flatten())
+            .find(|c| c.rank() < self.foundations.get(c.suit()))
+        {
+            return Err(BuildError::FoundationCard(card));
+        }
+
+        let mut columns: [PartialColumn; N_PILES as usize] =
+            core::array::from_fn(|_| PartialColumn {
+                hidden: Vec::new(),
+                visible: PileVec::new(),
+            });
+        for (i, (hidden, visible)) in self.columns.into_iter().enumerate() {
+            columns[i].hidden = hidden;
+            columns[i].visible =
+                PileVec::try_from(&visible[..]).map_err(|_| BuildError::ColumnTooLong(i))?;
+        }
+
+        Ok(PartialState {
+            columns,
+            deck: self.deck,
+            deck_offset: self.deck_offset,
+            draw_step: self.draw_step,
+            redeal_limit: self.redeal_limit,
+            foundations: self.foundations,
+        })
+    }
 }
 
 impl From<&StandardSolitaire> for PartialState {
@@ -53,14 +272,20 @@ impl From<&StandardSolitaire> for PartialState {
         Self {
             columns,
             deck,
+            deck_offset: g.get_deck().get_offset(),
             draw_step: g.get_deck().draw_step().get(),
+            redeal_limit: None,
+            foundations: (*g.get_stack()).into(),
         }
     }
 }
 
 impl PartialState {
     /// Create a partial state from a full `Solitaire` state where hidden cards
-    /// remain unknown.
+    /// remain unknown, as well as the deck cards that can't be drawn in the
+    /// current deck cycle ([`Drawable::None`]). This is what a player sees.
+    ///
+    /// See [`PartialState::from_known`] to keep every card.
     #[must_use]
     pub fn from_blind(sol: &Solitaire) -> Self {
         let hidden = sol.get_hidden().to_piles();
@@ -77,35 +302,501 @@ impl PartialState {
         Self {
             columns,
             deck,
+            deck_offset: sol.get_deck().get_offset(),
             draw_step: sol.get_deck().draw_step().get(),
+            redeal_limit: None,
+            foundations: (*sol.get_stack()).into(),
+        }
+    }
+
+    /// Create a partial state from a full `Solitaire` state keeping every card
+    /// known, including the hidden cards and the whole deck.
+    ///
+    /// This is the full-information counterpart of
+    /// [`PartialState::from_blind`], with the same card layout.
+    #[must_use]
+    pub fn from_known(sol: &Solitaire) -> Self {
+        let hidden = sol.get_hidden().to_piles();
+        let visible = sol.compute_visible_piles();
+        let columns: [PartialColumn; 7] = core::array::from_fn(|i| PartialColumn {
+            hidden: hidden[i].iter().map(|&c| Some(c)).collect(),
+            visible: visible[i].clone(),
+        });
+        let deck = sol.get_deck().iter_all().map(|(_, c, _)| Some(c)).collect();
+        Self {
+            columns,
+            deck,
+            deck_offset: sol.get_deck().get_offset(),
+            draw_step: sol.get_deck().draw_step().get(),
+            redeal_limit: None,
+            foundations: (*sol.get_stack()).into(),
+        }
+    }
+
+    /// Same as [`PartialState::from_blind`], keeping known the cards of
+    /// `revealed` it would hide, e.g. to measure how much knowing them helps.
+    /// The other cards of `revealed` are ignored.
+    #[must_use]
+    pub fn blind_except(sol: &Solitaire, revealed: &[Card]) -> Self {
+        let mut state = Self::from_known(sol);
+        let blind = Self::from_blind(sol);
+        let hide = |known: &mut Option<Card>, blind: &Option<Card>| {
+            if blind.is_none() && known.is_some_and(|c| !revealed.contains(&c)) {
+                *known = None;
+            }
+        };
+        for (col, blind_col) in state.columns.iter_mut().zip(&blind.columns) {
+            col.hidden.iter_mut().zip(&blind_col.hidden).for_each(|(k, b)| hide(k, b));
+        }
+        state.deck.iter_mut().zip(&blind.deck).for_each(|(k, b)| hide(k, b));
+        state
+    }
+
+    /// The draw step, which [`PartialStateBuilder::build`] ensures is not zero,
+    /// `None` when a zero draw step was set by hand
+    fn nonzero_draw_step(&self) -> Option<NonZeroU8> {
+        NonZeroU8::new(self.draw_step)
+    }
+
+    /// Number of unknown cards in the columns and the deck
+    #[must_use]
+    pub fn unknown_count(&self) -> usize {
+        let hidden = self.columns.iter().flat_map(|c| &c.hidden);
+        hidden.chain(&self.deck).filter(|c| c.is_none()).count()
+    }
+
+    /// The cards known nowhere in the state, one of them is behind each
+    /// unknown card, grouped by suit
+    ///
+    /// The same cards as [`StateAnalysis::remaining_cards`](crate::analysis::StateAnalysis::remaining_cards).
+    #[must_use]
+    pub fn remaining_by_suit(&self) -> [Vec<Card>; N_SUITS as usize] {
+        core::array::from_fn(|suit| {
+            #[allow(clippy::cast_possible_truncation)]
+            Card::all_of_suit(suit as u8).filter(|&c| !self.contains(c)).collect()
+        })
+    }
+
+    /// Same as [`Self::remaining_by_suit`] grouped by rank, from the aces
+    #[must_use]
+    pub fn remaining_by_rank(&self) -> [Vec<Card>; N_RANKS as usize] {
+        core::array::from_fn(|rank| {
+            #[allow(clippy::cast_possible_truncation)]
+            (0..N_SUITS).map(|suit| Card::new(rank as u8, suit)).filter(|&c| !self.contains(c)).collect()
+        })
+    }
+
+    /// Whether each card of [`Self::deck`] can still be drawn
+    ///
+    /// The stock is dealt from [`Self::deck_offset`]. Without any redeal left
+    /// only the current card and the cards shown by dealing `draw_step` cards
+    /// at a time can be reached, the others are locked under them. A redeal
+    /// lets the moves of the previous pass shift the groups, so any card can
+    /// be drawn then. Nothing can be drawn with a zero draw step.
+    #[must_use]
+    pub fn drawable_deck_cards(&self) -> Vec<bool> {
+        let Some(step) = self.nonzero_draw_step() else {
+            return vec![false; self.deck.len()];
+        };
+        let step = usize::from(step.get());
+        let offset = usize::from(self.deck_offset);
+        let len = self.deck.len();
+        (0..len)
+            .map(|i| {
+                self.redeal_limit != Some(0)
+                    || i + 1 == offset
+                    || (i >= offset && ((i + 1 - offset) % step == 0 || i + 1 == len))
+            })
+            .collect()
+    }
+
+    /// Number of draws bringing the known deck card `card` up as the current
+    /// card, without playing any card in between
+    ///
+    /// The stock is dealt from [`Self::deck_offset`] and redealt when empty, as
+    /// long as [`Self::redeal_limit`] allows it, as in
+    /// [`Deck::offset`](crate::deck::Deck::offset). Return `None` when the
+    /// card isn't known to be in the deck, is never on top of a group of
+    /// `draw_step` cards or the draw step is zero.
+    #[must_use]
+    pub fn plan_to_draw(&self, card: Card) -> Option<u8> {
+        let step = usize::from(self.nonzero_draw_step()?.get());
+        let shown = self.deck_position(card)? + 1;
+        let len = self.deck.len();
+        let mut offset = usize::from(self.deck_offset);
+        let mut redeals = self.redeal_limit;
+        // every offset comes back after two passes
+        for draws in 0..=2 * (len + 1) {
+            if offset == shown {
+                return u8::try_from(draws).ok();
+            }
+            if offset < len {
+                offset = len.min(offset + step);
+            } else {
+                redeals = match redeals {
+                    Some(0) => return None,
+                    r => r.map(|r| r - 1),
+                };
+                offset = 0;
+            }
         }
+        None
+    }
+
+    /// Whether `card` is known to be locked in the deck, see
+    /// [`Self::drawable_deck_cards`]
+    #[must_use]
+    pub fn is_deck_card_locked(&self, card: Card) -> bool {
+        self.deck_position(card)
+            .is_some_and(|pos| !self.drawable_deck_cards()[pos])
+    }
+
+    /// Compact binary encoding: the draw step, the redeal limit and the
+    /// height of the foundation of every suit, then for every column the number
+    /// of hidden cards, the hidden cards, the number of visible cards and the
+    /// visible cards, then the length of the deck, its cards and the number of
+    /// cards drawn
+    ///
+    /// A card is its mask index, an unknown card and an unlimited redeal are
+    /// [`UNKNOWN_BYTE`].
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn encode(c: Option<Card>) -> u8 {
+            c.map_or(UNKNOWN_BYTE, Card::mask_index)
+        }
+
+        let mut res = vec![self.draw_step, self.redeal_limit.unwrap_or(UNKNOWN_BYTE)];
+        res.extend((0..N_SUITS).map(|suit| self.foundations.get(suit)));
+        #[allow(clippy::cast_possible_truncation)]
+        for col in &self.columns {
+            res.push(col.hidden.len() as u8);
+            res.extend(col.hidden.iter().map(|&c| encode(c)));
+            res.push(col.visible.len() as u8);
+            res.extend(col.visible.iter().map(|&c| encode(Some(c))));
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        res.push(self.deck.len() as u8);
+        res.extend(self.deck.iter().map(|&c| encode(c)));
+        res.push(self.deck_offset);
+        res
+    }
+
+    /// Decode the bytes written by [`Self::to_bytes`]
+    ///
+    /// # Errors
+    ///
+    /// Return a `DecodeError` when the bytes don't hold exactly one state
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        fn next(it: &mut impl Iterator<Item = u8>) -> Result<u8, DecodeError> {
+            it.next().ok_or(DecodeError::Truncated)
+        }
+        // a length followed by the cards
+        fn cards(it: &mut impl Iterator<Item = u8>) -> Result<Vec<Option<Card>>, DecodeError> {
+            let len = next(it)?;
+            (0..len)
+                .map(|_| match next(it)? {
+                    UNKNOWN_BYTE => Ok(None),
+                    b if b < N_CARDS => Ok(Some(Card::from_mask_index(b))),
+                    b => Err(DecodeError::InvalidCard(b)),
+                })
+                .collect()
+        }
+
+        let mut it = bytes.iter().copied();
+        let draw_step = next(&mut it)?;
+        if draw_step == 0 {
+            return Err(DecodeError::ZeroDrawStep);
+        }
+        let redeal_limit = Some(next(&mut it)?).filter(|&b| b != UNKNOWN_BYTE);
+        let mut foundations = Foundation::default();
+        for suit in 0..N_SUITS {
+            let height = next(&mut it)?;
+            if height > N_RANKS {
+                return Err(DecodeError::InvalidFoundation);
+            }
+            for rank in 0..height {
+                foundations.push(Card::new(rank, suit));
+            }
+        }
+        let mut columns: [PartialColumn; N_PILES as usize] = core::array::from_fn(|_| PartialColumn {
+            hidden: Vec::new(),
+            visible: PileVec::new(),
+        });
+        for (i, col) in columns.iter_mut().enumerate() {
+            col.hidden = cards(&mut it)?;
+            for c in cards(&mut it)? {
+                let c = c.ok_or(DecodeError::InvalidCard(UNKNOWN_BYTE))?;
+                col.visible.try_push(c).map_err(|_| DecodeError::ColumnTooLong(i))?;
+            }
+        }
+        let deck = cards(&mut it)?;
+        let deck_offset = next(&mut it)?;
+        if usize::from(deck_offset) > deck.len() {
+            return Err(DecodeError::InvalidDeckOffset);
+        }
+
+        if it.next().is_some() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(Self {
+            columns,
+            deck,
+            deck_offset,
+            draw_step,
+            redeal_limit,
+            foundations,
+        })
+    }
+
+    /// The same state with its columns sorted by visible cards, then number
+    /// of hidden cards, then hidden cards
+    ///
+    /// Permuting the columns doesn't change the game, so all the permutations
+    /// of a state share this representative, which makes it a good key to
+    /// deduplicate states. Only use it where the column identity doesn't
+    /// matter: an index given to [`Self::merge_observation`] or the column
+    /// a card goes to in [`Self::apply_move`] refers to the original order.
+    #[must_use]
+    pub fn canonical(&self) -> Self {
+        fn index(c: &Option<Card>) -> u8 {
+            c.map_or(UNKNOWN_BYTE, Card::mask_index)
+        }
+
+        let mut res = self.clone();
+        res.columns.sort_by(|a, b| {
+            a.visible
+                .iter()
+                .map(|&c| c.mask_index())
+                .cmp(b.visible.iter().map(|&c| c.mask_index()))
+                .then(a.hidden.len().cmp(&b.hidden.len()))
+                .then(a.hidden.iter().map(index).cmp(b.hidden.iter().map(index)))
+        });
+        res
+    }
+
+    /// Whether the two states are the same up to the order of their columns,
+    /// i.e. have the same [`Self::canonical`] form
+    #[must_use]
+    pub fn eq_unordered(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+
+    /// Whether every card of the state is known
+    #[must_use]
+    pub fn is_fully_known(&self) -> bool {
+        self.unknown_count() == 0
+    }
+
+    /// Record that the hidden card at index `position` of
+    /// [`PartialColumn::hidden`] in `column` is `card`.
+    ///
+    /// # Errors
+    ///
+    /// Return an `ObsError` when the slot doesn't exist, is known to be a
+    /// different card, or `card` is already known to be elsewhere. The state
+    /// is left untouched in that case.
+    pub fn merge_observation(
+        &mut self,
+        column: usize,
+        position: usize,
+        card: Card,
+    ) -> Result<(), ObsError> {
+        let slot = self
+            .columns
+            .get(column)
+            .and_then(|c| c.hidden.get(position))
+            .ok_or(ObsError::NoSuchSlot)?;
+        match *slot {
+            Some(known) if known == card => return Ok(()),
+            Some(known) => return Err(ObsError::SlotKnown(known)),
+            None => {}
+        }
+
+        if self.contains(card) {
+            return Err(ObsError::CardKnown);
+        }
+
+        self.columns[column].hidden[position] = Some(card);
+        Ok(())
+    }
+
+    /// Play `m` on the known cards, without filling the unknown ones.
+    ///
+    /// The hidden cards stay where they are: emptying a column reveals its
+    /// last hidden card when it is known, an unknown one stays hidden (and the
+    /// column without visible card) until it is set with
+    /// [`PartialState::merge_observation`]. The moved cards go to the first
+    /// column accepting them.
+    ///
+    /// # Errors
+    ///
+    /// Return `InvalidMove` when the move can't be played on the known cards,
+    /// the state is left untouched in that case.
+    pub fn apply_move(&mut self, m: Move) -> MoveResult<()> {
+        match m {
+            Move::DeckPile(c) => {
+                let pos = self.deck_position(c).ok_or(InvalidMove)?;
+                let to = self.free_column(c, None).ok_or(InvalidMove)?;
+                self.draw(pos);
+                self.columns[to].visible.push(c);
+            }
+            Move::DeckStack(c) => {
+                let pos = self.deck_position(c).ok_or(InvalidMove)?;
+                if !self.foundations.push(c) {
+                    return Err(InvalidMove);
+                }
+                self.draw(pos);
+            }
+            Move::StackPile(c) => {
+                if self.foundations.top(c.suit()) != Some(c) {
+                    return Err(InvalidMove);
+                }
+                let to = self.free_column(c, None).ok_or(InvalidMove)?;
+                self.foundations.pop(c.suit());
+                self.columns[to].visible.push(c);
+            }
+            Move::Reveal(c) => {
+                let from = self
+                    .columns
+                    .iter()
+                    .position(|col| col.visible.first() == Some(&c))
+                    .ok_or(InvalidMove)?;
+                let to = self.free_column(c, Some(from)).ok_or(InvalidMove)?;
+                let cards = core::mem::take(&mut self.columns[from].visible);
+                self.columns[to].visible.extend(cards);
+                self.reveal(from);
+            }
+            Move::PileStack(c) => {
+                let (from, idx) = self
+                    .columns
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, col)| Some((i, col.visible.iter().position(|&v| v == c)?)))
+                    .ok_or(InvalidMove)?;
+                if !self.foundations.can_accept(c) {
+                    return Err(InvalidMove);
+                }
+                // the cards on top have to move out of the way first
+                if let Some(&above) = self.columns[from].visible.get(idx + 1) {
+                    let to = self.free_column(above, Some(from)).ok_or(InvalidMove)?;
+                    let cards: PileVec = self.columns[from].visible.drain(idx + 1..).collect();
+                    self.columns[to].visible.extend(cards);
+                }
+                self.columns[from].visible.pop();
+                self.foundations.push(c);
+                self.reveal(from);
+            }
+        }
+        Ok(())
+    }
+
+    fn contains(&self, card: Card) -> bool {
+        self.columns
+            .iter()
+            .any(|c| c.visible.contains(&card) || c.hidden.contains(&Some(card)))
+            || self.deck.contains(&Some(card))
+            || card.rank() < self.foundations.get(card.suit())
+    }
+
+    /// The cards on the foundation, suit by suit from the ace up
+    pub fn foundation_cards(&self) -> impl Iterator<Item = Card> + '_ {
+        (0..N_SUITS)
+            .flat_map(|suit| (0..self.foundations.get(suit)).map(move |rank| Card::new(rank, suit)))
+    }
+
+    fn deck_position(&self, card: Card) -> Option<usize> {
+        self.deck.iter().position(|&c| c == Some(card))
+    }
+
+    // the cards before it are drawn, as in `Deck::draw`
+    fn draw(&mut self, pos: usize) {
+        self.deck.remove(pos);
+        #[allow(clippy::cast_possible_truncation)]
+        let offset = pos as u8;
+        self.deck_offset = offset;
+    }
+
+    // a column waiting for an unknown card to be revealed doesn't accept anything
+    fn free_column(&self, card: Card, except: Option<usize>) -> Option<usize> {
+        self.columns
+            .iter()
+            .enumerate()
+            .position(|(i, col)| Some(i) != except && col.accepts(card))
+    }
+
+    fn reveal(&mut self, column: usize) {
+        let col = &mut self.columns[column];
+        if col.visible.is_empty() {
+            if let Some(&Some(c)) = col.hidden.last() {
+                col.hidden.pop();
+                col.visible.push(c);
+            }
+        }
+    }
+
+    /// Check that `sol` is a valid completion of this partial state: the
+    /// visible cards, the known hidden cards and the known deck cards all
+    /// match, while the unknown cards can be anything.
+    #[must_use]
+    pub fn compatible_with(&self, sol: &Solitaire) -> bool {
+        fn matches(known: &[Option<Card>], full: &[Card]) -> bool {
+            known.len() == full.len()
+                && known.iter().zip(full).all(|(k, c)| k.is_none_or(|k| k == *c))
+        }
+
+        if !sol.is_valid()
+            || sol.get_deck().draw_step().get() != self.draw_step
+            || sol.get_deck().get_offset() != self.deck_offset
+            || Foundation::from(*sol.get_stack()) != self.foundations
+        {
+            return false;
+        }
+
+        let hidden = sol.get_hidden().to_piles();
+        let visible = sol.compute_visible_piles();
+        let columns_match = self
+            .columns
+            .iter()
+            .zip(hidden.iter().zip(&visible))
+            .all(|(col, (h, v))| col.visible == *v && matches(&col.hidden, h));
+
+        let deck: Vec<Card> = sol.get_deck().iter_all().map(|(_, c, _)| c).collect();
+        columns_match && matches(&self.deck, &deck)
     }
 
     /// Fill the unknown cards using a random permutation of the remaining
     /// cards. The returned `StandardSolitaire` can then be solved using the
     /// existing engine.
-    #[must_use]
-    pub fn fill_unknowns_randomly<R: Rng>(&self, rng: &mut R) -> StandardSolitaire {
+    ///
+    /// The game keeps the board of the state: its columns, its deck drawn up
+    /// to [`Self::deck_offset`] and its foundations. A state holding fewer
+    /// cards, e.g. written by hand, is dealt afresh from its cards in order,
+    /// the missing ones last.
+    ///
+    /// # Errors
+    ///
+    /// [`FillError`] when the known cards don't leave exactly enough cards
+    /// for the unknown ones, so the state is inconsistent, the filled board
+    /// breaks the rules or the draw step is zero.
+    pub fn fill_unknowns_randomly<R: Rng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<StandardSolitaire, FillError> {
         let mut used = BTreeSet::new();
         for col in &self.columns {
             for c in &col.visible {
                 used.insert(c.mask_index());
             }
-            for c in &col.hidden {
-                if let Some(card) = c {
-                    used.insert(card.mask_index());
-                }
-            }
-        }
-        for c in &self.deck {
-            if let Some(card) = c {
+            for card in col.hidden.iter().flatten() {
                 used.insert(card.mask_index());
             }
         }
+        for card in self.deck.iter().flatten().copied().chain(self.foundation_cards()) {
+            used.insert(card.mask_index());
+        }
 
-        let mut remaining: Vec<Card> = (0..N_CARDS)
-            .filter(|i| !used.contains(i))
-            .map(Card::from_mask_index)
+        let mut remaining: Vec<Card> = Card::all()
+            .filter(|c| !used.contains(&c.mask_index()))
             .collect();
         remaining.shuffle(rng);
         let mut rem_iter = remaining.into_iter();
@@ -117,7 +808,7 @@ impl PartialState {
                 if let Some(c) = h {
                     cards.push(*c);
                 } else {
-                    cards.push(rem_iter.next().unwrap());
+                    cards.push(rem_iter.next().ok_or(FillError::NotEnoughCards)?);
                 }
             }
             for &v in &col.visible {
@@ -125,48 +816,44 @@ impl PartialState {
             }
         }
         for c in &self.deck {
-            if let Some(card) = c.clone() {
+            if let Some(card) = *c {
                 cards.push(card);
             } else {
-                cards.push(rem_iter.next().unwrap());
+                cards.push(rem_iter.next().ok_or(FillError::NotEnoughCards)?);
             }
         }
-        while cards.len() < N_CARDS as usize {
-            cards.push(rem_iter.next().unwrap());
+        while cards.len() + usize::from(self.foundations.len()) < N_CARDS as usize {
+            cards.push(rem_iter.next().ok_or(FillError::NotEnoughCards)?);
         }
-        let mut array: CardDeck = [Card::DEFAULT; N_CARDS as usize];
-        array.copy_from_slice(&cards);
-        use core::num::NonZeroU8;
-        StandardSolitaire::new(&array, NonZeroU8::new(self.draw_step).unwrap())
+        cards.extend(self.foundation_cards());
+        self.deal_filled(&cards)
     }
 
     /// Fill the unknown cards using weighted probabilities for each column.
-    #[must_use]
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::fill_unknowns_randomly`].
     pub fn fill_unknowns_weighted<R: Rng>(
         &self,
         probs: &[Vec<(Card, f64)>],
         rng: &mut R,
-    ) -> StandardSolitaire {
+    ) -> Result<StandardSolitaire, FillError> {
         let mut used = BTreeSet::new();
         for col in &self.columns {
             for c in &col.visible {
                 used.insert(c.mask_index());
             }
-            for c in &col.hidden {
-                if let Some(card) = c {
-                    used.insert(card.mask_index());
-                }
-            }
-        }
-        for c in &self.deck {
-            if let Some(card) = c {
+            for card in col.hidden.iter().flatten() {
                 used.insert(card.mask_index());
             }
         }
+        for card in self.deck.iter().flatten().copied().chain(self.foundation_cards()) {
+            used.insert(card.mask_index());
+        }
 
-        let mut remaining: Vec<Card> = (0..N_CARDS)
-            .filter(|i| !used.contains(i))
-            .map(Card::from_mask_index)
+        let mut remaining: Vec<Card> = Card::all()
+            .filter(|c| !used.contains(&c.mask_index()))
             .collect();
 
         let mut cards = Vec::with_capacity(N_CARDS as usize);
@@ -175,6 +862,9 @@ impl PartialState {
                 if let Some(c) = h {
                     cards.push(*c);
                 } else {
+                    if remaining.is_empty() {
+                        return Err(FillError::NotEnoughCards);
+                    }
                     let weights: Vec<f64> = remaining
                         .iter()
                         .map(|c| {
@@ -208,21 +898,157 @@ impl PartialState {
             }
         }
         for c in &self.deck {
-            if let Some(card) = c.clone() {
+            if let Some(card) = *c {
                 cards.push(card);
             } else {
+                if remaining.is_empty() {
+                    return Err(FillError::NotEnoughCards);
+                }
                 let idx = rng.random_range(0..remaining.len());
                 cards.push(remaining.remove(idx));
             }
         }
-        while cards.len() < N_CARDS as usize {
+        while cards.len() + usize::from(self.foundations.len()) < N_CARDS as usize {
+            if remaining.is_empty() {
+                return Err(FillError::NotEnoughCards);
+            }
             let idx = rng.random_range(0..remaining.len());
             cards.push(remaining.remove(idx));
         }
+        cards.extend(self.foundation_cards());
+        self.deal_filled(&cards)
+    }
+
+    fn deal_filled(&self, cards: &[Card]) -> Result<StandardSolitaire, FillError> {
         let mut array: CardDeck = [Card::DEFAULT; N_CARDS as usize];
-        array.copy_from_slice(&cards);
-        use core::num::NonZeroU8;
-        StandardSolitaire::new(&array, NonZeroU8::new(self.draw_step).unwrap())
+        match cards.len().cmp(&array.len()) {
+            Ordering::Less => return Err(FillError::NotEnoughCards),
+            Ordering::Greater => return Err(FillError::TooManyCards),
+            Ordering::Equal => {}
+        }
+        array.copy_from_slice(cards);
+        // the foundation cards sit at the bottom of the deck and nothing covers them
+        let n_in_play = N_CARDS as usize - usize::from(self.foundations.len());
+        let (in_play, on_foundation) = array.split_at(n_in_play);
+        if in_play.iter().any(|c| on_foundation.contains(c)) {
+            return Err(FillError::FoundationCard);
+        }
+        let draw_step = self.nonzero_draw_step().ok_or(FillError::ZeroDrawStep)?;
+
+        let n_slots = self.columns.iter().map(|c| c.hidden.len() + c.visible.len()).sum::<usize>();
+        if n_slots + self.deck.len() < n_in_play {
+            // a state short of cards, e.g. written by hand, is dealt afresh
+            let mut game = StandardSolitaire::new(&array, draw_step);
+            if !game.force_foundations(self.foundations) {
+                return Err(FillError::FoundationCard);
+            }
+            return Ok(game);
+        }
+
+        // the cards are in the order of the slots they fill
+        let mut rest = in_play;
+        let mut take = |n: usize| {
+            let (head, tail) = rest.split_at(n);
+            rest = tail;
+            head
+        };
+        let mut hidden_piles: [HiddenVec; N_PILES as usize] = Default::default();
+        let mut piles: [PileVec; N_PILES as usize] = Default::default();
+        for (i, col) in self.columns.iter().enumerate() {
+            hidden_piles[i] = take(col.hidden.len()).try_into().map_err(|_| FillError::InvalidBoard)?;
+            piles[i] = take(col.visible.len()).try_into().map_err(|_| FillError::InvalidBoard)?;
+        }
+        let deck = take(self.deck.len());
+        if deck.len() > N_DECK_CARDS as usize || usize::from(self.deck_offset) > deck.len() {
+            return Err(FillError::InvalidBoard);
+        }
+        let deck = Deck::from_cards(deck, draw_step, self.deck_offset);
+        let game = StandardSolitaire::from_parts(self.foundations.into(), hidden_piles, piles, deck);
+        game.validate().map_err(|_| FillError::InvalidBoard)?;
+        Ok(game)
+    }
+
+    /// Sample a weighted completion of the unknown cards along with the seed
+    /// reproducing it
+    ///
+    /// Only a seed is drawn from `rng`, the board is the one
+    /// [`Self::fill_from_seed`] builds from it.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::fill_unknowns_randomly`].
+    pub fn sample_completion<R: Rng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(StandardSolitaire, CompletionSeed), FillError> {
+        let seed = CompletionSeed(rng.random());
+        Ok((self.fill_from_seed(seed)?, seed))
+    }
+
+    /// Rebuild the completion drawn by [`Self::sample_completion`] on the
+    /// same state
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Self::fill_unknowns_randomly`].
+    pub fn fill_from_seed(&self, seed: CompletionSeed) -> Result<StandardSolitaire, FillError> {
+        let probs = self.column_probabilities();
+        self.fill_unknowns_weighted(&probs, &mut SmallRng::seed_from_u64(seed.0))
+    }
+
+    /// Lazily sample weighted completions of the unknown cards
+    ///
+    /// The column probabilities are computed once and shared by every sample,
+    /// each item is the same as a call to [`Self::fill_unknowns_weighted`].
+    pub fn sample_completions<'a, R: Rng>(
+        &'a self,
+        rng: &'a mut R,
+    ) -> impl Iterator<Item = Result<StandardSolitaire, FillError>> + 'a {
+        let probs = self.column_probabilities();
+        core::iter::repeat_with(move || self.fill_unknowns_weighted(&probs, &mut *rng))
+    }
+
+    /// Average length of the solutions of `samples` completions of the
+    /// unknown cards (see [`Self::sample_completions`]), the completions that
+    /// can't be won left out
+    ///
+    /// `None` when none of them can be won. Every completion is solved to the
+    /// end with [`solve`](crate::solver::solve), so this is only cheap on
+    /// states with few unknown cards.
+    #[must_use]
+    pub fn expected_moves_to_win<R: Rng>(&self, samples: usize, rng: &mut R) -> Option<f64> {
+        let lengths: Vec<usize> = self
+            .sample_completions(rng)
+            .take(samples)
+            .filter_map(|filled| {
+                let mut game: Solitaire = (&filled.ok()?).into();
+                crate::solver::solve(&mut game).1.map(|history| history.len())
+            })
+            .collect();
+        if lengths.is_empty() {
+            return None;
+        }
+        Some(lengths.iter().sum::<usize>() as f64 / lengths.len() as f64)
+    }
+
+    /// Probability that a draw of `card` from the deck is possible
+    ///
+    /// 1 when the card is known to be in the deck, 0 when it's known to be
+    /// elsewhere. Otherwise the card is one of the cards not known anywhere,
+    /// each as likely to be behind any unknown card, as in
+    /// [`Self::column_probabilities`], so it's in the deck with the share of
+    /// the unknown cards lying there.
+    #[must_use]
+    pub fn deck_card_probability(&self, card: Card) -> f64 {
+        if self.deck.contains(&Some(card)) {
+            return 1.0;
+        }
+        if self.contains(card) {
+            return 0.0;
+        }
+        let n_unknown = self.deck.iter().filter(|c| c.is_none()).count();
+        let n_remaining = Card::all().filter(|&c| !self.contains(c)).count();
+        n_unknown as f64 / n_remaining as f64
     }
 
     /// Compute simplistic probability estimates for every hidden column.
@@ -250,9 +1076,11 @@ impl PartialState {
                 total_unknown += 1;
             }
         }
-        let remaining: Vec<Card> = (0..N_CARDS)
-            .filter(|i| !used.contains(i))
-            .map(Card::from_mask_index)
+        for card in self.foundation_cards() {
+            used.insert(card.mask_index());
+        }
+        let remaining: Vec<Card> = Card::all()
+            .filter(|c| !used.contains(&c.mask_index()))
             .collect();
         let n_remaining = remaining.len() as f64;
         let mut res = Vec::new();
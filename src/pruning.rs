@@ -75,10 +75,11 @@ impl Pruner for FullPruner {
         }
     }
     fn prune_moves(&self, game: &Solitaire) -> MoveMask {
-        let filter = {
+        let mut filter = {
             let mut filter = match (self.last_move, &self.last_extra) {
                 // Moving the top layer card and leave the pile empty
                 // => Must move another king to fill the empty spot, otherwise it doesn't make sense
+                // the king moves themselves stay allowed, including a `Reveal` of a buried king
                 (Move::Reveal(_), ExtraInfo::RevealEmpty) => MoveMask {
                     pile_stack: !0,
                     deck_stack: !0,
@@ -119,10 +120,20 @@ impl Pruner for FullPruner {
                 // to get the required card to put under 8, but since it doesn't reveal anything, it's not doing it``
                 filter.reveal |= !((mm >> 4) | first_layer);
             }
-            filter
+            filter.combine(&self.cycle.prune_moves(game))
         };
 
-        filter.combine(&self.cycle.prune_moves(game))
+        // moving a lone card off an empty base is only useful to make room for a king,
+        // unless nothing else but taking cards back from the foundation is left
+        let idle = game.idle_empty_reveals();
+        if idle == 0 {
+            return filter;
+        }
+        let left = game.gen_moves::<false>().filter(&filter);
+        if left.pile_stack | left.deck_stack | left.deck_pile | (left.reveal & !idle) != 0 {
+            filter.reveal |= idle;
+        }
+        filter
     }
 }
 
@@ -0,0 +1,38 @@
+//! Shape of a Klondike deal: how the cards are split between the piles and
+//! the deck.
+
+use static_assertions::const_assert_eq;
+
+use crate::card::N_CARDS;
+
+/// Number of tableau piles
+pub const N_PILES: u8 = 7;
+/// Number of cards dealt to the piles, pile `i` getting `i + 1` cards
+pub const N_PILE_CARDS: u8 = N_PILES * (N_PILES + 1) / 2;
+/// Number of face-down cards in the deal, every pile has only its top card up
+pub const N_HIDDEN_CARDS: u8 = N_PILE_CARDS - N_PILES;
+/// Number of cards left in the deck after dealing
+pub const N_DECK_CARDS: u8 = N_CARDS - N_PILE_CARDS;
+
+const_assert_eq!(N_PILE_CARDS + N_DECK_CARDS, N_CARDS);
+const_assert_eq!(N_HIDDEN_CARDS + N_PILES, N_PILE_CARDS);
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use crate::{shuffler::default_shuffle, standard::StandardSolitaire};
+
+    #[test]
+    fn test_deal_shape() {
+        let game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(1).unwrap());
+
+        let hidden: usize = game.get_hidden().iter().map(|h| h.len()).sum();
+        let visible: usize = game.get_piles().iter().map(|p| p.len()).sum();
+        assert_eq!(hidden, N_HIDDEN_CARDS.into());
+        assert_eq!(visible, N_PILES.into());
+        assert_eq!(hidden + visible, N_PILE_CARDS.into());
+        assert_eq!(game.get_deck().len(), N_DECK_CARDS);
+    }
+}
@@ -1,22 +1,43 @@
 use crate::card::{Card, N_CARDS, N_RANKS, N_SUITS};
-use crate::deck::{N_PILES, N_PILE_CARDS};
+use crate::shape::{N_PILES, N_PILE_CARDS};
 use rand::prelude::*;
 use rand_mt::Mt;
-use uint::construct_uint;
-construct_uint! {
-    pub struct U256(4);
+
+// the expansion of `construct_uint` trips lints that we can't fix from here
+#[allow(clippy::manual_div_ceil)]
+mod wide {
+    use uint::construct_uint;
+    construct_uint! {
+        pub struct U256(4);
+    }
 }
+pub use wide::U256;
 
 pub type CardDeck = [Card; N_CARDS as usize];
 
+/// Whether every card appears exactly once in `deck`
+///
+/// The games are dealt from the deck without checking it, an invalid deck
+/// builds a corrupt game.
+#[must_use]
+pub fn is_valid_deck(deck: &CardDeck) -> bool {
+    // 52 cards covering the 52 cards can't have a duplicate
+    deck.iter().fold(0u64, |mask, c| mask | c.mask()) == (1 << N_CARDS) - 1
+}
+
 #[must_use]
 pub fn default_shuffle(seed: u64) -> CardDeck {
-    let mut rng = SmallRng::seed_from_u64(seed);
+    shuffle_with_rng(&mut SmallRng::seed_from_u64(seed))
+}
 
+/// Shuffle the cards with `rng`, [`default_shuffle`] seeding a fresh
+/// [`SmallRng`] instead
+#[must_use]
+pub fn shuffle_with_rng<R: Rng + ?Sized>(rng: &mut R) -> CardDeck {
     #[allow(clippy::cast_possible_truncation)]
     let mut cards: CardDeck =
         core::array::from_fn(|i| Card::new(i as u8 / N_SUITS, i as u8 % N_SUITS));
-    cards.shuffle(&mut rng);
+    cards.shuffle(rng);
     cards
 }
 
@@ -227,6 +248,9 @@ pub fn exact_shuffle(mut seed: U256) -> Option<CardDeck> {
 /// Return None when the `cards` is not a valid `CardDeck` (not a permutation of the valid cards)
 #[must_use]
 pub fn encode_shuffle(mut cards: CardDeck) -> Option<U256> {
+    if !is_valid_deck(&cards) {
+        return None;
+    }
     let mut encode = U256::zero();
     for i in (1..N_CARDS).rev() {
         let card = Card::new(i / N_SUITS, i % N_SUITS);
@@ -266,6 +290,18 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_shuffle_with_rng() {
+        for seed in 0..100 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let cards = shuffle_with_rng(&mut rng);
+            assert!(is_valid_deck(&cards));
+            assert_eq!(cards, default_shuffle(seed));
+            // the same stream keeps dealing new games
+            assert_ne!(shuffle_with_rng(&mut rng), cards);
+        }
+    }
+
     #[test]
     fn test_encode() {
         let mut rng = SmallRng::seed_from_u64(14);
@@ -277,6 +313,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_valid_deck() {
+        for seed in 0..100 {
+            assert!(is_valid_deck(&default_shuffle(seed)));
+            assert!(is_valid_deck(&ks_shuffle(seed as u32)));
+        }
+
+        let mut deck = default_shuffle(0);
+        deck[51] = deck[0];
+        assert!(!is_valid_deck(&deck));
+        assert_eq!(encode_shuffle(deck), None);
+
+        deck[51] = Card::INVALID;
+        assert!(!is_valid_deck(&deck));
+    }
+
     #[test]
     fn test_encode2() {
         let mut rng = SmallRng::seed_from_u64(14);
@@ -284,10 +336,10 @@ mod tests {
         // for _ in 0..1000 {
         let seed: u64 = rng.random();
         let deck = default_shuffle(seed);
-        let encode = encode_shuffle(deck.clone()).unwrap();
+        let encode = encode_shuffle(deck).unwrap();
         let deck_2 = exact_shuffle(encode).unwrap();
 
-        let encode2 = encode_shuffle(deck_2.clone()).unwrap();
+        let encode2 = encode_shuffle(deck_2).unwrap();
         assert_eq!(encode, encode2);
         assert_eq!(deck, deck_2);
         // }
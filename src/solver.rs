@@ -1,15 +1,25 @@
 use crate::{
-    moves::Move,
+    convert::convert_move,
+    engine::SolitaireEngine,
+    moves::{Move, N_MOVES_MAX},
     pruning::FullPruner,
+    standard::{InvalidMove, MoveResult, StandardHistoryVec, StandardMove, StandardSolitaire},
     state::{Encode, Solitaire},
-    tracking::{DefaultTerminateSignal, EmptySearchStats, SearchStatistics, TerminateSignal},
-    traverse::{traverse, Callback, Control, TpTable},
+    traverse::{traverse, BoundedTpTable, Callback, Control, TpTable, TranspositionTable},
 };
+// the search hooks are defined once in `tracking`, re-exported for the solver users
+pub use crate::tracking::{DefaultTerminateSignal, EmptySearchStats, SearchStatistics, TerminateSignal};
 use arrayvec::ArrayVec;
+use core::ops::ControlFlow;
+
+extern crate alloc;
+use alloc::vec::Vec;
 
 // before every progress you'd do at most 2*N_RANKS move
 // and there would only be N_FULL_DECK + N_HIDDEN progress step
-const N_PLY_MAX: usize = 1024;
+/// Longest solution the solvers return, deeper lines of play are cut off as
+/// if they were lost instead of overflowing the history
+pub const N_PLY_MAX: usize = 1024;
 
 pub type HistoryVec = ArrayVec<Move, N_PLY_MAX>;
 
@@ -23,6 +33,7 @@ pub enum SearchResult {
 
 struct SolverCallback<'a, S: SearchStatistics, T: TerminateSignal> {
     history: HistoryVec,
+    max_len: usize,
     stats: &'a S,
     sign: &'a T,
     result: SearchResult,
@@ -51,6 +62,9 @@ impl<S: SearchStatistics, T: TerminateSignal> Callback for SolverCallback<'_, S,
     }
 
     fn on_do_move(&mut self, _: &Solitaire, m: Move, _: Encode, _: &FullPruner) -> Control {
+        if self.history.len() >= self.max_len {
+            return Control::Skip;
+        }
         self.history.push(m);
         Control::Ok
     }
@@ -68,16 +82,41 @@ pub fn solve_with_tracking<S: SearchStatistics, T: TerminateSignal>(
     stats: &S,
     sign: &T,
 ) -> (SearchResult, Option<HistoryVec>) {
-    let mut tp = TpTable::default();
+    solve_with_table(game, &mut TpTable::default(), N_PLY_MAX, stats, sign)
+}
+
+/// Same as [`solve_with_tracking`] with a transposition table of about
+/// `cache_bytes` bytes instead of an unbounded one
+///
+/// When the table is full old states are forgotten and searched again if the
+/// search comes back to them: a small cache makes the search slower but the
+/// result stays the same.
+pub fn solve_with_cache_size<S: SearchStatistics, T: TerminateSignal>(
+    game: &mut Solitaire,
+    cache_bytes: usize,
+    stats: &S,
+    sign: &T,
+) -> (SearchResult, Option<HistoryVec>) {
+    solve_with_table(game, &mut BoundedTpTable::with_bytes(cache_bytes), N_PLY_MAX, stats, sign)
+}
 
+/// Search for a solution of at most `max_len` moves
+fn solve_with_table<TP: TranspositionTable, S: SearchStatistics, T: TerminateSignal>(
+    game: &mut Solitaire,
+    tp: &mut TP,
+    max_len: usize,
+    stats: &S,
+    sign: &T,
+) -> (SearchResult, Option<HistoryVec>) {
     let mut callback = SolverCallback {
         history: HistoryVec::new(),
+        max_len,
         stats,
         sign,
         result: SearchResult::Unsolvable,
     };
 
-    traverse(game, FullPruner::default(), &mut tp, &mut callback);
+    traverse(game, FullPruner::default(), tp, &mut callback);
 
     let result = callback.result;
 
@@ -88,6 +127,454 @@ pub fn solve_with_tracking<S: SearchStatistics, T: TerminateSignal>(
     }
 }
 
+/// Play the safe foundation moves of `game` (see
+/// [`Solitaire::safe_foundation_moves`]) until there is none left and return them
+pub fn autoplay_safe(game: &mut Solitaire) -> HistoryVec {
+    let mut history = HistoryVec::new();
+    while let ControlFlow::Break(m) = game
+        .safe_foundation_moves()
+        .iter_moves(ControlFlow::Break)
+    {
+        game.do_move(m);
+        history.push(m);
+    }
+    history
+}
+
+/// Same as [`solve_with_tracking`] but the safe foundation moves of the
+/// starting position are played before searching
+///
+/// They never lose a game, so the result is the same as the plain search with
+/// fewer states to visit. The returned history starts with these moves.
+pub fn solve_with_autoplay<S: SearchStatistics, T: TerminateSignal>(
+    game: &Solitaire,
+    stats: &S,
+    sign: &T,
+) -> (SearchResult, Option<HistoryVec>) {
+    let mut game = game.clone();
+    let mut history = autoplay_safe(&mut game);
+    let max_len = N_PLY_MAX - history.len();
+    let (res, rest) = solve_with_table(&mut game, &mut TpTable::default(), max_len, stats, sign);
+    let history = rest.map(|rest| {
+        history.extend(rest);
+        history
+    });
+    (res, history)
+}
+
+/// Same as [`solve_with_tracking`] on the subtree reached by playing the
+/// moves of `history` from `game`
+///
+/// The search is only over the positions after `history`, so a coordinator
+/// can hand out the subtrees of the search to workers by prefix. The returned
+/// history starts with `history`, the whole of it is still at most
+/// [`N_PLY_MAX`] moves long.
+///
+/// # Errors
+///
+/// Return `InvalidMove` when a move of `history` can't be played or there are
+/// more than [`N_PLY_MAX`] of them
+pub fn solve_from_history<S: SearchStatistics, T: TerminateSignal>(
+    game: &Solitaire,
+    history: &[Move],
+    stats: &S,
+    sign: &T,
+) -> MoveResult<(SearchResult, Option<HistoryVec>)> {
+    let mut prefix = HistoryVec::try_from(history).map_err(|_| InvalidMove)?;
+    let mut engine: SolitaireEngine<FullPruner> = game.clone().into();
+    if !history.iter().all(|&m| engine.do_move(m)) {
+        return Err(InvalidMove);
+    }
+
+    let mut game = engine.into_state();
+    let max_len = N_PLY_MAX - prefix.len();
+    let (res, rest) = solve_with_table(&mut game, &mut TpTable::default(), max_len, stats, sign);
+    let history = rest.map(|rest| {
+        prefix.extend(rest);
+        prefix
+    });
+    Ok((res, history))
+}
+
 pub fn solve(game: &mut Solitaire) -> (SearchResult, Option<HistoryVec>) {
     solve_with_tracking(game, &EmptySearchStats {}, &DefaultTerminateSignal {})
 }
+
+/// Label every legal move of `game` with whether a win is still reachable
+/// after playing it
+///
+/// Every move gets its own exhaustive search, so this is only meant for small
+/// endgames
+#[must_use]
+pub fn exact_move_values(game: &Solitaire) -> Vec<(Move, bool)> {
+    game.gen_moves::<false>()
+        .to_vec::<N_MOVES_MAX>()
+        .into_iter()
+        .map(|m| {
+            let mut next = game.clone();
+            next.do_move(m);
+            (m, solve(&mut next).0 == SearchResult::Solved)
+        })
+        .collect()
+}
+
+/// Drop the detours of `moves`, the runs of moves played from `game` that
+/// come back to a position reached before them, then play the deck moves
+/// earlier where it saves draws
+///
+/// The positions are compared by their [`Solitaire::encode`], which the
+/// search treats as the same state, so the moves after a detour can still be
+/// played. A deck move is brought forward when it can be played there, the
+/// history still ends on the same position and the standard moves of
+/// [`convert_moves`](crate::convert::convert_moves) draw from the deck fewer
+/// times in total, e.g. taking a card before the one above it instead of
+/// going through the whole deck again. A winning history stays winning and
+/// never gets longer. `moves` must be legal from `game`.
+#[must_use]
+pub fn compress_history(game: &Solitaire, moves: &[Move]) -> Vec<Move> {
+    let mut state = game.clone();
+    let mut reached: Vec<Encode> = Vec::with_capacity(moves.len() + 1);
+    reached.push(state.encode_cached());
+    let mut kept = Vec::with_capacity(moves.len());
+    for &m in moves {
+        state.do_move(m);
+        let encode = state.encode_cached();
+        if let Some(pos) = reached.iter().position(|&e| e == encode) {
+            reached.truncate(pos + 1);
+            kept.truncate(pos);
+        } else {
+            reached.push(encode);
+            kept.push(m);
+        }
+    }
+    collapse_draws(game, &mut kept);
+    kept
+}
+
+// bring a later deck move to each position in turn while it saves draws
+fn collapse_draws(game: &Solitaire, moves: &mut [Move]) {
+    let mut prefix = StandardSolitaire::from(game);
+    let Some((mut n_draws, end)) = play_counting_draws(prefix.clone(), moves, usize::MAX) else {
+        return;
+    };
+    let end = Solitaire::from(&end).encode();
+    let mut prefix_draws = 0;
+    for i in 0..moves.len() {
+        for j in i + 1..moves.len() {
+            if !matches!(moves[j], Move::DeckPile(_) | Move::DeckStack(_)) {
+                continue;
+            }
+            let mut order = moves[i..].to_vec();
+            order[..=j - i].rotate_right(1);
+            let limit = n_draws - prefix_draws;
+            if let Some((n, last)) = play_counting_draws(prefix.clone(), &order, limit) {
+                if Solitaire::from(&last).encode() == end {
+                    moves[i..].copy_from_slice(&order);
+                    n_draws = prefix_draws + n;
+                }
+            }
+        }
+        let Some((n, next)) = play_counting_draws(prefix, &moves[i..=i], usize::MAX) else {
+            return;
+        };
+        prefix = next;
+        prefix_draws += n;
+    }
+}
+
+// the draws of the standard moves of `moves` and the game they end on, `None`
+// when one of them isn't legal or they take `limit` draws or more
+fn play_counting_draws(
+    mut game: StandardSolitaire,
+    moves: &[Move],
+    limit: usize,
+) -> Option<(usize, StandardSolitaire)> {
+    let mut n_draws = 0;
+    for &m in moves {
+        let mut seq = StandardHistoryVec::new();
+        convert_move(&game, m, &mut seq).ok()?;
+        for sm in &seq {
+            n_draws += usize::from(*sm == StandardMove::DRAW_NEXT);
+            game.do_move(sm).ok()?;
+        }
+        if n_draws >= limit {
+            return None;
+        }
+    }
+    Some((n_draws, game))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use core::cell::Cell;
+
+    use crate::{convert::convert_moves, engine::MoveVec, shuffler::default_shuffle};
+    use rand::{rngs::SmallRng, seq::IndexedRandom, SeedableRng};
+
+    #[derive(Default)]
+    struct CountStats {
+        visited: Cell<usize>,
+        unique: Cell<usize>,
+        finished: Cell<usize>,
+        max_depth: Cell<usize>,
+    }
+
+    impl SearchStatistics for CountStats {
+        fn hit_a_state(&self, depth: usize) {
+            self.visited.set(self.visited.get() + 1);
+            self.max_depth.set(self.max_depth.get().max(depth));
+        }
+        fn hit_unique_state(&self, _: usize, _: u32) {
+            self.unique.set(self.unique.get() + 1);
+        }
+        fn finish_move(&self, _: usize) {
+            self.finished.set(self.finished.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_custom_stats() {
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let stats = CountStats::default();
+        let (res, history) = solve_with_tracking(&mut game, &stats, &DefaultTerminateSignal {});
+        assert_eq!(res, SearchResult::Solved);
+
+        let visited = stats.visited.get();
+        assert!(visited > 0 && stats.unique.get() <= visited);
+        assert!(stats.finished.get() > 0);
+        assert!(stats.max_depth.get() >= history.unwrap().len() - 1);
+    }
+
+    #[test]
+    fn test_exact_move_values() {
+        // replay a winning line up to a position where only one move keeps the win
+        let mut game = Solitaire::new(&default_shuffle(34), NonZeroU8::new(3).unwrap());
+        let history = solve(&mut game.clone()).1.unwrap();
+        for m in &history[..17] {
+            game.do_move(*m);
+        }
+
+        let values = exact_move_values(&game);
+        assert!(values.len() > 1);
+        let winning: Vec<Move> = values.iter().filter(|v| v.1).map(|v| v.0).collect();
+        assert_eq!(winning, [history[17]]);
+    }
+
+    #[test]
+    fn test_king_to_empty() {
+        // the king relocation of this line is the only move that keeps the win
+        let mut game = Solitaire::new(&default_shuffle(15), NonZeroU8::new(3).unwrap());
+        let history = solve(&mut game.clone()).1.unwrap();
+        let mut engine: SolitaireEngine<FullPruner> = game.clone().into();
+        for m in &history[..28] {
+            game.do_move(*m);
+            assert!(engine.do_move(*m));
+        }
+        let king_move = history[28];
+        assert!(matches!(king_move, Move::Reveal(c) if c.is_king()));
+
+        let values = exact_move_values(&game);
+        let winning: Vec<Move> = values.iter().filter(|v| v.1).map(|v| v.0).collect();
+        assert_eq!(winning, [king_move]);
+
+        // the pruned move generation keeps it and a fresh search plays it first
+        assert!(engine.list_moves().contains(&king_move));
+        assert_eq!(solve(&mut game).1.unwrap()[0], king_move);
+    }
+
+    #[test]
+    fn test_solve_with_autoplay() {
+        let (mut plain_visits, mut autoplay_visits) = (0, 0);
+        for seed in 12..25 {
+            let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+
+            let stats = CountStats::default();
+            let (res, _) = solve_with_tracking(&mut game.clone(), &stats, &DefaultTerminateSignal {});
+            plain_visits += stats.visited.get();
+
+            let stats = CountStats::default();
+            let (auto_res, history) = solve_with_autoplay(&game, &stats, &DefaultTerminateSignal {});
+            autoplay_visits += stats.visited.get();
+
+            assert_eq!(res, auto_res);
+            if let Some(history) = history {
+                let mut replay: SolitaireEngine<FullPruner> = game.into();
+                assert!(history.iter().all(|&m| replay.do_move(m)));
+                assert!(replay.state().is_win());
+            }
+        }
+        assert!(autoplay_visits < plain_visits);
+    }
+
+    #[test]
+    fn test_solve_with_cache_size() {
+        let game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let stats = CountStats::default();
+        let (res, history) =
+            solve_with_cache_size(&mut game.clone(), 1024, &stats, &DefaultTerminateSignal);
+        assert_eq!(res, SearchResult::Solved);
+        // more states than the table can hold
+        assert!(stats.unique.get() > 1024 / 9);
+
+        let mut replay: SolitaireEngine<FullPruner> = game.into();
+        assert!(history.unwrap().iter().all(|&m| replay.do_move(m)));
+        assert!(replay.state().is_win());
+    }
+
+    #[test]
+    fn test_solve_results_pinned() {
+        // the same deals stay solvable, each with a line that wins
+        let solvable = [false, true, true, true, false, true, true, true, false, true];
+        for (seed, solvable) in (0..).zip(solvable) {
+            let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+            let (res, history) = solve(&mut game.clone());
+            assert_eq!(res == SearchResult::Solved, solvable);
+            assert_eq!(history.is_some(), solvable);
+
+            if let Some(history) = history {
+                let mut replay: SolitaireEngine<FullPruner> = game.into();
+                assert!(history.iter().all(|&m| replay.do_move(m)));
+                assert!(replay.state().is_win());
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let game = Solitaire::new(&default_shuffle(1), NonZeroU8::new(3).unwrap());
+        let stats = EmptySearchStats {};
+        let short = solve_with_table(&mut game.clone(), &mut TpTable::default(), 10, &stats, &DefaultTerminateSignal);
+        assert_eq!(short, (SearchResult::Unsolvable, None));
+        let (res, history) = solve(&mut game.clone());
+        assert_eq!(res, SearchResult::Solved);
+        let len = history.unwrap().len();
+        let exact = solve_with_table(&mut game.clone(), &mut TpTable::default(), len, &stats, &DefaultTerminateSignal);
+        assert_eq!(exact.1.map(|h| h.len()), Some(len));
+
+        // a prefix wandering back and forth leaves almost no room for the search
+        let mut engine: SolitaireEngine<FullPruner> = game.clone().into();
+        let mut prefix = Vec::new();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let reverse = |m| match m {
+            Move::PileStack(c) => Some(Move::StackPile(c)),
+            Move::StackPile(c) => Some(Move::PileStack(c)),
+            _ => None,
+        };
+        while prefix.len() < N_PLY_MAX - 5 {
+            let moves = engine.list_moves();
+            // a card going back and forth from the foundation once it can
+            let undoable = moves.iter().copied().find(|&m| {
+                let mut next: SolitaireEngine<FullPruner> = engine.state().clone().into();
+                next.do_move(m) && reverse(m).is_some_and(|r| next.is_valid(r))
+            });
+            let Some(m) = undoable.or_else(|| moves.choose(&mut rng).copied()) else {
+                break;
+            };
+            assert!(engine.do_move(m));
+            prefix.push(m);
+        }
+        assert_eq!(prefix.len(), N_PLY_MAX - 5);
+        let (_, history) = solve_from_history(&game, &prefix, &stats, &DefaultTerminateSignal).unwrap();
+        assert!(history.is_none_or(|h| h.len() <= N_PLY_MAX));
+        let too_long = alloc::vec![prefix[0]; N_PLY_MAX + 1];
+        assert!(solve_from_history(&game, &too_long, &stats, &DefaultTerminateSignal).is_err());
+    }
+
+    #[test]
+    fn test_solve_from_history() {
+        for seed in [4, 12] {
+            let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+            let (res, history) = solve(&mut game.clone());
+            let prefix: MoveVec = match &history {
+                Some(history) => history[..3].iter().copied().collect(),
+                // any legal line, nothing wins after it either
+                None => {
+                    let mut engine: SolitaireEngine<FullPruner> = game.clone().into();
+                    (0..3)
+                        .map(|_| {
+                            let m = engine.list_moves_dom()[0];
+                            engine.do_move(m);
+                            m
+                        })
+                        .collect()
+                }
+            };
+
+            let (resumed, resumed_history) =
+                solve_from_history(&game, &prefix, &EmptySearchStats {}, &DefaultTerminateSignal)
+                    .unwrap();
+            assert_eq!(resumed, res);
+            if let Some(resumed_history) = resumed_history {
+                assert_eq!(resumed_history[..3], prefix[..]);
+                let mut replay: SolitaireEngine<FullPruner> = game.clone().into();
+                assert!(resumed_history.iter().all(|&m| replay.do_move(m)));
+                assert!(replay.state().is_win());
+            }
+
+            // the last move can't be played twice
+            let mut invalid = prefix.clone();
+            invalid.push(prefix[2]);
+            assert!(
+                solve_from_history(&game, &invalid, &EmptySearchStats {}, &DefaultTerminateSignal)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_compress_history() {
+        let legal = |g: &Solitaire| g.gen_moves::<false>().to_vec::<N_MOVES_MAX>();
+        let draws = |g: &Solitaire, moves: &[Move]| {
+            let converted = convert_moves(&mut g.into(), moves).unwrap();
+            converted.iter().filter(|m| **m == StandardMove::DRAW_NEXT).count()
+        };
+        let mut n_detours = 0;
+        let mut n_collapsed = 0;
+        for seed in 12..16 {
+            let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+            let Some(moves) = solve(&mut game.clone()).1 else {
+                continue;
+            };
+            let compressed = compress_history(&game, &moves);
+            assert!(compressed.len() <= moves.len());
+            let mut replay = game.clone();
+            for &m in &compressed {
+                assert!(legal(&replay).contains(&m));
+                replay.do_move(m);
+            }
+            assert!(replay.is_win());
+            // the deck moves of the solver are reordered to draw less
+            assert!(draws(&game, &compressed) <= draws(&game, &moves));
+            n_collapsed += usize::from(draws(&game, &compressed) < draws(&game, &moves));
+
+            // a move played and then taken back somewhere along the solution
+            let mut state = game.clone();
+            for (i, &m) in moves.iter().enumerate() {
+                let detour = legal(&state).into_iter().find_map(|a| {
+                    let mut next = state.clone();
+                    next.do_move(a);
+                    let back = legal(&next).into_iter().find(|&b| {
+                        let mut prev = next.clone();
+                        prev.do_move(b);
+                        prev.encode() == state.encode()
+                    });
+                    back.map(|b| [a, b])
+                });
+                if let Some(detour) = detour {
+                    let mut longer = moves[..i].to_vec();
+                    longer.extend(detour);
+                    longer.extend(&moves[i..]);
+                    assert_eq!(compress_history(&game, &longer), compressed);
+                    n_detours += 1;
+                    break;
+                }
+                state.do_move(m);
+            }
+        }
+        assert!(n_detours > 0);
+        assert!(n_collapsed > 0);
+    }
+}
@@ -49,6 +49,21 @@ impl Stack {
         self.get(card.suit()) == card.rank()
     }
 
+    /// Return the top card of the foundation of `suit`
+    #[must_use]
+    pub const fn top(self, suit: u8) -> Option<Card> {
+        match self.get(suit) {
+            0 => None,
+            rank => Some(Card::new(rank - 1, suit)),
+        }
+    }
+
+    /// Return whether `card` is the next card of its suit (an ace on an empty foundation)
+    #[must_use]
+    pub const fn can_accept(self, card: Card) -> bool {
+        card.rank() < N_RANKS && self.get(card.suit()) == card.rank()
+    }
+
     pub(crate) const fn is_valid(self) -> bool {
         let s = self.get_s();
         s[0] <= N_RANKS && s[1] <= N_RANKS && s[2] <= N_RANKS && s[3] <= N_RANKS
@@ -80,3 +95,117 @@ impl Stack {
         self.0 == 0
     }
 }
+
+/// The foundation piles of a position, the known cards put on them suit by suit
+///
+/// Unlike the engine's [`Stack`] every change goes through the foundation rules.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Foundation(Stack);
+
+impl Foundation {
+    /// Number of cards on the foundation of `suit`
+    #[must_use]
+    pub const fn height(self, suit: u8) -> u8 {
+        self.0.get(suit)
+    }
+
+    /// Same as [`Self::height`]
+    #[must_use]
+    pub const fn get(self, suit: u8) -> u8 {
+        self.height(suit)
+    }
+
+    /// Return the top card of the foundation of `suit`
+    #[must_use]
+    pub const fn top(self, suit: u8) -> Option<Card> {
+        self.0.top(suit)
+    }
+
+    /// Return whether `card` is the next card of its suit (an ace on an empty foundation)
+    #[must_use]
+    pub const fn can_accept(self, card: Card) -> bool {
+        self.0.can_accept(card)
+    }
+
+    /// Put `card` on its foundation
+    /// Return false and do nothing when the foundation can't accept it
+    pub fn push(&mut self, card: Card) -> bool {
+        if !self.can_accept(card) {
+            return false;
+        }
+        self.0.push(card.suit());
+        true
+    }
+
+    /// Take the top card of the foundation of `suit` back, if any
+    pub fn pop(&mut self, suit: u8) -> Option<Card> {
+        let top = self.top(suit);
+        if top.is_some() {
+            self.0.pop(suit);
+        }
+        top
+    }
+
+    /// Number of cards on the foundations
+    #[must_use]
+    pub const fn len(self) -> u8 {
+        self.0.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Stack> for Foundation {
+    fn from(stack: Stack) -> Self {
+        Self(stack)
+    }
+}
+
+impl From<Foundation> for Stack {
+    fn from(foundation: Foundation) -> Self {
+        foundation.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_accept() {
+        let mut stack = Foundation::default();
+        assert!(stack.is_empty());
+        // only aces onto empty foundations
+        assert!(stack.can_accept(Card::new(0, 2)));
+        assert!(!stack.can_accept(Card::new(1, 2)));
+        assert!(!stack.can_accept(Card::INVALID));
+        assert_eq!(stack.top(2), None);
+
+        assert!(stack.push(Card::new(0, 2)));
+        assert_eq!(stack.top(2), Some(Card::new(0, 2)));
+        // rank + 1 of the same suit
+        assert!(stack.can_accept(Card::new(1, 2)));
+        assert!(stack.can_accept(stack.top(2).and_then(Card::next_in_suit).unwrap()));
+        assert!(!stack.can_accept(Card::new(1, 3)));
+        assert!(!stack.can_accept(Card::new(0, 2)));
+        // rejects gaps
+        assert!(!stack.can_accept(Card::new(2, 2)));
+        assert!(!stack.push(Card::new(2, 2)));
+        assert_eq!(stack.get(2), 1);
+
+        for rank in 1..N_RANKS {
+            assert!(stack.push(Card::new(rank, 2)));
+        }
+        assert_eq!(stack.top(2), Some(Card::new(N_RANKS - 1, 2)));
+        assert!(!stack.can_accept(Card::INVALID.swap_suit()));
+        assert!(Stack::from(stack).is_valid());
+
+        assert_eq!(stack.pop(2), Some(Card::new(N_RANKS - 1, 2)));
+        assert_eq!(stack.height(2), N_RANKS - 1);
+        assert_eq!(stack.pop(0), None);
+        assert_eq!(stack.len(), N_RANKS - 1);
+    }
+}
@@ -2,10 +2,13 @@ use core::num::NonZeroU8;
 
 use arrayvec::ArrayVec;
 
-use crate::card::{Card, N_RANKS, N_SUITS};
-use crate::deck::{Deck, N_DECK_CARDS, N_PILES, N_PILE_CARDS};
+use crate::card::{Card, N_CARDS, N_RANKS, N_SUITS};
+use crate::convert::convert_moves;
+use crate::deck::Deck;
+use crate::shape::{N_PILES, N_PILE_CARDS};
 use crate::shuffler::CardDeck;
-use crate::stack::Stack;
+use crate::stack::{Foundation, Stack};
+use crate::solver;
 use crate::state::Solitaire;
 
 pub type PileVec = ArrayVec<Card, { N_RANKS as usize }>;
@@ -36,6 +39,15 @@ impl StandardMove {
     }
 }
 
+/// Windows style score of a card put on the foundation
+pub const SCORE_TO_FOUNDATION: i32 = 10;
+/// Windows style score of a card put from the deck on a pile
+pub const SCORE_DECK_TO_PILE: i32 = 5;
+/// Windows style score of a hidden card turned over
+pub const SCORE_TURN_OVER: i32 = 5;
+/// Windows style score of a card taken back from the foundation
+pub const SCORE_FROM_FOUNDATION: i32 = -15;
+
 const N_HIDDEN_MAX: usize = (N_PILES - 1) as usize;
 
 const N_PLY_MAX: usize = 1024;
@@ -43,12 +55,13 @@ const N_PLY_MAX: usize = 1024;
 pub type HiddenVec = ArrayVec<Card, N_HIDDEN_MAX>;
 pub type StandardHistoryVec = ArrayVec<StandardMove, N_PLY_MAX>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StandardSolitaire {
     final_stack: Stack,
     deck: Deck,
     hidden_piles: [HiddenVec; N_PILES as usize],
     piles: [PileVec; N_PILES as usize],
+    score: i32,
 }
 
 pub type MoveResult<T> = core::result::Result<T, InvalidMove>;
@@ -59,6 +72,25 @@ pub type MoveResult<T> = core::result::Result<T, InvalidMove>;
 #[derive(Debug, Clone, Copy)]
 pub struct InvalidMove;
 
+/// Error returned by [`StandardSolitaire::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleError {
+    /// A foundation holds more than `N_RANKS` cards.
+    Foundation(u8),
+    /// The visible cards of a pile are not a descending alternating run.
+    InvalidRun(u8),
+    /// A pile has hidden cards but no visible card on top of them.
+    UncoveredHidden(u8),
+    /// A pile has more hidden cards than the deal gives it.
+    TooManyHidden(u8),
+    /// Not a card of the standard deck.
+    InvalidCard(Card),
+    /// The card appears more than once on the board.
+    DuplicateCard(Card),
+    /// The card appears nowhere on the board.
+    MissingCard(Card),
+}
+
 #[must_use]
 pub fn split_at_card(cards: &[Card], card: Card) -> Option<(&[Card], &[Card])> {
     let pos = cards.iter().position(|c| card == *c)?;
@@ -66,6 +98,9 @@ pub fn split_at_card(cards: &[Card], card: Card) -> Option<(&[Card], &[Card])> {
 }
 
 impl StandardSolitaire {
+    /// Deal the game of `cards`, which must hold every card once (see
+    /// [`is_valid_deck`](crate::shuffler::is_valid_deck))
+    ///
     /// # Panics
     ///
     /// This function should never panic. If it does then the implementation is buggy
@@ -92,6 +127,25 @@ impl StandardSolitaire {
                 tmp.push(cards[i * (i + 1) / 2 + i]);
                 tmp
             }),
+            score: 0,
+        }
+    }
+
+    /// A game from its foundations, hidden cards, visible piles and deck, e.g.
+    /// a position in the middle of a game. See [`Self::validate`] to check it
+    #[must_use]
+    pub(crate) const fn from_parts(
+        final_stack: Stack,
+        hidden_piles: [HiddenVec; N_PILES as usize],
+        piles: [PileVec; N_PILES as usize],
+        deck: Deck,
+    ) -> Self {
+        Self {
+            final_stack,
+            deck,
+            hidden_piles,
+            piles,
+            score: 0,
         }
     }
 
@@ -100,6 +154,69 @@ impl StandardSolitaire {
         self.final_stack.is_full()
     }
 
+    /// Check that the board follows the rules: valid foundations, legal
+    /// visible runs and exactly one of each of the `N_CARDS` cards
+    ///
+    /// # Errors
+    ///
+    /// Return the first `RuleError` found
+    pub fn validate(&self) -> Result<(), RuleError> {
+        let mut seen = [false; N_CARDS as usize];
+        let mut mark = |card: Card| {
+            let idx = card.mask_index();
+            if idx >= N_CARDS {
+                return Err(RuleError::InvalidCard(card));
+            }
+            if core::mem::replace(&mut seen[idx as usize], true) {
+                return Err(RuleError::DuplicateCard(card));
+            }
+            Ok(())
+        };
+
+        for suit in 0..N_SUITS {
+            let height = self.final_stack.get(suit);
+            if height > N_RANKS {
+                return Err(RuleError::Foundation(suit));
+            }
+            for rank in 0..height {
+                mark(Card::new(rank, suit))?;
+            }
+        }
+
+        for (pos, (hidden, pile)) in (0u8..).zip(self.hidden_piles.iter().zip(&self.piles)) {
+            if pile.is_empty() && !hidden.is_empty() {
+                return Err(RuleError::UncoveredHidden(pos));
+            }
+            if hidden.len() > usize::from(pos) {
+                return Err(RuleError::TooManyHidden(pos));
+            }
+            if pile.windows(2).any(|w| !w[1].go_after(Some(w[0]))) {
+                return Err(RuleError::InvalidRun(pos));
+            }
+            for &card in hidden.iter().chain(pile) {
+                mark(card)?;
+            }
+        }
+
+        for (_, card, _) in self.deck.iter_all() {
+            mark(card)?;
+        }
+
+        match Card::all().find(|c| !seen[c.mask_index() as usize]) {
+            Some(card) => Err(RuleError::MissingCard(card)),
+            None => Ok(()),
+        }
+    }
+
+    /// Solve the game and return the winning sequence of standard moves
+    /// Return None when the game is unsolvable (or the search was cut short)
+    #[must_use]
+    pub fn solve(&self) -> Option<StandardHistoryVec> {
+        let mut game: Solitaire = self.into();
+        let moves = solver::solve(&mut game).1?;
+        convert_moves(&mut self.clone(), &moves).ok()
+    }
+
     #[must_use]
     pub const fn get_deck(&self) -> &Deck {
         &self.deck
@@ -120,24 +237,32 @@ impl StandardSolitaire {
         &self.hidden_piles
     }
 
+    /// Windows style score of the moves played so far, never below zero
+    ///
+    /// See the `SCORE_*` constants, the other moves score nothing.
+    #[must_use]
+    pub const fn score(&self) -> i32 {
+        self.score
+    }
+
     #[must_use]
     pub fn find_deck_card(&self, card: Card) -> Option<u8> {
-        for i in 0..=N_DECK_CARDS {
-            let offset = self.deck.offset(i);
-            if offset > 0 && self.deck.peek(offset - 1) == card {
-                return Some(i);
-            }
-        }
-        None
+        self.deck.draws_to(card)
     }
 
     #[must_use]
     pub fn find_free_pile(&self, card: Card) -> Option<u8> {
+        self.free_piles(card).next()
+    }
+
+    /// Every pile where `card` can be put
+    pub fn free_piles(&self, card: Card) -> impl Iterator<Item = u8> + '_ {
         #[allow(clippy::cast_possible_truncation)]
         self.piles
             .iter()
-            .position(|p| card.go_after(p.last().copied()))
-            .map(|pos| pos as u8)
+            .enumerate()
+            .filter(move |(_, p)| card.go_after(p.last().copied()))
+            .map(|(pos, _)| pos as u8)
     }
 
     #[must_use]
@@ -174,7 +299,7 @@ impl StandardSolitaire {
                 suit < N_SUITS
                     && self.deck.peek_current() == Some(card)
                     && card.suit() == suit
-                    && self.final_stack.get(suit) == card.rank()
+                    && self.final_stack.can_accept(card)
             }
             (Pos::Pile(from), Pos::Pile(to), card) => {
                 from != to
@@ -188,14 +313,13 @@ impl StandardSolitaire {
                     && suit < N_SUITS
                     && self.piles[from as usize].last() == Some(&card)
                     && card.suit() == suit
-                    && card.rank() == self.final_stack.get(suit)
+                    && self.final_stack.can_accept(card)
             }
 
             (Pos::Stack(suit), Pos::Pile(to), card) => {
                 suit < N_SUITS
                     && to < N_PILES
-                    && card.suit() == suit
-                    && card.rank() + 1 == self.final_stack.get(suit)
+                    && self.final_stack.top(suit) == Some(card)
                     && card.go_after(self.piles[to as usize].last().copied())
             }
         }
@@ -214,6 +338,12 @@ impl StandardSolitaire {
         if !self.validate_move(m) {
             return Err(InvalidMove {});
         }
+        let mut gain = match (m.from, m.to) {
+            (_, Pos::Stack(_)) => SCORE_TO_FOUNDATION,
+            (Pos::Deck, Pos::Pile(_)) => SCORE_DECK_TO_PILE,
+            (Pos::Stack(_), Pos::Pile(_)) => SCORE_FROM_FOUNDATION,
+            _ => 0,
+        };
         match (m.from, m.to, m.card) {
             (Pos::Deck, Pos::Deck, _) => {
                 self.deck.deal_once();
@@ -254,11 +384,64 @@ impl StandardSolitaire {
             if self.piles[from].is_empty() {
                 if let Some(card) = self.hidden_piles[from].pop() {
                     self.piles[from].push(card);
+                    gain += SCORE_TURN_OVER;
                 }
             }
         }
+        self.score = (self.score + gain).max(0);
         Ok(())
     }
+
+    /// Turn over the next `draw_step` cards of the stock onto the waste, as a
+    /// click on the stock does, or put the waste back into the stock once
+    /// the stock is empty
+    ///
+    /// There is no redeal limit, the stock can be gone through any number of
+    /// times.
+    ///
+    /// # Errors
+    ///
+    /// Will return `InvalidMove` when there is no card left in the stock and
+    /// the waste
+    pub fn draw_stock(&mut self) -> MoveResult<()> {
+        if self.deck.is_empty() {
+            return Err(InvalidMove {});
+        }
+        self.do_move(&StandardMove::DRAW_NEXT)
+    }
+}
+
+impl StandardSolitaire {
+    /// Build up the foundation of `suit` to `height` cards, e.g. to set up scenarios
+    /// The cards are taken from the deck, the hidden cards or the top of the piles
+    /// Return false when one of them is stuck under another card, the game might be partially updated
+    pub(crate) fn force_foundation(&mut self, suit: u8, height: u8) -> bool {
+        for rank in self.final_stack.get(suit)..height {
+            let card = Card::new(rank, suit);
+            if !self.deck.remove(card) {
+                if let Some(pile) = self.hidden_piles.iter_mut().find(|p| p.contains(&card)) {
+                    pile.retain(|c| *c != card);
+                } else if let Some(pos) = self.piles.iter().position(|p| p.last() == Some(&card)) {
+                    self.piles[pos].pop();
+                    if self.piles[pos].is_empty() {
+                        if let Some(c) = self.hidden_piles[pos].pop() {
+                            self.piles[pos].push(c);
+                        }
+                    }
+                } else {
+                    return false;
+                }
+            }
+            self.final_stack.push(suit);
+        }
+        true
+    }
+
+    /// Build up every foundation to its height in `foundation`, see
+    /// [`Self::force_foundation`]
+    pub(crate) fn force_foundations(&mut self, foundation: Foundation) -> bool {
+        (0..N_SUITS).all(|suit| self.force_foundation(suit, foundation.get(suit)))
+    }
 }
 
 impl From<&Solitaire> for StandardSolitaire {
@@ -268,6 +451,150 @@ impl From<&Solitaire> for StandardSolitaire {
             final_stack: *game.get_stack(),
             deck: game.get_deck().clone(),
             piles: game.compute_visible_piles(),
+            score: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::Move;
+    use crate::shape::N_DECK_CARDS;
+    use crate::shuffler::default_shuffle;
+
+    #[test]
+    fn test_solve() {
+        let draw_step = NonZeroU8::new(3).unwrap();
+        let game = StandardSolitaire::new(&default_shuffle(12), draw_step);
+        let moves = game.solve().unwrap();
+
+        let mut replay = game.clone();
+        for m in &moves {
+            assert!(replay.do_move(m).is_ok());
         }
+        assert!(replay.is_win());
+
+        let game = StandardSolitaire::new(&default_shuffle(0), draw_step);
+        assert!(game.solve().is_none());
+    }
+
+    #[test]
+    fn test_draw_stock() {
+        let mut game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(3).unwrap());
+        let stock: Vec<Card> = game.get_deck().iter().collect();
+        assert!(game.draw_stock().is_ok());
+        assert_eq!(game.get_deck().waste_iter().collect::<Vec<_>>(), stock[..3]);
+        assert_eq!(game.get_deck().deck_iter().len(), stock.len() - 3);
+
+        for _ in 1..N_DECK_CARDS / 3 {
+            assert!(game.draw_stock().is_ok());
+        }
+        assert_eq!(game.get_deck().deck_iter().len(), 0);
+        // recycle the waste
+        assert!(game.draw_stock().is_ok());
+        assert_eq!(game.get_deck().waste_iter().len(), 0);
+        assert_eq!(game.get_deck().deck_iter().collect::<Vec<_>>(), stock);
+
+        for card in stock {
+            game.deck.remove(card);
+        }
+        assert!(game.draw_stock().is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let game = StandardSolitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let moves = game.solve().unwrap();
+        let mut replay = game.clone();
+        assert_eq!(replay.score(), 0);
+
+        let mut sent_up = false;
+        let mut taken_back = false;
+        for m in &moves {
+            // with a card on the foundation, try to take it back once
+            if sent_up && !taken_back {
+                let back = (0..N_SUITS).find_map(|suit| {
+                    let card = replay.final_stack.top(suit)?;
+                    let to = replay.find_free_pile(card)?;
+                    Some(StandardMove::new(Pos::Stack(suit), Pos::Pile(to), card))
+                });
+                if let Some(back) = back {
+                    let mut tmp = replay.clone();
+                    tmp.do_move(&back).unwrap();
+                    assert_eq!(tmp.score(), (replay.score() + SCORE_FROM_FOUNDATION).max(0));
+                    assert!(tmp.score() < replay.score());
+                    taken_back = true;
+                }
+            }
+
+            let before = replay.score();
+            let n_hidden: usize = replay.hidden_piles.iter().map(ArrayVec::len).sum();
+            replay.do_move(m).unwrap();
+            let turned = n_hidden - replay.hidden_piles.iter().map(ArrayVec::len).sum::<usize>();
+            let turned = SCORE_TURN_OVER * i32::try_from(turned).unwrap();
+            match (m.from, m.to) {
+                (_, Pos::Stack(_)) => {
+                    assert_eq!(replay.score(), before + SCORE_TO_FOUNDATION + turned);
+                    sent_up = true;
+                }
+                (Pos::Deck, Pos::Pile(_)) => assert_eq!(replay.score(), before + SCORE_DECK_TO_PILE),
+                (Pos::Stack(_), _) => {
+                    assert_eq!(replay.score(), (before + SCORE_FROM_FOUNDATION).max(0));
+                }
+                _ => assert_eq!(replay.score(), before + turned),
+            }
+        }
+        assert!(taken_back);
+        assert!(replay.score() >= 52 * SCORE_TO_FOUNDATION);
+
+        // an illegal move scores nothing
+        let score = replay.score();
+        assert!(replay.do_move(&StandardMove::new(Pos::Deck, Pos::Stack(0), Card::DEFAULT)).is_err());
+        assert_eq!(replay.score(), score);
+    }
+
+    #[test]
+    fn test_deck_cost() {
+        let game = StandardSolitaire::new(&default_shuffle(12), NonZeroU8::new(1).unwrap());
+        let cards: Vec<Card> = game.get_deck().iter().collect();
+        let (first, last) = (cards[0], cards[cards.len() - 1]);
+        assert_eq!(Move::DeckStack(first).deck_cost(&game), Some(1));
+        assert_eq!(Move::DeckPile(last).deck_cost(&game), Some(N_DECK_CARDS));
+        assert_eq!(Move::Reveal(first).deck_cost(&game), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let draw_step = NonZeroU8::new(3).unwrap();
+        let mut cards = default_shuffle(12);
+        let mut game = StandardSolitaire::new(&cards, draw_step);
+        assert_eq!(game.validate(), Ok(()));
+
+        // still valid along a whole game
+        for m in &game.solve().unwrap() {
+            game.do_move(m).unwrap();
+            assert_eq!(game.validate(), Ok(()));
+        }
+
+        let first = cards[0];
+        cards[N_CARDS as usize - 1] = first;
+        let game = StandardSolitaire::new(&cards, draw_step);
+        assert_eq!(game.validate(), Err(RuleError::DuplicateCard(first)));
+
+        let mut game = StandardSolitaire::new(&default_shuffle(12), draw_step);
+        game.piles[1].push(game.piles[0][0]);
+        assert_eq!(game.validate(), Err(RuleError::InvalidRun(1)));
+        game.piles[1].clear();
+        assert_eq!(game.validate(), Err(RuleError::UncoveredHidden(1)));
+
+        let mut game = StandardSolitaire::new(&default_shuffle(12), draw_step);
+        let card = game.hidden_piles[1].pop().unwrap();
+        game.hidden_piles[0].push(card);
+        assert_eq!(game.validate(), Err(RuleError::TooManyHidden(0)));
+
+        let mut game = StandardSolitaire::new(&default_shuffle(12), draw_step);
+        game.deck = Deck::new(core::array::from_fn(|_| first), draw_step);
+        assert!(matches!(game.validate(), Err(RuleError::DuplicateCard(_))));
     }
 }
@@ -1,19 +1,19 @@
 use core::num::NonZeroU8;
 
 use arrayvec::ArrayVec;
-use rand::seq::SliceRandom;
 use rand::{Rng, RngCore};
 
 use crate::card::{
-    Card, ALT_MASK, HALF_MASK, KING_MASK, KING_RANK, N_CARDS, N_SUITS, RANK_MASK, SUIT_MASK,
+    Card, ACE_RANK, ALT_MASK, HALF_MASK, KING_MASK, KING_RANK, N_CARDS, N_SUITS, RANK_MASK, SUIT_MASK,
 };
-use crate::deck::{Deck, N_PILES, N_PILE_CARDS};
+use crate::deck::Deck;
+use crate::shape::{N_DECK_CARDS, N_PILES, N_PILE_CARDS};
 use crate::moves::{Move, MoveMask};
 use crate::stack::Stack;
 use crate::utils::full_mask;
 
-use crate::hidden::Hidden;
-use crate::shuffler::CardDeck;
+use crate::hidden::{encode_weight, Hidden};
+use crate::shuffler::{shuffle_with_rng, CardDeck};
 use crate::standard::{PileVec, StandardSolitaire};
 
 #[derive(Debug, Clone)]
@@ -23,10 +23,23 @@ pub struct Solitaire {
     deck: Deck,
 
     visible_mask: u64,
+    // `encode()` of the current state, updated by every move
+    encoded: Encode,
 }
 
+/// Compact key of a game state, see [`Solitaire::encode`]
+///
+/// It's a plain `u64` so it can be stored, compared and hashed as is, e.g. to
+/// memoize values per state (see [`crate::cache::StateValueCache`]). Decoding
+/// it back requires a state dealt from the same cards.
 pub type Encode = u64;
 
+// change of the encoding when a card of the suit of `card` is stacked
+#[must_use]
+const fn stack_encode_step(card: Card) -> Encode {
+    1 << (card.suit() * 4)
+}
+
 #[must_use]
 const fn swap_pair(a: u64) -> u64 {
     let half = (a & HALF_MASK) << 2;
@@ -43,10 +56,13 @@ pub enum ExtraInfo {
 }
 
 impl Solitaire {
-    #[must_use]
+    /// Deal the game of `cards`, which must hold every card once (see
+    /// [`is_valid_deck`](crate::shuffler::is_valid_deck))
+    ///
     /// # Panics
     ///
     /// Never (unless buggy)
+    #[must_use]
     pub fn new(cards: &CardDeck, draw_step: NonZeroU8) -> Self {
         let hidden_piles: [Card; N_PILE_CARDS as usize] =
             cards[0..N_PILE_CARDS as usize].try_into().unwrap();
@@ -70,17 +86,15 @@ impl Solitaire {
             final_stack: Stack::default(),
             deck,
             visible_mask,
+            encoded: 0,
         }
+        .with_encoded()
     }
 
     /// Deal a new random game using the provided RNG.
     #[must_use]
     pub fn deal_with_rng<R: Rng + ?Sized>(rng: &mut R) -> Self {
-        #[allow(clippy::cast_possible_truncation)]
-        let mut cards: CardDeck =
-            core::array::from_fn(|i| Card::new(i as u8 / N_SUITS, i as u8 % N_SUITS));
-        cards.shuffle(rng);
-        Self::new(&cards, NonZeroU8::new(1).unwrap())
+        Self::new(&shuffle_with_rng(rng), NonZeroU8::new(1).unwrap())
     }
 
     #[must_use]
@@ -88,6 +102,18 @@ impl Solitaire {
         &self.deck
     }
 
+    /// The cards that can become the current deck card by drawing, with the
+    /// deck offset at which they are current, see [`Deck::reachable_offsets`]
+    #[must_use]
+    pub fn legal_deck_draws(&self) -> ArrayVec<(u8, Card), { N_DECK_CARDS as usize }> {
+        self.deck
+            .reachable_offsets()
+            .into_iter()
+            .filter(|&offset| offset > 0)
+            .map(|offset| (offset, self.deck.peek(offset - 1)))
+            .collect()
+    }
+
     #[must_use]
     pub const fn get_stack(&self) -> &Stack {
         &self.final_stack
@@ -167,6 +193,22 @@ impl Solitaire {
         }
     }
 
+    /// The foundation moves that can never lose the game, the ones the
+    /// dominance of `gen_moves::<true>` plays without branching
+    #[must_use]
+    pub fn safe_foundation_moves(&self) -> MoveMask {
+        let sm = self.final_stack.mask();
+        let dom_sm = self.final_stack.dominance_mask();
+
+        let pile_stack = self.get_bottom_mask() & self.get_visible_mask() & sm & dom_sm;
+        let (deck_mask, dom) = self.get_deck_mask(dom_sm & sm);
+        MoveMask {
+            pile_stack,
+            deck_stack: if dom { deck_mask } else { 0 },
+            ..Default::default()
+        }
+    }
+
     #[must_use]
     pub fn gen_moves<const DOMINANCE: bool>(&self) -> MoveMask {
         let vis = self.get_visible_mask();
@@ -312,6 +354,34 @@ impl Solitaire {
         }
     }
 
+    /// Reveal moves of a lone card on an empty base while no king could fill
+    /// the freed pile right after
+    ///
+    /// Such a reveal only relocates the run between two piles, exposing nothing
+    /// new and enabling no other move, so it can be postponed until a
+    /// king is ready to take the empty spot
+    #[must_use]
+    pub(crate) fn idle_empty_reveals(&self) -> u64 {
+        let sole =
+            self.get_visible_mask() & self.get_locked_mask() & self.hidden.first_layer_mask();
+        let empty_reveal = sole & !KING_MASK;
+        if empty_reveal == 0 {
+            return 0;
+        }
+
+        let king_from_stack =
+            (0..N_SUITS).any(|s| self.final_stack.top(s).is_some_and(|c| c.is_king()));
+        let king_from_deck = self.get_deck_mask(0).0 & KING_MASK != 0;
+        let king_from_pile =
+            self.get_visible_mask() & self.get_locked_mask() & KING_MASK & !sole != 0;
+
+        if king_from_stack || king_from_deck || king_from_pile {
+            0
+        } else {
+            empty_reveal
+        }
+    }
+
     #[must_use]
     const fn reverse_move(&self, m: Move) -> Option<Move> {
         // check if this move can be undo using a legal move in the game
@@ -331,6 +401,7 @@ impl Solitaire {
     fn make_stack<const DECK: bool>(&mut self, card: Card) -> (UndoInfo, ExtraInfo) {
         let mask = card.mask();
         self.final_stack.push(card.suit());
+        self.encoded += stack_encode_step(card);
 
         if DECK {
             let (found, pos) = self.deck.find_card(card);
@@ -338,6 +409,7 @@ impl Solitaire {
 
             let old_offset = self.deck.get_offset();
             self.deck.draw(pos);
+            self.update_deck_encode();
             (old_offset, ExtraInfo::None)
         } else {
             let locked = (self.get_locked_mask() & mask) != 0;
@@ -354,10 +426,12 @@ impl Solitaire {
     fn unmake_stack<const DECK: bool>(&mut self, card: Card, info: UndoInfo) {
         let mask = card.mask();
         self.final_stack.pop(card.suit());
+        self.encoded -= stack_encode_step(card);
 
         if DECK {
             self.deck.push(card);
             self.deck.set_offset(info);
+            self.update_deck_encode();
         } else {
             self.visible_mask |= mask;
             if info > 0 {
@@ -376,9 +450,11 @@ impl Solitaire {
 
                 let old_offset = self.deck.get_offset();
                 self.deck.draw(pos);
+                self.update_deck_encode();
                 old_offset
             } else {
                 self.final_stack.pop(card.suit());
+                self.encoded -= stack_encode_step(card);
                 Default::default()
             },
             ExtraInfo::None,
@@ -391,12 +467,15 @@ impl Solitaire {
         if DECK {
             self.deck.push(card);
             self.deck.set_offset(info);
+            self.update_deck_encode();
         } else {
             self.final_stack.push(card.suit());
+            self.encoded += stack_encode_step(card);
         }
     }
 
     fn make_reveal(&mut self, card: Card) -> ExtraInfo {
+        self.encoded -= self.hidden_encode_step(card);
         if let Some(&new_card) = self.hidden.pop_card(card) {
             self.visible_mask |= new_card.mask();
             ExtraInfo::Card(new_card)
@@ -406,6 +485,7 @@ impl Solitaire {
     }
 
     fn unmake_reveal(&mut self, card: Card) {
+        self.encoded += self.hidden_encode_step(card);
         if let Some(new_card) = self.hidden.unpop_card(card) {
             self.visible_mask &= !new_card.mask();
         }
@@ -462,6 +542,30 @@ impl Solitaire {
             | (u64::from(deck_encode) << (16 + 16))
     }
 
+    /// Same value as [`Self::encode`], but kept up to date by the moves
+    /// instead of being computed from the whole state
+    #[must_use]
+    pub const fn encode_cached(&self) -> Encode {
+        self.encoded
+    }
+
+    #[must_use]
+    fn with_encoded(mut self) -> Self {
+        self.encoded = self.encode();
+        self
+    }
+
+    fn update_deck_encode(&mut self) {
+        self.encoded = (self.encoded & full_mask(16 + 16))
+            | (u64::from(self.deck.encode()) << (16 + 16));
+    }
+
+    // change of the encoding when the pile of the hidden `card` gets one card
+    #[must_use]
+    const fn hidden_encode_step(&self, card: Card) -> Encode {
+        (encode_weight(self.hidden.find(card)) as Encode) << 16
+    }
+
     #[must_use]
     fn compute_visible_mask(&self) -> u64 {
         let mut nonvis_mask = 0;
@@ -494,6 +598,7 @@ impl Solitaire {
         self.deck.decode(deck_encode);
 
         self.visible_mask = self.compute_visible_mask();
+        self.encoded = self.encode();
     }
 
     #[must_use]
@@ -525,7 +630,7 @@ impl Solitaire {
                 // push start card
                 cards.push(start_card);
 
-                if start_card.rank() == 0 {
+                if start_card.rank() == ACE_RANK {
                     break;
                 }
 
@@ -607,7 +712,9 @@ impl From<&StandardSolitaire> for Solitaire {
             final_stack: *game.get_stack(),
             deck: game.get_deck().clone(),
             visible_mask,
+            encoded: 0,
         }
+        .with_encoded()
     }
 }
 
@@ -617,7 +724,8 @@ mod tests {
     use core::ops::ControlFlow;
     use rand::prelude::*;
 
-    use crate::deck::{Drawable, N_DECK_CARDS};
+    use crate::deck::Drawable;
+    use crate::shape::N_DECK_CARDS;
     use crate::moves::N_MOVES_MAX;
     use crate::shuffler::default_shuffle;
 
@@ -658,6 +766,12 @@ mod tests {
 
                 assert_eq!(test, truth);
 
+                // the current card at each reachable offset
+                let mut draws = game.legal_deck_draws();
+                draws.iter_mut().for_each(|x| x.0 -= 1);
+                draws.sort_by_key(|x| x.0);
+                assert_eq!(draws, truth);
+
                 let moves = game.gen_moves::<false>().to_vec::<N_MOVES_MAX>();
                 if moves.is_empty() {
                     break;
@@ -757,6 +871,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_encode_cached() {
+        let mut rng = SmallRng::seed_from_u64(14);
+
+        for i in 0..1000 {
+            let draw_step = NonZeroU8::new(if i % 2 == 0 { 1 } else { 3 }).unwrap();
+            let mut game = Solitaire::new(&default_shuffle(12 + i), draw_step);
+            let mut history = ArrayVec::<(Move, UndoInfo), 100>::new();
+            assert_eq!(game.encode_cached(), game.encode());
+
+            for _ in 0..100 {
+                let moves = game.gen_moves::<false>().to_vec::<N_MOVES_MAX>();
+                if moves.is_empty() {
+                    break;
+                }
+
+                let m = *moves.choose(&mut rng).unwrap();
+                let (_, (undo, _)) = game.do_move(m);
+                history.push((m, undo));
+                assert_eq!(game.encode_cached(), game.encode());
+
+                if rng.random_bool(0.2) {
+                    let (m, undo) = history.pop().unwrap();
+                    game.undo_move(m, undo);
+                    assert_eq!(game.encode_cached(), game.encode());
+                }
+            }
+
+            while let Some((m, undo)) = history.pop() {
+                game.undo_move(m, undo);
+                assert_eq!(game.encode_cached(), game.encode());
+            }
+
+            let state = game.encode();
+            game.decode(state);
+            assert_eq!(game.encode_cached(), state);
+        }
+    }
+
     #[test]
     fn shuffle_hidden() {
         let mut rng = SmallRng::seed_from_u64(14);
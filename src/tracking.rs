@@ -1,3 +1,8 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 pub trait SearchStatistics {
     fn hit_a_state(&self, depth: usize);
     fn hit_unique_state(&self, depth: usize, n_moves: u32);
@@ -22,3 +27,125 @@ pub trait TerminateSignal {
 pub struct DefaultTerminateSignal;
 
 impl TerminateSignal for DefaultTerminateSignal {}
+
+/// A cloneable termination flag that can be shared with another thread to
+/// cancel a running search.
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl TerminateSignal for CancelToken {
+    fn terminate(&self) {
+        self.cancel();
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+/// Stop a search after visiting `limit` states, both the statistics and the
+/// termination signal of the search
+#[derive(Debug)]
+pub struct NodeLimit {
+    limit: usize,
+    visited: Cell<usize>,
+}
+
+impl NodeLimit {
+    #[must_use]
+    pub const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            visited: Cell::new(0),
+        }
+    }
+
+    #[must_use]
+    pub fn visited(&self) -> usize {
+        self.visited.get()
+    }
+}
+
+impl SearchStatistics for NodeLimit {
+    fn hit_a_state(&self, _: usize) {
+        self.visited.set(self.visited.get() + 1);
+    }
+    fn hit_unique_state(&self, _: usize, _: u32) {}
+    fn finish_move(&self, _: usize) {}
+}
+
+impl TerminateSignal for NodeLimit {
+    fn is_terminated(&self) -> bool {
+        self.visited.get() >= self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::num::NonZeroU8;
+
+    use super::*;
+    use crate::{
+        shuffler::default_shuffle,
+        solver::{solve_with_tracking, SearchResult},
+        state::Solitaire,
+    };
+
+    struct VisitHook<F: Fn()>(F);
+
+    impl<F: Fn()> SearchStatistics for VisitHook<F> {
+        fn hit_a_state(&self, _: usize) {
+            (self.0)();
+        }
+        fn hit_unique_state(&self, _: usize, _: u32) {}
+        fn finish_move(&self, _: usize) {}
+    }
+
+    #[test]
+    fn test_cancel_mid_solve() {
+        let token = CancelToken::new();
+        let visits = Cell::new(0usize);
+        let stats = VisitHook(|| {
+            visits.set(visits.get() + 1);
+            if visits.get() == 10 {
+                token.cancel();
+            }
+        });
+
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let (res, history) = solve_with_tracking(&mut game, &stats, &token);
+        assert_eq!(res, SearchResult::Terminated);
+        assert!(history.is_none());
+        assert_eq!(visits.get(), 10);
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let mut game = Solitaire::new(&default_shuffle(12), NonZeroU8::new(3).unwrap());
+        let limit = NodeLimit::new(100);
+        let (res, _) = solve_with_tracking(&mut game.clone(), &limit, &limit);
+        assert_eq!(res, SearchResult::Terminated);
+        assert_eq!(limit.visited(), 100);
+
+        let limit = NodeLimit::new(usize::MAX);
+        let (res, history) = solve_with_tracking(&mut game, &limit, &limit);
+        assert_eq!(res, SearchResult::Solved);
+        assert!(history.is_some() && limit.visited() > 100);
+    }
+}
@@ -1,8 +1,9 @@
 use hashbrown::HashSet;
+use static_assertions::const_assert;
 
 use crate::{
     moves::{Move, MoveMask},
-    pruning::Pruner,
+    pruning::{FullPruner, Pruner},
     state::{Encode, Solitaire},
     utils::MixHasherBuilder,
 };
@@ -59,7 +60,65 @@ impl TranspositionTable for TpTable {
     }
 }
 
-// it guarantee to return the state of g back into normal state
+/// A transposition table keeping at most `capacity` states
+///
+/// When full the oldest half of the states is forgotten. A forgotten state is
+/// searched again when it is reached again, which is slower but doesn't change
+/// the result of the search.
+#[derive(Debug)]
+pub struct BoundedTpTable {
+    current: TpTable,
+    previous: TpTable,
+    capacity: usize,
+}
+
+impl BoundedTpTable {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            current: TpTable::default(),
+            previous: TpTable::default(),
+            capacity,
+        }
+    }
+
+    /// Table using about `bytes` of memory
+    #[must_use]
+    pub fn with_bytes(bytes: usize) -> Self {
+        // every entry of a set also has a control byte
+        Self::new(bytes / (core::mem::size_of::<Encode>() + 1))
+    }
+}
+
+impl TranspositionTable for BoundedTpTable {
+    fn clear(&mut self) {
+        self.current.clear();
+        self.previous.clear();
+    }
+
+    fn insert(&mut self, value: Encode) -> bool {
+        if self.previous.contains(&value) || self.current.contains(&value) {
+            return false;
+        }
+        if self.current.len() >= self.capacity / 2 {
+            self.previous = core::mem::take(&mut self.current);
+        }
+        self.current.insert(value)
+    }
+}
+
+// the per node data of `traverse` lives on the stack: none of it can own a heap buffer
+const_assert!(!core::mem::needs_drop::<MoveMask>());
+const_assert!(!core::mem::needs_drop::<FullPruner>());
+
+/// Depth first search of the states reachable from `game`, reporting to `callback`
+///
+/// It guarantees to return the state of `game` back to what it was.
+///
+/// A node doesn't allocate: the moves are generated into a [`MoveMask`]
+/// bitset and played in place on `game`, and the pruner is copied by value
+/// down the recursion. The only heap allocations of a search come from `tp`
+/// growing and from what the callback does itself.
 pub fn traverse<T: TranspositionTable, C: Callback>(
     game: &mut Solitaire,
     prune_info: C::Pruner,
@@ -70,7 +129,7 @@ pub fn traverse<T: TranspositionTable, C: Callback>(
         return callback.on_win(game);
     }
 
-    let encode = game.encode();
+    let encode = game.encode_cached();
 
     match callback.on_visit(game, encode) {
         Control::Halt => return Control::Halt,
@@ -16,6 +16,67 @@ pub(crate) const fn full_mask(i: u8) -> u64 {
     (1 << i) - 1
 }
 
+#[must_use]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    // f64::sqrt is not available in core, Newton's method converges in a few steps
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut r = if x > 1.0 { x } else { 1.0 };
+    for _ in 0..64 {
+        let next = 0.5 * (r + x / r);
+        if next >= r {
+            break;
+        }
+        r = next;
+    }
+    r
+}
+
+#[must_use]
+pub(crate) fn exp(x: f64) -> f64 {
+    // f64::exp is not available in core, the series converges fast on a small
+    // argument which is then squared back up
+    if x < -746.0 {
+        return 0.0;
+    }
+    if x > 710.0 {
+        return f64::INFINITY;
+    }
+    let mut r = x;
+    let mut halvings = 0;
+    while !(-0.5..=0.5).contains(&r) {
+        r *= 0.5;
+        halvings += 1;
+    }
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for i in 1..24 {
+        term *= r / f64::from(i);
+        sum += term;
+    }
+    for _ in 0..halvings {
+        sum *= sum;
+    }
+    sum
+}
+
+/// `x.round() as i32` (halfway cases away from zero), `f64::round` is not available in core
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn round(x: f64) -> i32 {
+    let int = x as i32;
+    // exact for any value fitting in an i32
+    let frac = x - f64::from(int);
+    if frac >= 0.5 {
+        int + 1
+    } else if frac <= -0.5 {
+        int - 1
+    } else {
+        int
+    }
+}
+
 #[inline]
 const fn mix(mut h: u64) -> u64 {
     // the mix function is the mixer from fasthash64 from here https://github.com/rurban/smhasher/
@@ -51,3 +112,37 @@ impl BuildHasher for MixHasherBuilder {
         MixHasher(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round() {
+        for i in -2000..=2000 {
+            for x in [f64::from(i) / 8.0, f64::from(i) / 3.0, f64::from(i) * 0.1] {
+                assert_eq!(round(x), x.round() as i32, "{x}");
+            }
+        }
+        for x in [0.499_999_999_999_999_94, -0.499_999_999_999_999_94, 1e9 + 0.5, -1e9 - 0.5] {
+            assert_eq!(round(x), x.round() as i32, "{x}");
+        }
+    }
+
+    #[test]
+    fn test_sqrt() {
+        for i in 0..1000 {
+            let x = f64::from(i) * 0.37;
+            assert!((sqrt(x) - x.sqrt()).abs() < 1e-9, "{x}");
+        }
+    }
+
+    #[test]
+    fn test_exp() {
+        for i in -2000..200 {
+            let x = f64::from(i) * 0.37;
+            assert!((exp(x) - x.exp()).abs() <= 1e-12 * x.exp(), "{x}");
+        }
+        assert_eq!(exp(-1000.0), 0.0);
+    }
+}
@@ -0,0 +1,42 @@
+//! Helpers shared by the integration tests
+
+use lonelybot::card::{Card, N_RANKS, N_SUITS};
+use lonelybot::partial::PartialStateBuilder;
+use lonelybot::stack::Foundation;
+use lonelybot::state::Solitaire;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+/// A fully known game of draw step 1 with `columns`, each its hidden cards
+/// from the top then its visible cards from the bottom, and `deck`, the other
+/// cards being on the foundations
+pub fn known_game(columns: [(&[Card], &[Card]); 7], deck: &[Card]) -> Solitaire {
+    let in_play: Vec<Card> = columns
+        .iter()
+        .flat_map(|&(hidden, visible)| hidden.iter().chain(visible))
+        .chain(deck)
+        .copied()
+        .collect();
+    let mut foundations = Foundation::default();
+    for suit in 0..N_SUITS {
+        for rank in 0..N_RANKS {
+            let card = Card::new(rank, suit);
+            if in_play.contains(&card) {
+                break;
+            }
+            foundations.push(card);
+        }
+    }
+
+    let builder = columns.iter().fold(PartialStateBuilder::new(), |b, &(hidden, visible)| {
+        let hidden: Vec<Option<Card>> = hidden.iter().copied().map(Some).collect();
+        b.column(&hidden, visible)
+    });
+    let deck: Vec<Option<Card>> = deck.iter().copied().map(Some).collect();
+    let state = builder.deck(&deck).foundations(foundations).build().unwrap();
+    let filled = state.fill_unknowns_randomly(&mut SmallRng::seed_from_u64(0)).unwrap();
+    let game: Solitaire = (&filled).into();
+    // every card is where it was put
+    assert!(state.compatible_with(&game));
+    game
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::num::NonZeroU8;
+
+use lonelybot::card::Card;
+use lonelybot::engine::{GameStatus, SolitaireEngine};
+use lonelybot::moves::Move;
+use lonelybot::pruning::FullPruner;
+use lonelybot::shuffler::default_shuffle;
+use lonelybot::state::Solitaire;
+
+// every card that could move is buried under the two queens
+fn stuck_game() -> Solitaire {
+    let c = Card::new;
+    common::known_game(
+        [
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[c(10, 1), c(12, 1), c(12, 3)], &[c(11, 1)]),
+            (&[c(9, 0), c(10, 0), c(11, 0), c(12, 0), c(10, 2), c(12, 2)], &[c(11, 2)]),
+        ],
+        &[],
+    )
+}
+
+#[test]
+fn test_has_any_move() {
+    let engine: SolitaireEngine<FullPruner> = stuck_game().into();
+    assert!(engine.list_moves().is_empty());
+    assert!(!engine.has_any_move());
+
+    let engine: SolitaireEngine<FullPruner> =
+        Solitaire::new(&default_shuffle(0), NonZeroU8::new(3).unwrap()).into();
+    assert!(!engine.list_moves_dom().is_empty());
+    assert!(engine.has_any_move());
+}
+
+#[test]
+fn test_status() {
+    let c = Card::new;
+    let king = common::known_game(
+        [
+            (&[], &[c(12, 3)]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+        ],
+        &[],
+    );
+    let mut engine: SolitaireEngine<FullPruner> = king.into();
+    assert_eq!(engine.status(), GameStatus::Ongoing);
+    // the last king goes up
+    assert!(engine.do_move(Move::PileStack(c(12, 3))));
+    assert_eq!(engine.status(), GameStatus::Won);
+
+    let engine: SolitaireEngine<FullPruner> = stuck_game().into();
+    assert_eq!(engine.status(), GameStatus::Lost);
+}
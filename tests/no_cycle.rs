@@ -1,8 +1,12 @@
+mod common;
+
 use std::num::NonZeroU8;
 
 use lonelybot::{
-    moves::MoveMask,
-    pruning::FullPruner,
+    card::Card,
+    engine::SolitaireEngine,
+    moves::{Move, MoveMask},
+    pruning::{FullPruner, NoPruner},
     shuffler::ks_shuffle,
     state::{Encode, Solitaire},
     traverse::{traverse, Callback, Control, TpTable},
@@ -49,3 +53,53 @@ fn test_no_cycle() {
         assert_eq!(res, Control::Ok);
     }
 }
+
+#[test]
+fn test_prune_idle_empty_reveal() {
+    let c = Card::new;
+    // Q♥ alone on an empty base, every king on its own pile
+    let game = common::known_game(
+        [
+            (&[], &[]),
+            (&[], &[c(12, 0)]),
+            (&[], &[c(12, 1)]),
+            (&[], &[c(12, 2)]),
+            (&[], &[c(12, 3)]),
+            (&[], &[c(11, 0)]),
+            (&[], &[]),
+        ],
+        &[],
+    );
+    let full: SolitaireEngine<FullPruner> = game.clone().into();
+    let none: SolitaireEngine<NoPruner> = game.into();
+
+    // moving Q♥ onto a black king only relocates it...
+    assert!(none.list_moves().contains(&Move::Reveal(c(11, 0))));
+    assert!(!full.list_moves().contains(&Move::Reveal(c(11, 0))));
+    // ...while it can go to the foundation
+    assert!(full.list_moves().contains(&Move::PileStack(c(11, 0))));
+}
+
+#[test]
+fn test_keep_idle_empty_reveal_when_alone() {
+    let c = Card::new;
+    // every king and every card the foundation waits for is buried
+    let game = common::known_game(
+        [
+            (&[], &[c(10, 0)]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[c(10, 1), c(12, 1), c(12, 3)], &[c(11, 1)]),
+            (&[c(9, 0), c(11, 0), c(12, 0), c(10, 2), c(12, 2)], &[c(11, 2)]),
+        ],
+        &[],
+    );
+    let engine: SolitaireEngine<FullPruner> = game.into();
+
+    // J♥ onto Q♣ is the only move besides taking cards back from the foundation
+    let moves = engine.list_moves();
+    assert!(moves.contains(&Move::Reveal(c(10, 0))));
+    assert!(moves.iter().all(|m| matches!(m, Move::Reveal(_) | Move::StackPile(_))));
+}
@@ -1,9 +1,29 @@
-use lonelybot::partial::{PartialColumn, PartialState};
+mod common;
+
+use lonelybot::partial::{
+    BuildError, CompletionSeed, DecodeError, FillError, ObsError, PartialColumn, PartialState,
+    PartialStateBuilder, UNKNOWN_BYTE,
+};
 use lonelybot::card::Card;
+use lonelybot::deck::Drawable;
 use lonelybot::standard::PileVec;
+use lonelybot::stack::Foundation;
 use rand::rngs::SmallRng;
+use rand::seq::IndexedRandom;
 use rand::SeedableRng;
-use lonelybot::analysis::analyze_state;
+use lonelybot::moves::{AbstractMove, Move};
+use lonelybot::analysis::{
+    analyze_state, analyze_state_with_moves, blocked_suits, deadlock_risk_calibrated, ranked_moves,
+    ranked_moves_from_partial, ranked_moves_from_partial_with_rng, sample_move,
+    score_move_from_partial, sort_ranked_moves, AnalysisSession, HeuristicConfig, PlayStyle,
+    RankKey, SESSION_CACHE_SIZE,
+};
+use lonelybot::engine::SolitaireEngine;
+use lonelybot::pruning::FullPruner;
+use lonelybot::shuffler::default_shuffle;
+use lonelybot::standard::StandardSolitaire;
+use lonelybot::state::Solitaire;
+use std::num::NonZeroU8;
 
 #[test]
 fn test_fill_unknown() {
@@ -12,12 +32,101 @@ fn test_fill_unknown() {
         p.push(Card::new(0,0));
         p
     }};
-    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], draw_step: 1 };
+    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], deck_offset: 0, draw_step: 1, redeal_limit: None, foundations: Foundation::default() };
     let mut rng = SmallRng::seed_from_u64(0);
-    let g = state.fill_unknowns_randomly(&mut rng);
+    let g = state.fill_unknowns_randomly(&mut rng).unwrap();
     assert_eq!(g.get_deck().len(), 24);
 }
 
+#[test]
+fn test_fill_keeps_board() {
+    let mut std = StandardSolitaire::new(&default_shuffle(3), NonZeroU8::new(3).unwrap());
+    std.draw_stock().unwrap();
+    std.draw_stock().unwrap();
+    let sol: Solitaire = (&std).into();
+    let state = PartialState::from_blind(&sol);
+    let mut rng = SmallRng::seed_from_u64(0);
+    let filled = state.fill_unknowns_randomly(&mut rng).unwrap();
+    assert_eq!(filled.get_piles(), std.get_piles());
+    assert_eq!(filled.get_deck().waste_iter().len(), 6);
+    assert_eq!(filled.get_deck().peek_current(), std.get_deck().peek_current());
+    for (h, std_h) in filled.get_hidden().iter().zip(std.get_hidden()) {
+        assert_eq!(h.len(), std_h.len());
+    }
+    assert!(state.compatible_with(&(&filled).into()));
+
+    // the first column has no room for a hidden card
+    let mut state = state;
+    let card = state.columns[1].hidden.pop().unwrap();
+    state.columns[0].hidden.push(card);
+    assert_eq!(state.fill_unknowns_randomly(&mut rng).err(), Some(FillError::InvalidBoard));
+}
+
+#[test]
+fn test_fill_not_enough_cards() {
+    let game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(1).unwrap());
+    let mut state: PartialState = (&game).into();
+    // every card is known but one more is unknown
+    state.deck.push(None);
+    let mut rng = SmallRng::seed_from_u64(0);
+    assert_eq!(state.fill_unknowns_randomly(&mut rng).err(), Some(FillError::NotEnoughCards));
+    let probs = state.column_probabilities();
+    assert_eq!(state.fill_unknowns_weighted(&probs, &mut rng).err(), Some(FillError::NotEnoughCards));
+    state.columns[0].hidden.push(None);
+    state.deck.pop();
+    assert_eq!(state.fill_unknowns_weighted(&probs, &mut rng).err(), Some(FillError::NotEnoughCards));
+    assert!(ranked_moves_from_partial(&state, PlayStyle::Neutral, &HeuristicConfig::default()).is_empty());
+
+    // a card given twice
+    let mut state: PartialState = (&game).into();
+    state.deck.push(state.deck[0]);
+    assert_eq!(state.fill_unknowns_randomly(&mut rng).err(), Some(FillError::TooManyCards));
+}
+
+#[test]
+fn test_foundation_card_in_play() {
+    let ace = Card::new(0, 2);
+    let mut foundations = Foundation::default();
+    assert!(foundations.push(ace));
+    let builder = (0..7).fold(PartialStateBuilder::new(), |b, _| b.column(&[None], &[]));
+    assert!(builder.clone().foundations(foundations).build().is_ok());
+    assert_eq!(
+        builder.clone().deck(&[Some(ace)]).foundations(foundations).build(),
+        Err(BuildError::FoundationCard(ace))
+    );
+
+    // the same state set by hand can't be filled
+    let game = StandardSolitaire::new(&default_shuffle(0), NonZeroU8::new(1).unwrap());
+    let mut state: PartialState = (&game).into();
+    // the ace is still in play, a deck card makes room for it
+    let pos = state.deck.iter().position(|&c| c != Some(ace)).unwrap();
+    state.deck.remove(pos);
+    state.foundations = foundations;
+    let mut rng = SmallRng::seed_from_u64(0);
+    assert_eq!(state.fill_unknowns_randomly(&mut rng).err(), Some(FillError::FoundationCard));
+}
+
+#[test]
+fn test_column_accepts() {
+    let empty = PartialColumn { hidden: vec![], visible: PileVec::new() };
+    assert_eq!(empty.top_visible(), None);
+    assert!(Card::all().filter(|&c| empty.accepts(c)).all(Card::is_king));
+    assert_eq!(Card::all().filter(|&c| empty.accepts(c)).count(), 4);
+
+    // nothing goes on a column waiting for a reveal
+    let unrevealed = PartialColumn { hidden: vec![None], visible: PileVec::new() };
+    assert!(!Card::all().any(|c| unrevealed.accepts(c)));
+
+    let seven_spades = Card::new(6, 3);
+    let mut visible = PileVec::new();
+    visible.push(seven_spades);
+    let col = PartialColumn { hidden: vec![None], visible };
+    assert_eq!(col.top_visible(), Some(seven_spades));
+    let accepted: Vec<Card> = Card::all().filter(|&c| col.accepts(c)).collect();
+    assert_eq!(accepted.len(), 2);
+    assert!(accepted.contains(&Card::new(5, 0)) && accepted.contains(&Card::new(5, 1)));
+}
+
 #[test]
 fn test_analyze_state() {
     let col = PartialColumn { hidden: vec![None], visible: {
@@ -25,12 +134,122 @@ fn test_analyze_state() {
         p.push(Card::new(0,0));
         p
     }};
-    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], draw_step: 1 };
+    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], deck_offset: 0, draw_step: 1, redeal_limit: None, foundations: Foundation::default() };
     let info = analyze_state(&state);
     assert_eq!(info.unknown_cards, 8);
     assert!(info.mobility > 0);
 }
 
+#[test]
+fn test_useful_stock_cards() {
+    let tops = [(4, 0), (12, 1), (7, 2), (9, 3), (2, 1), (10, 0), (8, 2)];
+    let builder = tops.iter().enumerate().fold(PartialStateBuilder::new(), |b, (i, &(rank, suit))| {
+        b.column(&vec![None; i], &[Card::new(rank, suit)])
+    });
+    // only the four of spades has somewhere to go, on the five of hearts
+    let mut deck = vec![Some(Card::new(3, 3)), Some(Card::new(5, 0)), Some(Card::new(1, 0)), Some(Card::new(11, 0))];
+    deck.resize(24, None);
+    let state = builder.deck(&deck).build().unwrap();
+    assert_eq!(analyze_state(&state).useful_stock_cards, 1);
+}
+
+#[test]
+fn test_blocked_suits() {
+    let (ace, five) = (Card::new(0, 0), Card::new(4, 0));
+    let (six_c, six_s) = (Card::new(5, 2), Card::new(5, 3));
+    let build = |other: &[Card], buried: &[Option<Card>]| {
+        PartialStateBuilder::new()
+            .column(buried, &[five])
+            .column(&[None], other)
+            .column(&[None; 2], &[Card::new(12, 1)])
+            .column(&[None; 3], &[Card::new(7, 2)])
+            .column(&[None; 4], &[Card::new(9, 3)])
+            .column(&[None; 5], &[Card::new(2, 1)])
+            .column(&[None; 6], &[Card::new(10, 0)])
+            // 27 cards besides `buried`, `other` and the deck
+            .deck(&vec![None; 25 - buried.len() - other.len()])
+            .build()
+            .unwrap()
+    };
+
+    // the ace of hearts is under the five, which can only go on the sixes under it
+    let state = build(&[Card::new(1, 1)], &[Some(ace), Some(six_c), None, Some(six_s)]);
+    assert_eq!(blocked_suits(&state), vec![0]);
+    assert_eq!(analyze_state(&state).blocked_suits, vec![0]);
+
+    // a six the five can go on is elsewhere
+    let state = build(&[six_s], &[Some(ace), Some(six_c), None]);
+    assert!(blocked_suits(&state).is_empty());
+    // the ace isn't under the five
+    let state = build(&[ace], &[Some(six_c), Some(six_s)]);
+    assert!(blocked_suits(&state).is_empty());
+}
+
+#[test]
+fn test_analyze_state_sampled_moves() {
+    let game = StandardSolitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+    let state = PartialState::from(&game);
+
+    assert!(analyze_state(&state).sampled_moves.is_none());
+
+    let info = analyze_state_with_moves(&state, true);
+    let moves = info.sampled_moves.unwrap();
+    assert!(info.mobility > 0);
+    assert_eq!(moves.len(), info.mobility);
+}
+
+#[test]
+fn test_deck_move_probability() {
+    // a large coefficient keeps the weighting visible after rounding
+    let cfg = HeuristicConfig { explain: true, aggressive_coef: 10, ..HeuristicConfig::default() };
+    let (game, m) = (0..20)
+        .find_map(|seed| {
+            let game = StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(1).unwrap());
+            let engine: SolitaireEngine<FullPruner> = Solitaire::from(&game).into();
+            let ranked = ranked_moves(&engine, &(&game).into(), PlayStyle::Aggressive, &cfg);
+            ranked
+                .into_iter()
+                .find(|r| matches!(r.mv, Move::DeckPile(_) | Move::DeckStack(_)) && r.heuristic_score != 0)
+                .map(|r| (game, r.mv))
+        })
+        .unwrap();
+    let (Move::DeckPile(card) | Move::DeckStack(card)) = m else { unreachable!() };
+    let engine: SolitaireEngine<FullPruner> = Solitaire::from(&game).into();
+    let find = |state: &PartialState| {
+        ranked_moves(&engine, state, PlayStyle::Aggressive, &cfg)
+            .into_iter()
+            .find(|r| r.mv == m)
+            .unwrap()
+    };
+
+    // a known deck card is certain
+    let known: PartialState = (&game).into();
+    assert_eq!(known.deck_card_probability(card), 1.0);
+    let certain = find(&known);
+    assert!(certain.score_breakdown.iter().all(|t| t.0 != "probability"));
+
+    // every card left unknown is in the deck
+    let mut unknown = known.clone();
+    for c in &mut unknown.deck {
+        *c = None;
+    }
+    assert_eq!(unknown.deck_card_probability(card), 1.0);
+
+    // an unknown one is weighted by the chance of being in the deck
+    for col in &mut unknown.columns {
+        col.hidden.fill(None);
+    }
+    let n_deck = unknown.deck.len();
+    assert_eq!(unknown.deck_card_probability(card), n_deck as f64 / unknown.unknown_count() as f64);
+    let weighted = find(&unknown);
+    assert!(weighted.heuristic_score.abs() < certain.heuristic_score.abs());
+    assert!(weighted.score_breakdown.iter().any(|t| t.0 == "probability"));
+
+    // the card is known to be elsewhere
+    unknown.columns[0].hidden.push(Some(card));
+    assert_eq!(unknown.deck_card_probability(card), 0.0);
+}
+
 #[test]
 fn test_fill_unknown_weighted_zero_sum() {
     let col = PartialColumn { hidden: vec![None], visible: {
@@ -38,10 +257,871 @@ fn test_fill_unknown_weighted_zero_sum() {
         p.push(Card::new(0,0));
         p
     }};
-    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], draw_step: 1 };
+    let state = PartialState { columns: [col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col.clone(), col], deck: vec![None], deck_offset: 0, draw_step: 1, redeal_limit: None, foundations: Foundation::default() };
     let probs: Vec<Vec<(Card, f64)>> = vec![Vec::new(); 7];
     let mut rng = SmallRng::seed_from_u64(0);
-    let g = state.fill_unknowns_weighted(&probs, &mut rng);
+    let g = state.fill_unknowns_weighted(&probs, &mut rng).unwrap();
     let expected = Card::new(4, 1); // mask index 17 chosen by rng
     assert_eq!(g.get_piles()[0][0], expected);
 }
+
+#[test]
+fn test_sample_completions() {
+    let game = Solitaire::new(&default_shuffle(5), NonZeroU8::new(3).unwrap());
+    let state = PartialState::from_blind(&game);
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    let samples: Vec<StandardSolitaire> = state.sample_completions(&mut rng).take(10).map(Result::unwrap).collect();
+    assert_eq!(samples.len(), 10);
+    for g in &samples {
+        let mut cards: Vec<u8> = g
+            .get_hidden()
+            .iter()
+            .flatten()
+            .chain(g.get_piles().iter().flatten())
+            .copied()
+            .chain(g.get_deck().iter_all().map(|(_, c, _)| c))
+            .map(Card::mask_index)
+            .collect();
+        cards.sort_unstable();
+        assert_eq!(cards, (0..52).collect::<Vec<u8>>());
+    }
+    // the known cards stay in place
+    assert!(samples.iter().all(|g| state.compatible_with(&g.into())));
+}
+
+#[test]
+fn test_fill_from_seed() {
+    let game = Solitaire::new(&default_shuffle(5), NonZeroU8::new(3).unwrap());
+    let state = PartialState::from_blind(&game);
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    // every card in its place
+    let encode = |g: &StandardSolitaire| -> Vec<Card> {
+        let hidden = g.get_hidden().iter().flatten();
+        let piles = g.get_piles().iter().flatten();
+        hidden.chain(piles).copied().chain(g.get_deck().iter()).collect()
+    };
+    let samples: Vec<_> = (0..10).map(|_| state.sample_completion(&mut rng).unwrap()).collect();
+    for (g, seed) in &samples {
+        assert_eq!(encode(&state.fill_from_seed(*seed).unwrap()), encode(g));
+        assert!(state.compatible_with(&g.into()));
+    }
+    // the seeds give different boards
+    assert!(samples.iter().any(|(g, _)| encode(g) != encode(&samples[0].0)));
+    assert_eq!(
+        encode(&state.fill_from_seed(CompletionSeed(7)).unwrap()),
+        encode(&state.fill_from_seed(CompletionSeed(7)).unwrap())
+    );
+}
+
+#[test]
+fn test_builder_matches_hand_built() {
+    let visible = |cards: &[Card]| -> PileVec { cards.iter().copied().collect() };
+    let hand_built = PartialState {
+        columns: [
+            PartialColumn { hidden: vec![], visible: visible(&[Card::new(12, 0)]) },
+            PartialColumn { hidden: vec![None], visible: visible(&[Card::new(3, 1), Card::new(2, 2)]) },
+            PartialColumn { hidden: vec![Some(Card::new(0, 3)), None], visible: visible(&[Card::new(7, 3)]) },
+            PartialColumn { hidden: vec![], visible: PileVec::new() },
+            PartialColumn { hidden: vec![None; 3], visible: visible(&[Card::new(9, 0)]) },
+            PartialColumn { hidden: vec![None; 4], visible: visible(&[Card::new(5, 2)]) },
+            PartialColumn { hidden: vec![None; 5], visible: visible(&[Card::new(10, 1)]) },
+        ],
+        deck: vec![None, Some(Card::new(1, 1))],
+        deck_offset: 1,
+        draw_step: 3,
+        redeal_limit: None,
+        foundations: Foundation::default(),
+    };
+
+    let builder = PartialStateBuilder::new()
+        .column(&[], &[Card::new(12, 0)])
+        .column(&[None], &[Card::new(3, 1), Card::new(2, 2)])
+        .column(&[Some(Card::new(0, 3)), None], &[Card::new(7, 3)])
+        .column(&[], &[])
+        .column(&[None; 3], &[Card::new(9, 0)])
+        .column(&[None; 4], &[Card::new(5, 2)]);
+
+    assert_eq!(builder.clone().build(), Err(BuildError::ColumnCount(6)));
+
+    let builder = builder
+        .column(&[None; 5], &[Card::new(10, 1)])
+        .deck(&[None, Some(Card::new(1, 1))])
+        .draw_step(3);
+    assert_eq!(builder.clone().deck_offset(3).build(), Err(BuildError::DeckOffset(3)));
+    assert_eq!(builder.deck_offset(1).build(), Ok(hand_built));
+}
+
+#[test]
+fn test_ranked_moves_from_partial_known_state() {
+    let cfg = HeuristicConfig::default();
+    for seed in 0..5 {
+        let game = StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+        let state: PartialState = (&game).into();
+        let engine: SolitaireEngine<FullPruner> = lonelybot::state::Solitaire::from(&game).into();
+
+        let expected = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg);
+        let mut rng = SmallRng::seed_from_u64(seed + 100);
+        let with_rng = ranked_moves_from_partial_with_rng(&state, PlayStyle::Neutral, &cfg, &mut rng);
+        let default = ranked_moves_from_partial(&state, PlayStyle::Neutral, &cfg);
+
+        assert_eq!(expected[0].mv, with_rng[0].mv);
+        assert_eq!(expected[0].mv, default[0].mv);
+        assert_eq!(expected[0].heuristic_score, with_rng[0].heuristic_score);
+    }
+}
+
+fn sorted_visible(state: &PartialState) -> Vec<u8> {
+    let mut visible: Vec<u8> = state
+        .columns
+        .iter()
+        .flat_map(|c| c.visible.iter().map(|c| c.mask_index()))
+        .collect();
+    visible.sort_unstable();
+    visible
+}
+
+#[test]
+fn test_apply_move() {
+    let mut rng = SmallRng::seed_from_u64(4);
+    let mut n_deck_moves = 0;
+    for seed in 0..20 {
+        let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+        let mut engine: SolitaireEngine<FullPruner> = game.into();
+        for _ in 0..100 {
+            let Some(&m) = engine.list_moves().choose(&mut rng) else {
+                break;
+            };
+            let mut state = PartialState::from_known(engine.state());
+            state.apply_move(m).unwrap();
+            assert!(engine.do_move(m));
+            let expected = PartialState::from_known(engine.state());
+
+            for (col, exp) in state.columns.iter().zip(&expected.columns) {
+                assert_eq!(col.hidden, exp.hidden);
+            }
+            // the engine doesn't keep track of which of the two possible
+            // columns a card went to, only compare the visible cards
+            assert_eq!(sorted_visible(&state), sorted_visible(&expected));
+            for col in &state.columns {
+                assert!(col.visible.windows(2).all(|w| w[1].go_after(Some(w[0]))));
+            }
+            assert_eq!(state.deck, expected.deck);
+            assert_eq!(state.foundations, expected.foundations);
+            n_deck_moves += usize::from(matches!(m, Move::DeckPile(_) | Move::DeckStack(_)));
+        }
+    }
+    assert!(n_deck_moves > 0);
+
+    // an unknown card stays hidden when its column gets emptied
+    let ace = Card::new(0, 0);
+    let mut state = PartialStateBuilder::new()
+        .column(&[None], &[ace])
+        .column(&[], &[])
+        .column(&[], &[])
+        .column(&[], &[])
+        .column(&[], &[])
+        .column(&[], &[])
+        .column(&[], &[])
+        .build()
+        .unwrap();
+    let before = state.clone();
+    assert!(state.apply_move(Move::DeckStack(ace)).is_err());
+    assert_eq!(state, before);
+    state.apply_move(Move::PileStack(ace)).unwrap();
+    assert_eq!(state.columns[0].hidden, vec![None]);
+    assert!(state.columns[0].visible.is_empty());
+}
+
+#[test]
+fn test_zero_draw_step() {
+    let build = |draw_step| {
+        (0..7)
+            .fold(PartialStateBuilder::new(), |b, _| b.column(&[], &[]))
+            .draw_step(draw_step)
+            .build()
+    };
+    assert_eq!(build(0), Err(BuildError::ZeroDrawStep));
+
+    let mut state = build(3).unwrap();
+    let filled = state.fill_unknowns_randomly(&mut SmallRng::seed_from_u64(0)).unwrap();
+    assert_eq!(filled.get_deck().draw_step().get(), 3);
+
+    // set by hand, the fill reports it
+    state.draw_step = 0;
+    let res = state.fill_unknowns_randomly(&mut SmallRng::seed_from_u64(0));
+    assert_eq!(res.err(), Some(FillError::ZeroDrawStep));
+}
+
+#[test]
+fn test_sample_move() {
+    let game = single_blocking_move_state();
+    let state = PartialState::from_known(&game);
+    let engine: SolitaireEngine<FullPruner> = game.into();
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
+    assert!(moves.len() > 2);
+    let mut rng = SmallRng::seed_from_u64(0);
+    assert_eq!(sample_move(&[], 1.0, &mut rng), None);
+
+    let samples = |temperature, rng: &mut SmallRng| -> Vec<Move> {
+        (0..200).map(|_| sample_move(&moves, temperature, rng).unwrap()).collect()
+    };
+    assert!(samples(0.0, &mut rng).iter().all(|&m| m == moves[0].mv));
+    let hot = samples(1000.0, &mut rng);
+    // every move but the dead end
+    assert!(moves.iter().all(|m| hot.contains(&m.mv) != m.will_block));
+
+    // the dead end scoring the highest is still never picked
+    let cfg = HeuristicConfig { deadlock_penalty: 100, ..HeuristicConfig::default() };
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg);
+    let blocking = moves.last().unwrap();
+    assert!(blocking.will_block);
+    assert!(moves.iter().all(|m| m.heuristic_score <= blocking.heuristic_score));
+    let samples = |temperature, rng: &mut SmallRng| -> Vec<Move> {
+        (0..200).map(|_| sample_move(&moves, temperature, rng).unwrap()).collect()
+    };
+    assert!(samples(0.0, &mut rng).iter().all(|&m| m == moves[0].mv));
+    for temperature in [1.0, 1000.0] {
+        assert!(!samples(temperature, &mut rng).contains(&blocking.mv));
+    }
+}
+
+#[test]
+fn test_score_move() {
+    let game = StandardSolitaire::new(&default_shuffle(3), NonZeroU8::new(1).unwrap());
+    let state: PartialState = (&game).into();
+    let cfg = HeuristicConfig::default();
+    let moves = ranked_moves_from_partial(&state, PlayStyle::Aggressive, &cfg);
+    assert!(!moves.is_empty());
+    for m in &moves {
+        let score = score_move_from_partial(&state, m.mv, PlayStyle::Aggressive, &cfg).unwrap();
+        assert_eq!(score.mv, m.mv);
+        assert_eq!(score.heuristic_score, m.heuristic_score);
+        assert_eq!(score.will_block, m.will_block);
+        assert_eq!(score.revealed_cards, m.revealed_cards);
+    }
+    let illegal = Move::PileStack(Card::new(12, 0));
+    assert!(score_move_from_partial(&state, illegal, PlayStyle::Aggressive, &cfg).is_none());
+}
+
+#[test]
+fn test_sort_ranked_moves() {
+    // the first deal with a choice of moves
+    let mut moves = (0..)
+        .map(|seed| {
+            let game = StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(1).unwrap());
+            let state: PartialState = (&game).into();
+            ranked_moves_from_partial(&state, PlayStyle::Neutral, &HeuristicConfig::default())
+        })
+        .find(|moves| moves.len() > 2)
+        .unwrap();
+    assert!(moves.windows(2).all(|w| w[0].heuristic_score >= w[1].heuristic_score));
+
+    for (i, m) in moves.iter_mut().enumerate() {
+        m.win_rate = [0.25, 0.75, 0.5][i % 3];
+        m.simulation_score = i as i32;
+    }
+    let by_heuristic = moves.clone();
+
+    sort_ranked_moves(&mut moves, RankKey::WinRate);
+    assert!(moves.windows(2).all(|w| w[0].win_rate >= w[1].win_rate));
+    assert_eq!(moves[0].mv, by_heuristic[1].mv);
+
+    sort_ranked_moves(&mut moves, RankKey::Simulation);
+    assert!(moves.windows(2).all(|w| w[0].simulation_score > w[1].simulation_score));
+
+    // the blend goes from one key to the other
+    sort_ranked_moves(&mut moves, RankKey::Blended(0.0));
+    assert!(moves.windows(2).all(|w| w[0].simulation_score > w[1].simulation_score));
+    sort_ranked_moves(&mut moves, RankKey::Blended(1.0));
+    let heuristic: Vec<i32> = moves.iter().map(|m| m.heuristic_score).collect();
+    let expected: Vec<i32> = by_heuristic.iter().map(|m| m.heuristic_score).collect();
+    assert_eq!(heuristic, expected);
+}
+
+// playing the king of hearts from the deck to the only empty column leaves
+// no follow-up move, while moving one of the buried kings there turns over a
+// card that can be played
+fn single_blocking_move_state() -> Solitaire {
+    let c = Card::new;
+    common::known_game(
+        [
+            (&[], &[]),
+            (&[c(11, 2)], &[c(12, 3)]),
+            (&[c(11, 3)], &[c(12, 1)]),
+            (&[c(9, 0)], &[c(12, 2)]),
+            (&[c(9, 1), c(11, 0)], &[c(10, 0)]),
+            (&[c(9, 2), c(11, 1)], &[c(10, 1)]),
+            (&[c(9, 3), c(10, 3)], &[c(10, 2)]),
+        ],
+        &[c(12, 0)],
+    )
+}
+
+#[test]
+fn test_will_block() {
+    let game = single_blocking_move_state();
+    let engine: SolitaireEngine<FullPruner> = game.clone().into();
+    let state = PartialState::from_known(&game);
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
+    assert!(moves.len() > 1);
+    let blocking: Vec<Move> = moves.iter().filter(|m| m.will_block).map(|m| m.mv).collect();
+    assert_eq!(blocking, [Move::DeckPile(Card::new(12, 0))]);
+
+    // the winning move empties the move list without blocking
+    let c = Card::new;
+    let game = common::known_game(
+        [
+            (&[], &[c(12, 3)]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+            (&[], &[]),
+        ],
+        &[],
+    );
+    let state = PartialState::from_known(&game);
+    let engine: SolitaireEngine<FullPruner> = game.into();
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &HeuristicConfig::default());
+    assert_eq!(moves.len(), 1);
+    assert!(!moves[0].will_block);
+}
+
+#[test]
+fn test_avoid_blocking() {
+    let game = single_blocking_move_state();
+    let engine: SolitaireEngine<FullPruner> = game.clone().into();
+    let state = PartialState::from_known(&game);
+    // rewarding the dead end puts it on top of the heuristic order
+    let raw = HeuristicConfig { deadlock_penalty: 100, avoid_blocking: false, ..HeuristicConfig::default() };
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &raw);
+    assert!(moves[0].will_block);
+
+    let safe = HeuristicConfig { avoid_blocking: true, ..raw };
+    let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &safe);
+    let last = moves.last().unwrap();
+    assert!(last.will_block);
+    assert!(moves[..moves.len() - 1].iter().all(|m| !m.will_block));
+    assert!(moves[0].heuristic_score < last.heuristic_score);
+    assert!(moves.windows(2).take(moves.len() - 2).all(|w| w[0].heuristic_score >= w[1].heuristic_score));
+}
+
+#[test]
+fn test_remaining_by_suit_and_rank() {
+    let game = Solitaire::new(&default_shuffle(6), NonZeroU8::new(3).unwrap());
+    let mut engine: SolitaireEngine<FullPruner> = game.into();
+    let mut rng = SmallRng::seed_from_u64(1);
+    for _ in 0..30 {
+        let Some(&m) = engine.list_moves_dom().choose(&mut rng) else {
+            break;
+        };
+        engine.do_move(m);
+    }
+    let state = PartialState::from_blind(engine.state());
+    let mut remaining = analyze_state(&state).remaining_cards;
+    remaining.sort_by_key(|c| c.mask_index());
+    assert!(!remaining.is_empty());
+
+    let by_suit = state.remaining_by_suit();
+    let by_rank = state.remaining_by_rank();
+    for (suit, cards) in by_suit.iter().enumerate() {
+        assert!(cards.iter().all(|c| usize::from(c.suit()) == suit));
+    }
+    for (rank, cards) in by_rank.iter().enumerate() {
+        assert!(cards.iter().all(|c| usize::from(c.rank()) == rank));
+    }
+    for groups in [by_suit.concat(), by_rank.concat()] {
+        let mut cards = groups.clone();
+        cards.sort_by_key(|c| c.mask_index());
+        cards.dedup();
+        // no card in two groups
+        assert_eq!(cards.len(), groups.len());
+        assert_eq!(cards, remaining);
+    }
+}
+
+#[test]
+fn test_abstract_key() {
+    let game = Solitaire::new(&default_shuffle(3), NonZeroU8::new(3).unwrap());
+    let state = PartialState::from_blind(&game);
+    let mut rng = SmallRng::seed_from_u64(0);
+    let dets: Vec<Solitaire> = (0..2).map(|_| (&state.fill_unknowns_randomly(&mut rng).unwrap()).into()).collect();
+    assert_ne!(dets[0].get_hidden().to_piles(), dets[1].get_hidden().to_piles());
+
+    // the cards turned over differ but the moves are the same
+    let reveals = |g: &Solitaire| {
+        let engine: SolitaireEngine<FullPruner> = g.clone().into();
+        let moves = engine.list_moves();
+        moves.iter().filter(|m| matches!(m, Move::Reveal(_))).map(|m| m.abstract_key(g)).collect::<Vec<_>>()
+    };
+    assert!(!reveals(&dets[0]).is_empty());
+    assert_eq!(reveals(&dets[0]), reveals(&dets[1]));
+    let col = |g: &Solitaire, i: usize| g.compute_visible_piles()[i][0];
+    assert_eq!(Move::Reveal(col(&dets[0], 6)).abstract_key(&dets[0]), AbstractMove::Reveal(Some(6)));
+
+    // an unknown deck card is known by its position
+    let pos = state.deck.iter().position(Option::is_none).unwrap();
+    let cards: Vec<Card> = dets.iter().map(|g| g.get_deck().iter().nth(pos).unwrap()).collect();
+    assert_ne!(cards[0], cards[1]);
+    let keys: Vec<AbstractMove> = dets.iter().zip(&cards).map(|(g, &c)| Move::DeckPile(c).abstract_key(g)).collect();
+    assert_eq!(keys[0], keys[1]);
+    assert_eq!(Move::StackPile(cards[0]).abstract_key(&dets[0]), AbstractMove::StackPile(cards[0].suit()));
+
+    // a card out of the deck doesn't take the key of a deck position
+    let seventh = dets[0].get_deck().iter().nth(7).unwrap();
+    assert_eq!(Move::DeckPile(seventh).abstract_key(&dets[0]), AbstractMove::DeckPile(Some(7)));
+    let top = col(&dets[0], 0);
+    assert_eq!(Move::DeckPile(top).abstract_key(&dets[0]), AbstractMove::DeckPile(None));
+    assert_eq!(Move::Reveal(seventh).abstract_key(&dets[0]), AbstractMove::Reveal(None));
+}
+
+#[test]
+fn test_expected_moves_to_win() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let known = |seed| {
+        PartialState::from(&StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap()))
+    };
+    // every completion of a known deal is the deal itself
+    let mut game = Solitaire::new(&default_shuffle(1), NonZeroU8::new(3).unwrap());
+    let len = lonelybot::solver::solve(&mut game).1.unwrap().len();
+    assert_eq!(known(1).expected_moves_to_win(3, &mut rng), Some(len as f64));
+    assert_eq!(known(0).expected_moves_to_win(3, &mut rng), None);
+    assert_eq!(known(1).expected_moves_to_win(0, &mut rng), None);
+
+    // a few unknown cards left
+    let hidden = game.get_hidden().to_piles();
+    let unknown: Vec<Card> = hidden.iter().flatten().take(3).copied().collect();
+    let state = PartialState::blind_except(&game, &Card::all().filter(|c| !unknown.contains(c)).collect::<Vec<_>>());
+    assert_eq!(state.unknown_count(), 3);
+    let expected = state.expected_moves_to_win(4, &mut rng).unwrap();
+    assert!(expected > 0.0);
+}
+
+#[test]
+fn test_deadlock_risk_calibrated() {
+    // fully known deals, every completion is the deal itself
+    let known = |seed| {
+        PartialState::from(&StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap()))
+    };
+    let mut rng = SmallRng::seed_from_u64(0);
+    // seed 0 can't be won while seed 1 can
+    assert_eq!(deadlock_risk_calibrated(&known(0), 2, usize::MAX, &mut rng), 1.0);
+    assert_eq!(deadlock_risk_calibrated(&known(1), 2, usize::MAX, &mut rng), 0.0);
+    // an unfinished search counts as a loss
+    assert_eq!(deadlock_risk_calibrated(&known(1), 2, 10, &mut rng), 1.0);
+    assert_eq!(deadlock_risk_calibrated(&known(1), 0, usize::MAX, &mut rng), 0.0);
+
+    // with unknown cards the completions can differ
+    let blind = PartialState::from_blind(&Solitaire::new(&default_shuffle(1), NonZeroU8::new(3).unwrap()));
+    let risk = deadlock_risk_calibrated(&blind, 4, 100_000, &mut rng);
+    assert!((0.0..=1.0).contains(&risk));
+}
+
+#[test]
+fn test_bytes_round_trip() {
+    // hidden cards, a deck and cards on the foundations
+    let game = single_blocking_move_state();
+    let state = PartialState::from_blind(&game);
+    assert!(state.unknown_count() > 0);
+
+    let bytes = state.to_bytes();
+    assert_eq!(PartialState::from_bytes(&bytes), Ok(state.clone()));
+    // one byte per card, a length per column and for the deck, the draw step,
+    // the redeal limit, a height per foundation and the deck offset
+    assert_eq!(bytes.len(), 52 - usize::from(game.get_stack().len()) + 2 * 7 + 4 + 4);
+    assert!(bytes.contains(&UNKNOWN_BYTE));
+
+    assert_eq!(PartialState::from_bytes(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated));
+    assert_eq!(PartialState::from_bytes(&[]), Err(DecodeError::Truncated));
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert_eq!(PartialState::from_bytes(&longer), Err(DecodeError::TrailingBytes));
+    let mut zero = bytes.clone();
+    zero[0] = 0;
+    assert_eq!(PartialState::from_bytes(&zero), Err(DecodeError::ZeroDrawStep));
+    // the top card of the deck
+    assert!(!state.deck.is_empty());
+    let mut invalid = bytes.clone();
+    invalid[bytes.len() - 2] = 52;
+    assert_eq!(PartialState::from_bytes(&invalid), Err(DecodeError::InvalidCard(52)));
+    let mut drawn = bytes;
+    *drawn.last_mut().unwrap() = 25;
+    assert_eq!(PartialState::from_bytes(&drawn), Err(DecodeError::InvalidDeckOffset));
+
+    let mut tall = state.to_bytes();
+    tall[2] = 14;
+    assert_eq!(PartialState::from_bytes(&tall), Err(DecodeError::InvalidFoundation));
+
+    let limited = PartialState { redeal_limit: Some(2), ..state };
+    assert_eq!(PartialState::from_bytes(&limited.to_bytes()), Ok(limited));
+}
+
+#[test]
+fn test_foundation() {
+    // a deal with the first hearts in the deck, played to the foundation
+    let (std, height) = (0..)
+        .map(|seed| StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap()))
+        .find_map(|std| {
+            let height = (0..5).take_while(|&rank| std.find_deck_card(Card::new(rank, 0)).is_some()).count() as u8;
+            (height >= 2).then_some((std, height))
+        })
+        .unwrap();
+    let mut state = PartialState::from(&std);
+    state.deck.retain(|c| c.is_none_or(|c| c.suit() != 0 || c.rank() >= height));
+    let mut foundations = Foundation::default();
+    for rank in 0..height {
+        assert!(foundations.push(Card::new(rank, 0)));
+    }
+    state.foundations = foundations;
+    assert_eq!(state.foundation_cards().count(), usize::from(height));
+    assert_eq!(PartialState::from_bytes(&state.to_bytes()), Ok(state.clone()));
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let filled = state.fill_unknowns_randomly(&mut rng).unwrap();
+    assert_eq!(filled.get_stack().get(0), height);
+    assert_eq!(filled.get_deck().len(), 24 - height);
+    assert!(filled.validate().is_ok());
+    assert!(state.compatible_with(&(&filled).into()));
+
+    // the hearts on the foundation can't be played again
+    let mut played = state.clone();
+    assert!(played.apply_move(Move::DeckStack(Card::new(0, 0))).is_err());
+    assert!(played.apply_move(Move::StackPile(Card::new(height, 0))).is_err());
+}
+
+#[test]
+fn test_foundation_cards_used_once() {
+    let game = Solitaire::new(&default_shuffle(8), NonZeroU8::new(3).unwrap());
+    let mut engine: SolitaireEngine<FullPruner> = game.into();
+    let mut rng = SmallRng::seed_from_u64(2);
+    while engine.state().get_stack().len() < 3 {
+        let moves = engine.list_moves_dom();
+        // prefer the moves to the foundation
+        let m = moves
+            .iter()
+            .find(|m| matches!(m, Move::DeckStack(_) | Move::PileStack(_)))
+            .or_else(|| moves.choose(&mut rng))
+            .copied()
+            .unwrap();
+        engine.do_move(m);
+    }
+    let state = PartialState::from_blind(engine.state());
+    assert_eq!(state.foundations, (*engine.state().get_stack()).into());
+    let on_foundation: Vec<Card> = state.foundation_cards().collect();
+    assert_eq!(on_foundation.len(), usize::from(state.foundations.len()));
+
+    let analysis = analyze_state(&state);
+    assert!(analysis.remaining_cards.iter().all(|c| !on_foundation.contains(c)));
+    assert_eq!(analysis.remaining_cards.len(), state.unknown_count());
+    for col in state.column_probabilities() {
+        assert!(col.iter().all(|(c, _)| !on_foundation.contains(c)));
+    }
+
+    let probs = state.column_probabilities();
+    for _ in 0..5 {
+        for filled in [state.fill_unknowns_randomly(&mut rng).unwrap(), state.fill_unknowns_weighted(&probs, &mut rng).unwrap()] {
+            let mut seen: Vec<Card> = filled.get_deck().iter().collect();
+            for (hidden, pile) in filled.get_hidden().iter().zip(filled.get_piles()) {
+                seen.extend(hidden.iter().chain(pile.iter()).copied());
+            }
+            assert!(seen.iter().all(|c| !on_foundation.contains(c)));
+            seen.sort_by_key(|c| c.mask_index());
+            seen.dedup();
+            assert_eq!(seen.len() + usize::from(filled.get_stack().len()), 52);
+            assert_eq!(Foundation::from(*filled.get_stack()), state.foundations);
+        }
+    }
+}
+
+#[test]
+fn test_canonical() {
+    let game = Solitaire::new(&default_shuffle(8), NonZeroU8::new(3).unwrap());
+    let state = PartialState::from_blind(&game);
+
+    let mut permuted = state.clone();
+    permuted.columns.reverse();
+    permuted.columns.swap(0, 3);
+    assert_ne!(permuted, state);
+    assert_eq!(permuted.canonical(), state.canonical());
+    assert_eq!(state.canonical().canonical(), state.canonical());
+    assert_eq!(state.canonical().to_bytes().len(), state.to_bytes().len());
+
+    // the known hidden cards are part of the canonical form
+    let mut known = state.clone();
+    known.columns[6].hidden[0] = Some(Card::new(0, 0));
+    assert_ne!(known.canonical(), state.canonical());
+    let mut permuted = known.clone();
+    permuted.columns.swap(5, 6);
+    assert_eq!(permuted.canonical(), known.canonical());
+}
+
+#[test]
+fn test_eq_unordered() {
+    let game = Solitaire::new(&default_shuffle(8), NonZeroU8::new(3).unwrap());
+    let state = PartialState::from_known(&game);
+    let mut permuted = state.clone();
+    permuted.columns.rotate_left(2);
+    assert_ne!(permuted, state);
+    assert!(permuted.eq_unordered(&state) && state.eq_unordered(&permuted));
+
+    // the deck still has to match
+    permuted.deck.swap(0, 1);
+    assert!(!permuted.eq_unordered(&state));
+}
+
+#[test]
+fn test_plan_to_draw() {
+    for draw_step in 1..=3 {
+        let game = StandardSolitaire::new(&default_shuffle(9), NonZeroU8::new(draw_step).unwrap());
+        let state = PartialState::from(&game);
+        let mut n_planned = 0;
+        for c in game.get_deck().iter() {
+            // the same count as drawing on the full game
+            assert_eq!(state.plan_to_draw(c), game.find_deck_card(c));
+            n_planned += usize::from(state.plan_to_draw(c).is_some());
+        }
+        assert_eq!(n_planned, 24usize.div_ceil(usize::from(draw_step)));
+    }
+
+    let game = StandardSolitaire::new(&default_shuffle(9), NonZeroU8::new(3).unwrap());
+    let mut state = PartialState::from(&game);
+    let third = state.deck[2].unwrap();
+    assert_eq!(state.plan_to_draw(third), Some(1));
+    assert_eq!(state.plan_to_draw(state.deck[23].unwrap()), Some(8));
+    // a card of the columns, and an unknown one
+    assert_eq!(state.plan_to_draw(game.get_piles()[0][0]), None);
+    state.deck[2] = None;
+    assert_eq!(state.plan_to_draw(third), None);
+    // mid-pass the draws count from the current card, going around the deck
+    let mut drawn = game.clone();
+    drawn.draw_stock().unwrap();
+    drawn.draw_stock().unwrap();
+    let mut state = PartialState::from(&drawn);
+    for c in drawn.get_deck().iter() {
+        assert_eq!(state.plan_to_draw(c), drawn.find_deck_card(c));
+    }
+    assert_eq!(state.plan_to_draw(state.deck[5].unwrap()), Some(0));
+    assert_eq!(state.plan_to_draw(state.deck[8].unwrap()), Some(1));
+    assert_eq!(state.plan_to_draw(third), Some(8));
+    state.redeal_limit = Some(0);
+    assert_eq!(state.plan_to_draw(third), None);
+    assert_eq!(state.plan_to_draw(state.deck[23].unwrap()), Some(6));
+    // taking the current card shifts the groups of the stock
+    let next = state.deck[9].unwrap();
+    state.deck.remove(5);
+    state.deck_offset = 5;
+    assert_eq!(state.plan_to_draw(next), None);
+    assert_eq!(state.plan_to_draw(state.deck[7].unwrap()), Some(1));
+}
+
+#[test]
+fn test_redeal_limit() {
+    let game = StandardSolitaire::new(&default_shuffle(4), NonZeroU8::new(3).unwrap());
+    let mut state = PartialState::from(&game);
+    assert!(state.drawable_deck_cards().iter().all(|&d| d));
+
+    // a single pass only shows every third card and the last one
+    state.redeal_limit = Some(0);
+    let drawable = state.drawable_deck_cards();
+    assert_eq!(drawable.len(), 24);
+    let expected: Vec<bool> = (0..24).map(|i| i % 3 == 2).collect();
+    assert_eq!(drawable, expected);
+    let locked = state.deck[0].unwrap();
+    assert!(state.is_deck_card_locked(locked));
+    assert!(!state.is_deck_card_locked(state.deck[2].unwrap()));
+
+    state.redeal_limit = Some(1);
+    assert!(state.drawable_deck_cards().iter().all(|&d| d));
+
+    // two draws in, the cards drawn are locked under the current one
+    let mut drawn = game.clone();
+    drawn.draw_stock().unwrap();
+    drawn.draw_stock().unwrap();
+    let mut state = PartialState { redeal_limit: Some(0), ..PartialState::from(&drawn) };
+    assert_eq!(state.deck_offset, 6);
+    let expected: Vec<bool> = (0..24).map(|i| i >= 5 && i % 3 == 2).collect();
+    assert_eq!(state.drawable_deck_cards(), expected);
+    assert!(state.is_deck_card_locked(state.deck[2].unwrap()));
+    assert!(!state.is_deck_card_locked(state.deck[5].unwrap()));
+    // taking the current card shifts the groups of the stock
+    state.deck.remove(5);
+    state.deck_offset = 5;
+    let expected: Vec<bool> = (0..23).map(|i| i == 4 || (i >= 5 && i % 3 == 1)).collect();
+    assert_eq!(state.drawable_deck_cards(), expected);
+
+    let game = StandardSolitaire::new(&default_shuffle(4), NonZeroU8::new(1).unwrap());
+    let state = PartialState { redeal_limit: Some(0), ..PartialState::from(&game) };
+    assert!(state.drawable_deck_cards().iter().all(|&d| d));
+}
+
+#[test]
+fn test_compatible_with() {
+    let std = StandardSolitaire::new(&default_shuffle(5), NonZeroU8::new(3).unwrap());
+    let sol: Solitaire = (&std).into();
+    let known = PartialState::from(&std);
+    assert!(known.compatible_with(&sol));
+
+    // a blind state replays into a compatible completion
+    let blind = PartialState::from_blind(&sol);
+    assert!(blind.compatible_with(&sol));
+    let mut rng = SmallRng::seed_from_u64(1);
+    for _ in 0..10 {
+        let filled: Solitaire = (&blind.fill_unknowns_randomly(&mut rng).unwrap()).into();
+        assert!(blind.compatible_with(&filled));
+    }
+
+    // some moves later the blind state still matches
+    let mut engine: SolitaireEngine<FullPruner> = sol.clone().into();
+    for _ in 0..5 {
+        let Some(&m) = engine.list_moves_dom().first() else {
+            break;
+        };
+        engine.do_move(m);
+    }
+    assert!(PartialState::from_blind(engine.state()).compatible_with(engine.state()));
+}
+
+#[test]
+fn test_incompatible_with() {
+    let std = StandardSolitaire::new(&default_shuffle(5), NonZeroU8::new(3).unwrap());
+    let sol: Solitaire = (&std).into();
+    let other: Solitaire =
+        (&StandardSolitaire::new(&default_shuffle(6), NonZeroU8::new(3).unwrap())).into();
+
+    let known = PartialState::from(&std);
+    assert!(!known.compatible_with(&other));
+    assert!(!PartialState::from_blind(&sol).compatible_with(&other));
+
+    let mut swapped = known.clone();
+    swapped.deck.swap(0, 1);
+    assert!(!swapped.compatible_with(&sol));
+
+    let mut draw_one = known;
+    draw_one.draw_step = 1;
+    assert!(!draw_one.compatible_with(&sol));
+}
+
+#[test]
+fn test_merge_observation() {
+    let game = Solitaire::new(&default_shuffle(4), NonZeroU8::new(1).unwrap());
+    let mut state = PartialState::from_blind(&game);
+    let hidden = game.get_hidden().to_piles();
+    let unknown = state.unknown_count();
+
+    let card = hidden[6][2];
+    state.merge_observation(6, 2, card).unwrap();
+    assert_eq!(state.unknown_count(), unknown - 1);
+    assert_eq!(state.columns[6].hidden[2], Some(card));
+    assert!(state.compatible_with(&game));
+
+    // observing the same card again is fine
+    assert_eq!(state.merge_observation(6, 2, card), Ok(()));
+    assert_eq!(state.merge_observation(6, 2, hidden[6][3]), Err(ObsError::SlotKnown(card)));
+    assert_eq!(state.merge_observation(5, 0, card), Err(ObsError::CardKnown));
+    let visible = state.columns[0].visible[0];
+    assert_eq!(state.merge_observation(5, 0, visible), Err(ObsError::CardKnown));
+    assert_eq!(state.merge_observation(0, 0, hidden[6][3]), Err(ObsError::NoSuchSlot));
+    assert_eq!(state.merge_observation(7, 0, hidden[6][3]), Err(ObsError::NoSuchSlot));
+    assert_eq!(state.unknown_count(), unknown - 1);
+}
+
+#[test]
+fn test_analysis_session() {
+    let game = Solitaire::new(&default_shuffle(8), NonZeroU8::new(1).unwrap());
+    let first = PartialState::from_blind(&game);
+
+    let mut session = AnalysisSession::new();
+    let res = session.analyze(&first);
+    assert_eq!(session.hits(), 0);
+    assert_eq!(res, analyze_state(&first));
+    assert_eq!(session.analyze(&first), res);
+    assert_eq!(session.hits(), 1);
+
+    // an older position is still cached after moving on
+    let mut engine: SolitaireEngine<FullPruner> = game.into();
+    engine.do_move(engine.list_moves_dom()[0]);
+    let second = PartialState::from_blind(engine.state());
+    assert_eq!(session.analyze(&second), analyze_state(&second));
+    assert_eq!(session.hits(), 1);
+    assert_eq!(session.analyze(&first), res);
+    assert_eq!(session.hits(), 2);
+
+    // and evicted once enough other positions were analyzed
+    let mut probe = first.clone();
+    for i in 0..SESSION_CACHE_SIZE {
+        probe.draw_step = 2 + i as u8;
+        let _ = session.analyze(&probe);
+    }
+    let hits = session.hits();
+    let _ = session.analyze(&first);
+    assert_eq!(session.hits(), hits);
+}
+
+#[test]
+fn test_from_known_and_blind_deck() {
+    let game = Solitaire::new(&default_shuffle(9), NonZeroU8::new(3).unwrap());
+    let known = PartialState::from_known(&game);
+    let blind = PartialState::from_blind(&game);
+
+    assert_eq!(known.unknown_count(), 0);
+    assert!(!known.deck.contains(&None));
+    assert!(known.compatible_with(&game));
+    assert!(blind.compatible_with(&game));
+
+    // the blind deck only keeps the cards reachable in the current cycle
+    let drawable = game
+        .get_deck()
+        .iter_all()
+        .filter(|(_, _, d)| !matches!(d, Drawable::None))
+        .count();
+    assert!(drawable < known.deck.len());
+    assert_eq!(blind.deck.iter().flatten().count(), drawable);
+    for (b, k) in blind.deck.iter().zip(&known.deck) {
+        assert!(b.is_none() || b == k);
+    }
+    assert_eq!(known.columns.map(|c| c.visible), blind.columns.map(|c| c.visible));
+}
+
+#[test]
+fn test_blind_except() {
+    let game = Solitaire::new(&default_shuffle(9), NonZeroU8::new(3).unwrap());
+    let known = PartialState::from_known(&game);
+    let blind = PartialState::from_blind(&game);
+    assert_eq!(PartialState::blind_except(&game, &[]), blind);
+    assert_eq!(PartialState::blind_except(&game, &Card::all().collect::<Vec<_>>()), known);
+
+    // three hidden cards and a visible one, already known
+    let hidden = game.get_hidden().to_piles();
+    let visible = game.compute_visible_piles();
+    let revealed = [hidden[6][0], hidden[6][3], hidden[2][1], visible[0][0]];
+    let state = PartialState::blind_except(&game, &revealed);
+    assert_eq!(state.unknown_count(), blind.unknown_count() - 3);
+    assert!(state.columns[6].hidden.contains(&Some(hidden[6][3])));
+    assert!(state.compatible_with(&game));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_ranked_moves_timed() {
+    use lonelybot::analysis::{ranked_moves_timed, RankTimings};
+
+    let cfg = HeuristicConfig::default();
+    let mut timings = RankTimings::default();
+    let mut n_moves = 0;
+    for seed in 0..5 {
+        let game = StandardSolitaire::new(&default_shuffle(seed), NonZeroU8::new(1).unwrap());
+        let state: PartialState = (&game).into();
+        let engine: SolitaireEngine<FullPruner> = Solitaire::from(&game).into();
+
+        let timed = ranked_moves_timed(&engine, &state, PlayStyle::Neutral, &cfg, &mut timings);
+        let plain = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg);
+        assert_eq!(timed.len(), plain.len());
+        n_moves += timed.len();
+    }
+    assert_eq!(timings.n_moves, n_moves);
+    assert!(timings.total() > std::time::Duration::ZERO);
+    assert!(timings.to_string().starts_with(&format!("{n_moves} moves in ")));
+}
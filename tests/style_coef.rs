@@ -1,6 +1,11 @@
-use lonelybot::analysis::{ranked_moves, HeuristicConfig, PlayStyle};
+mod common;
+
+use lonelybot::analysis::{ranked_moves, run_move_score, HeuristicConfig, PlayStyle};
+use lonelybot::card::Card;
 use lonelybot::engine::SolitaireEngine;
-use lonelybot::pruning::FullPruner;
+use lonelybot::moves::Move;
+use lonelybot::state::Solitaire;
+use lonelybot::pruning::{FullPruner, NoPruner};
 use lonelybot::standard::StandardSolitaire;
 use lonelybot::shuffler::default_shuffle;
 use lonelybot::partial::PartialState;
@@ -14,8 +19,10 @@ fn test_style_coefficient_scales_total_score() {
     let engine: SolitaireEngine<FullPruner> = solitaire.into();
     let state: PartialState = (&game).into();
 
-    let mut cfg1 = HeuristicConfig::default();
-    cfg1.neutral_coef = 1;
+    let cfg1 = HeuristicConfig {
+        neutral_coef: 1,
+        ..Default::default()
+    };
     let moves1 = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg1);
 
     let mut cfg2 = cfg1.clone();
@@ -27,3 +34,230 @@ fn test_style_coefficient_scales_total_score() {
         assert_eq!(b.heuristic_score, a.heuristic_score * 2);
     }
 }
+
+#[test]
+fn test_style_from_str() {
+    assert_eq!("aggressive".parse(), Ok(PlayStyle::Aggressive));
+    assert_eq!("conservative".parse(), Ok(PlayStyle::Conservative));
+    assert_eq!("neutral".parse(), Ok(PlayStyle::Neutral));
+    assert_eq!("Neutral".parse(), Ok(PlayStyle::Neutral));
+    assert!("foo".parse::<PlayStyle>().is_err());
+    assert!("".parse::<PlayStyle>().is_err());
+
+    for style in [PlayStyle::Aggressive, PlayStyle::Conservative, PlayStyle::Neutral] {
+        assert_eq!(style.to_string().parse(), Ok(style));
+    }
+}
+
+#[test]
+fn test_score_breakdown_sums_to_score() {
+    let explain = HeuristicConfig {
+        explain: true,
+        aggressive_coef: 3,
+        neutral_coef: 2,
+        ..Default::default()
+    };
+    let mut n_terms = 0;
+    for seed in 0..10 {
+        let game = Solitaire::new(&default_shuffle(seed), NonZeroU8::new(3).unwrap());
+        let engine: SolitaireEngine<FullPruner> = game.clone().into();
+        let state = PartialState::from_blind(&game);
+        for style in [PlayStyle::Aggressive, PlayStyle::Conservative, PlayStyle::Neutral] {
+            let plain = ranked_moves(&engine, &state, style, &HeuristicConfig { explain: false, ..explain.clone() });
+            let explained = ranked_moves(&engine, &state, style, &explain);
+            assert_eq!(plain.len(), explained.len());
+            for (a, b) in plain.iter().zip(&explained) {
+                assert!(a.score_breakdown.is_empty());
+                assert_eq!((a.mv, a.heuristic_score), (b.mv, b.heuristic_score));
+                let total: i32 = b.score_breakdown.iter().map(|t| t.1).sum();
+                assert_eq!(total, b.heuristic_score);
+                n_terms += b.score_breakdown.len();
+            }
+        }
+    }
+    assert!(n_terms > 0);
+}
+
+// the king of hearts can be played from the deck to the only empty column
+// while the kings of spades and diamonds sit on top of hidden cards
+fn king_staging_state() -> (Solitaire, Move) {
+    let c = Card::new;
+    let game = common::known_game(
+        [
+            (&[], &[]),
+            (&[c(11, 3)], &[c(12, 3)]),
+            (&[c(11, 1)], &[c(12, 1)]),
+            (&[c(11, 0), c(10, 2)], &[c(11, 2)]),
+            (&[], &[c(12, 2)]),
+            (&[c(9, 3)], &[c(10, 3)]),
+            (&[c(9, 1)], &[c(10, 1)]),
+        ],
+        &[c(12, 0)],
+    );
+    (game, Move::DeckPile(c(12, 0)))
+}
+
+#[test]
+fn test_hold_empty_for_king() {
+    let (game, fill) = king_staging_state();
+    let state = PartialState::from_known(&game);
+    let engine: SolitaireEngine<FullPruner> = game.into();
+
+    let score = |hold_empty_for_king, mv| {
+        let cfg = HeuristicConfig {
+            hold_empty_for_king,
+            ..Default::default()
+        };
+        let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg);
+        moves.iter().find(|m| m.mv == mv).unwrap().heuristic_score
+    };
+    assert_eq!(score(-5, fill), score(0, fill) - 5);
+    assert!(score(HeuristicConfig::default().hold_empty_for_king, fill) < score(0, fill));
+    // moving a buried king to the empty column is what the column is held for
+    let buried = Move::Reveal(Card::new(12, 3));
+    assert_eq!(score(-5, buried), score(0, buried));
+}
+
+#[test]
+fn test_deck_dig_penalty() {
+    let game = StandardSolitaire::new(&default_shuffle(12), NonZeroU8::new(1).unwrap());
+    let state: PartialState = (&game).into();
+    let engine: SolitaireEngine<FullPruner> = Solitaire::from(&game).into();
+
+    let score = |deck_dig_penalty| {
+        let cfg = HeuristicConfig {
+            deck_dig_penalty,
+            ..Default::default()
+        };
+        ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg)
+    };
+    let (plain, penalized) = (score(0), score(-2));
+    let mut n_deck = 0;
+    for a in &plain {
+        let b = penalized.iter().find(|b| b.mv == a.mv).unwrap();
+        let draws = a.mv.deck_cost(&game).map_or(0, i32::from);
+        assert_eq!(b.heuristic_score, a.heuristic_score - 2 * draws);
+        n_deck += usize::from(draws > 0);
+    }
+    assert!(n_deck > 0);
+}
+
+#[test]
+fn test_columns_freed_bonus() {
+    let c = Card::new;
+    let queen_up = Move::PileStack(c(11, 0));
+    // the freed columns and the scores without and with the bonus of sending
+    // the queen of hearts up from `column`
+    let score = |column: &[Card]| {
+        let game = common::known_game(
+            [
+                (&[], &[]),
+                (&[], &[]),
+                (&[], &[]),
+                (&[], &[]),
+                (&[], &[]),
+                (&[], column),
+                (&[], &[c(12, 0)]),
+            ],
+            &[],
+        );
+        let state = PartialState::from_known(&game);
+        let engine: SolitaireEngine<FullPruner> = game.into();
+        let [plain, bonus] = [0, 3].map(|empty_column_bonus| {
+            let cfg = HeuristicConfig {
+                empty_column_bonus,
+                ..Default::default()
+            };
+            let moves = ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg);
+            moves.into_iter().find(|m| m.mv == queen_up).unwrap()
+        });
+        (plain.columns_freed, plain.heuristic_score, bonus.heuristic_score)
+    };
+
+    // alone in her column
+    let (freed, plain, bonus) = score(&[c(11, 0)]);
+    assert_eq!(freed, 1);
+    assert_eq!(bonus, plain + 3);
+    // on top of the king of spades
+    let (freed, plain, bonus) = score(&[c(12, 3), c(11, 0)]);
+    assert_eq!(freed, 0);
+    assert_eq!(bonus, plain);
+}
+
+#[test]
+fn test_run_move_bonus() {
+    let c = Card::new;
+    // two runs of a queen and a jack can go onto a king, one from a hidden
+    // card and one from an empty base, the other kings being buried
+    let game = common::known_game(
+        [
+            (&[], &[]),
+            (&[], &[c(12, 0)]),
+            (&[c(11, 0)], &[c(11, 2), c(10, 1)]),
+            (&[], &[c(11, 1), c(10, 3)]),
+            (&[], &[c(12, 3)]),
+            (&[c(12, 1), c(12, 2), c(11, 3)], &[c(10, 0)]),
+            (&[], &[]),
+        ],
+        &[],
+    );
+    let (exposing, relocating) = (Move::Reveal(c(11, 2)), Move::Reveal(c(11, 1)));
+    let legal = SolitaireEngine::<NoPruner>::from(game.clone()).list_moves();
+    assert!(legal.contains(&exposing) && legal.contains(&relocating));
+    let engine: SolitaireEngine<FullPruner> = game.into();
+    let cfg = HeuristicConfig::default();
+
+    // moving the run turns over the card under it
+    assert!(run_move_score(&engine, exposing, &cfg) >= cfg.run_move_bonus * 2);
+    // the run only goes from one column to another
+    assert_eq!(run_move_score(&engine, relocating, &cfg), -cfg.run_move_bonus * 2);
+
+    let off = HeuristicConfig { run_move_bonus: 0, ..cfg };
+    assert_eq!(run_move_score(&engine, exposing, &off), 0);
+    assert_eq!(run_move_score(&engine, relocating, &off), 0);
+}
+
+// moving the king of the second column to an empty column uncovers a nine,
+// which goes to the foundation with the ten of the last column, and nothing
+// moves
+#[test]
+fn test_deadlock_lookahead() {
+    let c = Card::new;
+    let game = common::known_game(
+        [
+            (&[], &[]),
+            (&[c(8, 2)], &[c(12, 3)]),
+            (&[], &[]),
+            (&[c(10, 3), c(10, 2)], &[c(11, 3)]),
+            (&[], &[c(12, 2)]),
+            (&[c(12, 1), c(12, 0), c(10, 0), c(11, 1), c(11, 0)], &[c(11, 2)]),
+            (&[], &[c(9, 2)]),
+        ],
+        &[],
+    );
+    let king = Move::Reveal(c(12, 3));
+    let state = PartialState::from_known(&game);
+    let engine: SolitaireEngine<FullPruner> = game.into();
+    let moves = engine.list_moves_dom();
+    assert!(moves.len() > 1 && moves.contains(&king));
+
+    let penalized = |deadlock_lookahead| -> Vec<Move> {
+        let cfg = HeuristicConfig {
+            deadlock_lookahead,
+            explain: true,
+            ..Default::default()
+        };
+        ranked_moves(&engine, &state, PlayStyle::Neutral, &cfg)
+            .into_iter()
+            .filter(|m| m.score_breakdown.iter().any(|t| t.0 == "deadlock_penalty"))
+            .map(|m| m.mv)
+            .collect()
+    };
+    assert!(penalized(0).is_empty());
+    assert!(penalized(1).is_empty());
+    assert_eq!(penalized(2), [king]);
+    // taking the jack back from the foundation gets stuck five moves later
+    let jack = Move::StackPile(c(10, 1));
+    assert_eq!(penalized(4), [king]);
+    assert_eq!(penalized(5), [king, jack]);
+}